@@ -0,0 +1,434 @@
+use crate::scene::{NodeId, Scene, Transform};
+
+/// Bones a single vertex can be bound to, the size of `Vertex::bone_ids`
+pub const MAX_BONE_INFLUENCE: usize = 4;
+/// Size of the `bones` array of the skinning shader. OpenGL 3.3 only guarantees 1024 vertex
+/// uniform components, 48 matrices leave room for the other uniforms of the shader.
+pub const MAX_BONES: usize = 48;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe<T> {
+    /// In seconds from the start of the clip
+    pub time: f32,
+    pub value: T,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Holds the value of the previous keyframe
+    Step,
+    Linear,
+}
+
+/// Keyframes of one property of a node, sorted by time
+#[derive(Clone, Debug, PartialEq)]
+pub enum Keyframes {
+    Translation(Vec<Keyframe<glm::Vec3>>),
+    Rotation(Vec<Keyframe<glm::Quat>>),
+    Scale(Vec<Keyframe<glm::Vec3>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Channel {
+    pub node: NodeId,
+    pub keyframes: Keyframes,
+    pub interpolation: Interpolation,
+}
+impl Channel {
+    /// Overwrites the animated property of `transform` with its value at `time`
+    pub fn sample_into(&self, time: f32, transform: &mut Transform) {
+        let step = self.interpolation == Interpolation::Step;
+        match &self.keyframes {
+            Keyframes::Translation(keys) => {
+                if let Some(value) = sample(keys, time, step, glm::lerp) {
+                    transform.translation = value;
+                }
+            }
+            Keyframes::Rotation(keys) => {
+                if let Some(value) = sample(keys, time, step, glm::quat_slerp) {
+                    transform.rotation = value;
+                }
+            }
+            Keyframes::Scale(keys) => {
+                if let Some(value) = sample(keys, time, step, glm::lerp) {
+                    transform.scale = value;
+                }
+            }
+        }
+    }
+}
+
+/// Value at `time` of sorted keyframes, clamped to the first and last one
+fn sample<T: Copy>(
+    keys: &[Keyframe<T>],
+    time: f32,
+    step: bool,
+    interpolate: impl Fn(&T, &T, f32) -> T,
+) -> Option<T> {
+    let first = keys.first()?;
+    let next = keys.partition_point(|key| key.time <= time);
+    if next == 0 {
+        return Some(first.value);
+    }
+    let previous = &keys[next - 1];
+    let Some(next) = keys.get(next) else {
+        return Some(previous.value);
+    };
+    if step {
+        return Some(previous.value);
+    }
+    let t = (time - previous.time) / (next.time - previous.time);
+    Some(interpolate(&previous.value, &next.value, t))
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnimationClip {
+    pub name: String,
+    /// In seconds
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+impl AnimationClip {
+    /// Overwrites the animated properties in `pose`, the local transforms of the scene nodes
+    pub fn sample_into(&self, time: f32, pose: &mut [Transform]) {
+        for channel in self.channels.iter() {
+            channel.sample_into(time, &mut pose[channel.node.0]);
+        }
+    }
+}
+
+/// Joints deforming a skinned mesh. Bone ids of the vertices index `joints`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Skeleton {
+    pub joints: Vec<NodeId>,
+    /// Moves a vertex from model space to the space of the joint in bind pose
+    pub inverse_bind_matrices: Vec<glm::Mat4>,
+}
+impl Skeleton {
+    /// Skinning matrices for the current pose of `scene`, in model space
+    pub fn palette(&self, scene: &Scene) -> Vec<glm::Mat4> {
+        self.joints
+            .iter()
+            .zip(self.inverse_bind_matrices.iter())
+            .map(|(joint, inverse_bind)| scene.world_transform(*joint) * inverse_bind)
+            .collect()
+    }
+}
+
+/// A clip played by an `Animator`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationLayer {
+    /// Index of the clip in the list given to the animator
+    pub clip: usize,
+    /// In seconds
+    pub time: f32,
+    pub speed: f32,
+    /// Relative to the other layers, layers with no weight are skipped
+    pub weight: f32,
+    pub looping: bool,
+}
+
+/// Plays clips and blends them into a pose
+#[derive(Clone, Debug, Default)]
+pub struct Animator {
+    pub layers: Vec<AnimationLayer>,
+}
+impl Animator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Starts a looping clip from its beginning, returns the index of its layer
+    pub fn play(&mut self, clip: usize, weight: f32) -> usize {
+        self.layers.push(AnimationLayer {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            weight,
+            looping: true,
+        });
+        self.layers.len() - 1
+    }
+    pub fn stop(&mut self, layer: usize) {
+        self.layers.remove(layer);
+    }
+    pub fn advance(&mut self, delta_time: f32, clips: &[AnimationClip]) {
+        for layer in self.layers.iter_mut() {
+            let duration = clips[layer.clip].duration;
+            layer.time += delta_time * layer.speed;
+            layer.time = if layer.looping && duration > 0.0 {
+                layer.time.rem_euclid(duration)
+            } else {
+                layer.time.clamp(0.0, duration)
+            };
+        }
+    }
+    /// Local transforms of every node, `rest` blended with the clips of every layer by weight.
+    /// Nodes a clip doesn't animate keep their rest transform in that clip.
+    pub fn sample(&self, clips: &[AnimationClip], rest: &[Transform]) -> Vec<Transform> {
+        let layers: Vec<_> = self.layers.iter().filter(|l| l.weight > 0.0).collect();
+        let total_weight: f32 = layers.iter().map(|layer| layer.weight).sum();
+        if layers.is_empty() {
+            return rest.to_vec();
+        }
+
+        let mut pose = vec![
+            Transform {
+                translation: glm::Vec3::zeros(),
+                rotation: glm::quat(0.0, 0.0, 0.0, 0.0),
+                scale: glm::Vec3::zeros(),
+            };
+            rest.len()
+        ];
+        let mut layer_pose = rest.to_vec();
+        for layer in layers {
+            layer_pose.copy_from_slice(rest);
+            clips[layer.clip].sample_into(layer.time, &mut layer_pose);
+            let weight = layer.weight / total_weight;
+            for (blended, sampled) in pose.iter_mut().zip(layer_pose.iter()) {
+                blended.translation += sampled.translation * weight;
+                blended.scale += sampled.scale * weight;
+                // q and -q are the same rotation, the closest one is blended to take the short way
+                let rotation = if blended.rotation.dot(&sampled.rotation) < 0.0 {
+                    -sampled.rotation
+                } else {
+                    sampled.rotation
+                };
+                blended.rotation += rotation * weight;
+            }
+        }
+        for transform in pose.iter_mut() {
+            transform.rotation = transform.rotation.normalize();
+        }
+        pose
+    }
+    /// Samples the layers and moves the nodes of `scene`
+    pub fn apply(&self, clips: &[AnimationClip], rest: &[Transform], scene: &mut Scene) {
+        for (i, transform) in self.sample(clips, rest).into_iter().enumerate() {
+            if *scene.node(NodeId(i)).transform() != transform {
+                scene.set_transform(NodeId(i), transform);
+            }
+        }
+    }
+}
+
+/// Binds a vertex to a bone, replacing its weakest bone once all the slots are used
+pub fn add_bone_influence(
+    bone_ids: &mut [u32; MAX_BONE_INFLUENCE],
+    bone_weights: &mut [f32; MAX_BONE_INFLUENCE],
+    id: u32,
+    weight: f32,
+) {
+    let (weakest, weakest_weight) = bone_weights
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(i, weight)| (i, *weight))
+        .unwrap();
+    if weight > weakest_weight {
+        bone_ids[weakest] = id;
+        bone_weights[weakest] = weight;
+    }
+}
+
+/// Scales the weights of a vertex so they sum to one, influences dropped by
+/// `add_bone_influence` would otherwise shrink the mesh
+pub fn normalize_bone_weights(bone_weights: &mut [f32; MAX_BONE_INFLUENCE]) {
+    let total: f32 = bone_weights.iter().sum();
+    if total > 0.0 {
+        for weight in bone_weights.iter_mut() {
+            *weight /= total;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn approx(a: &glm::Vec3, b: &glm::Vec3) -> bool {
+        (a - b).norm() < 1e-5
+    }
+
+    fn translation_channel(interpolation: Interpolation) -> Channel {
+        Channel {
+            node: NodeId(0),
+            keyframes: Keyframes::Translation(vec![
+                Keyframe {
+                    time: 1.0,
+                    value: glm::vec3(0.0, 0.0, 0.0),
+                },
+                Keyframe {
+                    time: 2.0,
+                    value: glm::vec3(2.0, 0.0, 0.0),
+                },
+            ]),
+            interpolation,
+        }
+    }
+
+    fn sampled_translation(channel: &Channel, time: f32) -> glm::Vec3 {
+        let mut transform = Transform::default();
+        channel.sample_into(time, &mut transform);
+        transform.translation
+    }
+
+    #[test]
+    fn step_holds_the_previous_key() {
+        let channel = translation_channel(Interpolation::Step);
+        let first = glm::vec3(0.0, 0.0, 0.0);
+        let last = glm::vec3(2.0, 0.0, 0.0);
+        assert!(approx(&sampled_translation(&channel, 0.0), &first));
+        assert!(approx(&sampled_translation(&channel, 1.5), &first));
+        assert!(approx(&sampled_translation(&channel, 2.0), &last));
+        assert!(approx(&sampled_translation(&channel, 3.0), &last));
+    }
+
+    #[test]
+    fn linear_interpolates_and_clamps() {
+        let channel = translation_channel(Interpolation::Linear);
+        assert!(approx(
+            &sampled_translation(&channel, 0.0),
+            &glm::Vec3::zeros()
+        ));
+        assert!(approx(
+            &sampled_translation(&channel, 1.5),
+            &glm::vec3(1.0, 0.0, 0.0)
+        ));
+        assert!(approx(
+            &sampled_translation(&channel, 5.0),
+            &glm::vec3(2.0, 0.0, 0.0)
+        ));
+    }
+
+    #[test]
+    fn rotations_are_slerped() {
+        let quarter_turn = glm::quat_angle_axis(90f32.to_radians(), &glm::Vec3::y());
+        let channel = Channel {
+            node: NodeId(0),
+            keyframes: Keyframes::Rotation(vec![
+                Keyframe {
+                    time: 0.0,
+                    value: glm::quat_identity(),
+                },
+                Keyframe {
+                    time: 1.0,
+                    value: quarter_turn,
+                },
+            ]),
+            interpolation: Interpolation::Linear,
+        };
+        let mut transform = Transform::default();
+        channel.sample_into(0.5, &mut transform);
+        let expected = glm::quat_angle_axis(45f32.to_radians(), &glm::Vec3::y());
+        assert!((transform.rotation.coords - expected.coords).norm() < 1e-5);
+        channel.sample_into(-1.0, &mut transform);
+        assert!((transform.rotation.coords - glm::quat_identity().coords).norm() < 1e-5);
+        channel.sample_into(2.0, &mut transform);
+        assert!((transform.rotation.coords - quarter_turn.coords).norm() < 1e-5);
+    }
+
+    #[test]
+    fn layers_are_blended_by_weight() {
+        let clip = |translation: glm::Vec3, angle: f32| AnimationClip {
+            name: String::new(),
+            duration: 1.0,
+            channels: vec![
+                Channel {
+                    node: NodeId(0),
+                    keyframes: Keyframes::Translation(vec![Keyframe {
+                        time: 0.0,
+                        value: translation,
+                    }]),
+                    interpolation: Interpolation::Linear,
+                },
+                Channel {
+                    node: NodeId(0),
+                    keyframes: Keyframes::Rotation(vec![Keyframe {
+                        time: 0.0,
+                        value: glm::quat_angle_axis(angle.to_radians(), &glm::Vec3::z()),
+                    }]),
+                    interpolation: Interpolation::Linear,
+                },
+            ],
+        };
+        let clips = [
+            clip(glm::vec3(2.0, 0.0, 0.0), 0.0),
+            clip(glm::vec3(0.0, 2.0, 0.0), 90.0),
+        ];
+        // The second node isn't animated and keeps its rest transform
+        let rest = [
+            Transform::default(),
+            Transform::from_translation(glm::vec3(0.0, 0.0, 5.0)),
+        ];
+        let mut animator = Animator::new();
+        animator.play(0, 1.0);
+        animator.play(1, 3.0);
+        let pose = animator.sample(&clips, &rest);
+        assert!(approx(&pose[0].translation, &glm::vec3(0.5, 1.5, 0.0)));
+        assert!(approx(&pose[0].scale, &glm::vec3(1.0, 1.0, 1.0)));
+        assert_eq!(pose[1], rest[1]);
+
+        // Equal weights give the rotation halfway
+        animator.layers[1].weight = 1.0;
+        let pose = animator.sample(&clips, &rest);
+        let halfway = glm::quat_angle_axis(45f32.to_radians(), &glm::Vec3::z());
+        assert!((pose[0].rotation.coords - halfway.coords).norm() < 1e-5);
+
+        // Without weight, the rest pose
+        animator
+            .layers
+            .iter_mut()
+            .for_each(|layer| layer.weight = 0.0);
+        assert_eq!(animator.sample(&clips, &rest), rest.to_vec());
+    }
+
+    #[test]
+    fn palette_of_a_two_bone_chain() {
+        let mut scene = Scene::new();
+        let root = scene.add_node(
+            "root",
+            Transform::from_translation(glm::vec3(0.0, 1.0, 0.0)),
+            None,
+        );
+        let child = scene.add_node(
+            "child",
+            Transform::from_translation(glm::vec3(0.0, 1.0, 0.0)),
+            Some(root),
+        );
+        let skeleton = Skeleton {
+            joints: vec![root, child],
+            inverse_bind_matrices: vec![
+                glm::translation(&glm::vec3(0.0, -1.0, 0.0)),
+                glm::translation(&glm::vec3(0.0, -2.0, 0.0)),
+            ],
+        };
+        // In bind pose the vertices don't move
+        for matrix in skeleton.palette(&scene) {
+            assert!((matrix - glm::Mat4::identity()).norm() < 1e-5);
+        }
+
+        // Bending the child a quarter turn around Z swings what's above it to the left
+        scene.set_transform(
+            child,
+            Transform {
+                rotation: glm::quat_angle_axis(90f32.to_radians(), &glm::Vec3::z()),
+                ..Transform::from_translation(glm::vec3(0.0, 1.0, 0.0))
+            },
+        );
+        let palette = skeleton.palette(&scene);
+        assert!((palette[0] - glm::Mat4::identity()).norm() < 1e-5);
+        let tip = palette[1] * glm::vec4(0.0, 3.0, 0.0, 1.0);
+        assert!(approx(&tip.xyz(), &glm::vec3(-1.0, 2.0, 0.0)));
+    }
+
+    #[test]
+    fn bone_weights_are_normalized() {
+        let mut weights = [1.0, 1.0, 2.0, 0.0];
+        normalize_bone_weights(&mut weights);
+        assert_eq!(weights, [0.25, 0.25, 0.5, 0.0]);
+        // Unskinned vertices stay unskinned
+        let mut weights = [0.0; MAX_BONE_INFLUENCE];
+        normalize_bone_weights(&mut weights);
+        assert_eq!(weights, [0.0; MAX_BONE_INFLUENCE]);
+    }
+}
@@ -0,0 +1,316 @@
+use crate::gl_objects::Texture2D;
+use gl::types::*;
+use std::fmt;
+
+/// Depth and stencil storage of a framebuffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthAttachment {
+    None,
+    /// 24-bit depth and 8-bit stencil renderbuffer, for depth testing only
+    Renderbuffer,
+    /// 24-bit depth and 8-bit stencil texture, which can be sampled after the pass
+    Texture,
+}
+
+/// Attachments of a framebuffer and their size
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FramebufferDescriptor {
+    pub width: u32,
+    pub height: u32,
+    /// Internal format of each color attachment, such as `gl::RGBA8` or `gl::RGBA16F` for HDR.
+    /// Attachment `i` is written by fragment output `i`.
+    pub color_formats: Vec<GLenum>,
+    pub depth: DepthAttachment,
+}
+impl Default for FramebufferDescriptor {
+    fn default() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            color_formats: vec![gl::RGBA8],
+            depth: DepthAttachment::Renderbuffer,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FramebufferError {
+    /// Status returned by `glCheckFramebufferStatus`
+    Incomplete(GLenum),
+    /// More color attachments than `GL_MAX_COLOR_ATTACHMENTS`
+    TooManyColorAttachments { count: usize, max: usize },
+    /// A zero width or height
+    EmptySize,
+}
+impl fmt::Display for FramebufferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FramebufferError::Incomplete(status) => {
+                let reason = match *status {
+                    gl::FRAMEBUFFER_UNDEFINED => "undefined",
+                    gl::FRAMEBUFFER_INCOMPLETE_ATTACHMENT => "incomplete attachment",
+                    gl::FRAMEBUFFER_INCOMPLETE_MISSING_ATTACHMENT => "missing attachment",
+                    gl::FRAMEBUFFER_INCOMPLETE_DRAW_BUFFER => "incomplete draw buffer",
+                    gl::FRAMEBUFFER_INCOMPLETE_READ_BUFFER => "incomplete read buffer",
+                    gl::FRAMEBUFFER_UNSUPPORTED => "unsupported combination of formats",
+                    gl::FRAMEBUFFER_INCOMPLETE_MULTISAMPLE => "incomplete multisample",
+                    _ => "unknown status",
+                };
+                write!(f, "incomplete framebuffer: {} (0x{:x})", reason, status)
+            }
+            FramebufferError::TooManyColorAttachments { count, max } => write!(
+                f,
+                "{} color attachments requested, the driver supports {}",
+                count, max
+            ),
+            FramebufferError::EmptySize => write!(f, "framebuffer has an empty size"),
+        }
+    }
+}
+impl std::error::Error for FramebufferError {}
+
+/// Offscreen render target with texture color attachments
+pub struct Framebuffer {
+    id: GLuint,
+    descriptor: FramebufferDescriptor,
+    colors: Vec<Texture2D>,
+    depth_texture: Option<Texture2D>,
+    depth_renderbuffer: GLuint,
+}
+impl Framebuffer {
+    pub fn new(descriptor: FramebufferDescriptor) -> Result<Self, FramebufferError> {
+        let mut max = 0;
+        unsafe { gl::GetIntegerv(gl::MAX_COLOR_ATTACHMENTS, &mut max) };
+        if descriptor.color_formats.len() > max as usize {
+            return Err(FramebufferError::TooManyColorAttachments {
+                count: descriptor.color_formats.len(),
+                max: max as usize,
+            });
+        }
+        let mut id = 0;
+        unsafe { gl::GenFramebuffers(1, &mut id) };
+        let (width, height) = (descriptor.width, descriptor.height);
+        let mut framebuffer = Self {
+            id,
+            descriptor,
+            colors: vec![],
+            depth_texture: None,
+            depth_renderbuffer: 0,
+        };
+        framebuffer.allocate(width, height)?;
+        Ok(framebuffer)
+    }
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+    pub fn width(&self) -> u32 {
+        self.descriptor.width
+    }
+    pub fn height(&self) -> u32 {
+        self.descriptor.height
+    }
+    pub fn descriptor(&self) -> &FramebufferDescriptor {
+        &self.descriptor
+    }
+    /// Binds the framebuffer for drawing and sets the viewport to its size
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+            gl::Viewport(
+                0,
+                0,
+                self.descriptor.width as GLsizei,
+                self.descriptor.height as GLsizei,
+            );
+        }
+    }
+    /// Binds the default framebuffer, the viewport is left as it is
+    pub fn unbind() {
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, 0) }
+    }
+    pub fn color(&self, index: usize) -> Option<&Texture2D> {
+        self.colors.get(index)
+    }
+    /// Only with `DepthAttachment::Texture`
+    pub fn depth_texture(&self) -> Option<&Texture2D> {
+        self.depth_texture.as_ref()
+    }
+    /// Reallocates the attachments, their contents are lost. Does nothing if the size didn't
+    /// change. On failure the attachments are allocated again with the previous size.
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), FramebufferError> {
+        let (previous_width, previous_height) = (self.descriptor.width, self.descriptor.height);
+        if width == previous_width && height == previous_height {
+            return Ok(());
+        }
+        let result = self.allocate(width, height);
+        if result.is_err() {
+            let _ = self.allocate(previous_width, previous_height);
+        }
+        result
+    }
+    /// Creates the attachments for the given size and checks completeness. The size of the
+    /// descriptor is only updated on success. The previously bound framebuffer stays bound.
+    fn allocate(&mut self, width: u32, height: u32) -> Result<(), FramebufferError> {
+        if width == 0 || height == 0 {
+            return Err(FramebufferError::EmptySize);
+        }
+        let FramebufferDescriptor {
+            ref color_formats,
+            depth,
+            ..
+        } = self.descriptor;
+        let (gl_width, gl_height) = (width as GLsizei, height as GLsizei);
+        let mut previous = 0;
+        unsafe {
+            gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+        }
+
+        self.colors = color_formats
+            .iter()
+            .enumerate()
+            .map(|(i, internal_format)| {
+                let (format, data_type) = transfer_format(*internal_format);
+                let texture = Texture2D::new();
+                texture.bind();
+                unsafe {
+                    gl::TexImage2D(
+                        gl::TEXTURE_2D,
+                        0,
+                        *internal_format as GLint,
+                        gl_width,
+                        gl_height,
+                        0,
+                        format,
+                        data_type,
+                        std::ptr::null(),
+                    );
+                    // Integer textures are incomplete with linear filtering
+                    let integer = matches!(
+                        format,
+                        gl::RED_INTEGER | gl::RG_INTEGER | gl::RGB_INTEGER | gl::RGBA_INTEGER
+                    );
+                    set_sampling(if integer { gl::NEAREST } else { gl::LINEAR });
+                    gl::FramebufferTexture2D(
+                        gl::FRAMEBUFFER,
+                        gl::COLOR_ATTACHMENT0 + i as GLenum,
+                        gl::TEXTURE_2D,
+                        texture.id(),
+                        0,
+                    );
+                }
+                texture
+            })
+            .collect();
+        let draw_buffers: Vec<GLenum> = (0..self.colors.len() as GLenum)
+            .map(|i| gl::COLOR_ATTACHMENT0 + i)
+            .collect();
+        unsafe {
+            if draw_buffers.is_empty() {
+                gl::DrawBuffer(gl::NONE);
+                gl::ReadBuffer(gl::NONE);
+            } else {
+                gl::DrawBuffers(draw_buffers.len() as GLsizei, draw_buffers.as_ptr());
+                gl::ReadBuffer(gl::COLOR_ATTACHMENT0);
+            }
+        }
+
+        self.depth_texture = None;
+        unsafe {
+            gl::DeleteRenderbuffers(1, &self.depth_renderbuffer);
+            self.depth_renderbuffer = 0;
+        }
+        match depth {
+            DepthAttachment::None => {}
+            DepthAttachment::Renderbuffer => unsafe {
+                gl::GenRenderbuffers(1, &mut self.depth_renderbuffer);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, self.depth_renderbuffer);
+                gl::RenderbufferStorage(
+                    gl::RENDERBUFFER,
+                    gl::DEPTH24_STENCIL8,
+                    gl_width,
+                    gl_height,
+                );
+                gl::FramebufferRenderbuffer(
+                    gl::FRAMEBUFFER,
+                    gl::DEPTH_STENCIL_ATTACHMENT,
+                    gl::RENDERBUFFER,
+                    self.depth_renderbuffer,
+                );
+                gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+            },
+            DepthAttachment::Texture => {
+                let texture = Texture2D::new();
+                texture.bind();
+                unsafe {
+                    gl::TexImage2D(
+                        gl::TEXTURE_2D,
+                        0,
+                        gl::DEPTH24_STENCIL8 as GLint,
+                        gl_width,
+                        gl_height,
+                        0,
+                        gl::DEPTH_STENCIL,
+                        gl::UNSIGNED_INT_24_8,
+                        std::ptr::null(),
+                    );
+                    set_sampling(gl::NEAREST);
+                    gl::FramebufferTexture2D(
+                        gl::FRAMEBUFFER,
+                        gl::DEPTH_STENCIL_ATTACHMENT,
+                        gl::TEXTURE_2D,
+                        texture.id(),
+                        0,
+                    );
+                }
+                self.depth_texture = Some(texture);
+            }
+        }
+
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, previous as GLuint) };
+        if status == gl::FRAMEBUFFER_COMPLETE {
+            self.descriptor.width = width;
+            self.descriptor.height = height;
+            Ok(())
+        } else {
+            Err(FramebufferError::Incomplete(status))
+        }
+    }
+}
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+            gl::DeleteRenderbuffers(1, &self.depth_renderbuffer);
+        }
+    }
+}
+
+/// Pixel format and type accepted with `internal_format` when allocating without data
+fn transfer_format(internal_format: GLenum) -> (GLenum, GLenum) {
+    match internal_format {
+        gl::R8 | gl::R16F | gl::R32F => (gl::RED, gl::FLOAT),
+        gl::RG8 | gl::RG16F | gl::RG32F => (gl::RG, gl::FLOAT),
+        gl::RGB8 | gl::SRGB8 | gl::RGB16F | gl::RGB32F | gl::R11F_G11F_B10F => (gl::RGB, gl::FLOAT),
+        gl::R32I => (gl::RED_INTEGER, gl::INT),
+        gl::R32UI => (gl::RED_INTEGER, gl::UNSIGNED_INT),
+        _ => (gl::RGBA, gl::FLOAT),
+    }
+}
+
+/// Clamped to the edges of the bound 2D texture, without mipmaps
+unsafe fn set_sampling(filter: GLenum) {
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, filter as GLint);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, filter as GLint);
+    gl::TexParameteri(
+        gl::TEXTURE_2D,
+        gl::TEXTURE_WRAP_S,
+        gl::CLAMP_TO_EDGE as GLint,
+    );
+    gl::TexParameteri(
+        gl::TEXTURE_2D,
+        gl::TEXTURE_WRAP_T,
+        gl::CLAMP_TO_EDGE as GLint,
+    );
+}
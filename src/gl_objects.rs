@@ -0,0 +1,247 @@
+extern crate gl;
+use gl::types::*;
+use std::ffi::c_void;
+use std::mem::size_of_val;
+
+// Owned OpenGL objects, deleted when dropped. They must be dropped while the context that created
+// them is still current. Objects shared between owners (textures used by several meshes) are
+// wrapped in an `Rc` so they are deleted once.
+
+#[derive(Debug)]
+pub struct VertexArray(GLuint);
+impl VertexArray {
+    pub fn new() -> Self {
+        let mut id = 0;
+        unsafe { gl::GenVertexArrays(1, &mut id) };
+        Self(id)
+    }
+    pub fn id(&self) -> GLuint {
+        self.0
+    }
+    pub fn bind(&self) {
+        unsafe { gl::BindVertexArray(self.0) }
+    }
+    pub fn unbind() {
+        unsafe { gl::BindVertexArray(0) }
+    }
+}
+impl Default for VertexArray {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteVertexArrays(1, &self.0) }
+    }
+}
+
+#[derive(Debug)]
+pub struct Buffer {
+    id: GLuint,
+    target: GLenum,
+}
+impl Buffer {
+    /// `target` is the binding point used by `bind`, e.g. `gl::ARRAY_BUFFER`
+    pub fn new(target: GLenum) -> Self {
+        let mut id = 0;
+        unsafe { gl::GenBuffers(1, &mut id) };
+        Self { id, target }
+    }
+    /// Creates a buffer and uploads `data` to it, leaving it bound
+    pub fn with_data<T>(target: GLenum, data: &[T], usage: GLenum) -> Self {
+        let buffer = Self::new(target);
+        buffer.bind();
+        buffer.data(data, usage);
+        buffer
+    }
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+    pub fn bind(&self) {
+        unsafe { gl::BindBuffer(self.target, self.id) }
+    }
+    /// Replaces the content of the buffer, which must be bound
+    pub fn data<T>(&self, data: &[T], usage: GLenum) {
+        unsafe {
+            gl::BufferData(
+                self.target,
+                size_of_val(data) as GLsizeiptr,
+                data.as_ptr() as *const c_void,
+                usage,
+            );
+        }
+    }
+    /// Overwrites part of the content, starting `offset` bytes in. The buffer must be bound.
+    pub fn sub_data<T>(&self, offset: usize, data: &[T]) {
+        unsafe {
+            gl::BufferSubData(
+                self.target,
+                offset as GLintptr,
+                size_of_val(data) as GLsizeiptr,
+                data.as_ptr() as *const c_void,
+            );
+        }
+    }
+}
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.id) }
+    }
+}
+
+#[derive(Debug)]
+pub struct Texture2D(GLuint);
+impl Texture2D {
+    pub fn new() -> Self {
+        let mut id = 0;
+        unsafe { gl::GenTextures(1, &mut id) };
+        Self(id)
+    }
+    pub fn id(&self) -> GLuint {
+        self.0
+    }
+    pub fn bind(&self) {
+        unsafe { gl::BindTexture(gl::TEXTURE_2D, self.0) }
+    }
+    /// Binds the texture to texture unit `unit`
+    pub fn bind_to_unit(&self, unit: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_2D, self.0);
+        }
+    }
+}
+impl Default for Texture2D {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Drop for Texture2D {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.0) }
+    }
+}
+
+#[derive(Debug)]
+pub struct TextureCube(GLuint);
+impl TextureCube {
+    pub fn new() -> Self {
+        let mut id = 0;
+        unsafe { gl::GenTextures(1, &mut id) };
+        Self(id)
+    }
+    pub fn id(&self) -> GLuint {
+        self.0
+    }
+    pub fn bind(&self) {
+        unsafe { gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.0) }
+    }
+    /// Binds the texture to texture unit `unit`
+    pub fn bind_to_unit(&self, unit: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.0);
+        }
+    }
+}
+impl Default for TextureCube {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Drop for TextureCube {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.0) }
+    }
+}
+
+/// Array of 2D textures of the same size, sampled with a `sampler2DArray`
+#[derive(Debug)]
+pub struct Texture2DArray(GLuint);
+impl Texture2DArray {
+    pub fn new() -> Self {
+        let mut id = 0;
+        unsafe { gl::GenTextures(1, &mut id) };
+        Self(id)
+    }
+    pub fn id(&self) -> GLuint {
+        self.0
+    }
+    pub fn bind(&self) {
+        unsafe { gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.0) }
+    }
+    /// Binds the texture to texture unit `unit`
+    pub fn bind_to_unit(&self, unit: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.0);
+        }
+    }
+}
+impl Default for Texture2DArray {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Drop for Texture2DArray {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.0) }
+    }
+}
+
+/// Texture reading its texels from a buffer, sampled with `texelFetch` on a `samplerBuffer`
+#[derive(Debug)]
+pub struct BufferTexture {
+    texture: GLuint,
+    buffer: Buffer,
+}
+impl BufferTexture {
+    /// `format` is the sized internal format of the texels, e.g. `gl::RGBA32F`
+    pub fn with_data<T>(format: GLenum, data: &[T]) -> Self {
+        let buffer = Buffer::with_data(gl::TEXTURE_BUFFER, data, gl::STATIC_DRAW);
+        let mut texture = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_BUFFER, texture);
+            gl::TexBuffer(gl::TEXTURE_BUFFER, format, buffer.id());
+            gl::BindTexture(gl::TEXTURE_BUFFER, 0);
+            gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+        }
+        Self { texture, buffer }
+    }
+    pub fn id(&self) -> GLuint {
+        self.texture
+    }
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+    pub fn bind_to_unit(&self, unit: u32) {
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_BUFFER, self.texture);
+        }
+    }
+}
+impl Drop for BufferTexture {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.texture) }
+    }
+}
+
+#[derive(Debug)]
+pub struct Program(GLuint);
+impl Program {
+    /// Takes ownership of a linked program
+    pub fn from_raw(id: GLuint) -> Self {
+        Self(id)
+    }
+    pub fn id(&self) -> GLuint {
+        self.0
+    }
+}
+impl Drop for Program {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteProgram(self.0) }
+    }
+}
@@ -0,0 +1,647 @@
+use crate::animation::{
+    normalize_bone_weights, AnimationClip, Channel, Interpolation, Keyframe, Keyframes, Skeleton,
+};
+use crate::importer::{
+    flip_uvs, generate_normals, generate_tangents, ImageData, ImportOptions, MaterialData,
+    ModelData, ModelImporter, NodeData, TextureSource,
+};
+use crate::material::TextureSlot;
+use crate::mesh::{MeshData, Vertex};
+use crate::model::ModelError;
+use crate::morph::MorphTarget;
+use crate::scene::{NodeId, Transform};
+use gltf::animation::util::ReadOutputs;
+use gltf::image::Format;
+use gltf::mesh::Mode;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// glTF 2.0 files, both `.gltf` with external or embedded buffers and binary `.glb`.
+/// Image indices are the same as in the file. Nodes are not: the nodes of the default scene (or
+/// of the first one) are stored parents first, in depth-first order from its roots, and the other
+/// nodes are dropped. Every triangle primitive becomes a mesh, copied for each skin it is drawn
+/// with.
+pub struct GltfImporter;
+impl ModelImporter for GltfImporter {
+    fn import(&self, path: &Path, options: &ImportOptions) -> Result<ModelData, ModelError> {
+        let (document, buffers, images) =
+            gltf::import(path).map_err(|error| ModelError::Parse {
+                path: path.to_path_buf(),
+                message: error.to_string(),
+            })?;
+
+        let mut data = ModelData {
+            materials: document.materials().map(convert_material).collect(),
+            images: images.iter().map(convert_image).collect(),
+            ..ModelData::default()
+        };
+
+        // Every primitive has its own material, so it becomes a mesh of its own
+        let mut primitive_meshes: Vec<Vec<usize>> = vec![];
+        for mesh in document.meshes() {
+            let mut meshes = vec![];
+            for primitive in mesh.primitives() {
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                // Sparse accessors are applied by the reader
+                let Some(positions) = reader.read_positions() else {
+                    continue;
+                };
+                let mut vertices: Vec<Vertex> = positions
+                    .map(|position| Vertex {
+                        position: position.into(),
+                        normal: glm::Vec3::zeros(),
+                        tex_coords: glm::Vec2::zeros(),
+                        ..Vertex::default()
+                    })
+                    .collect();
+                let has_normals = match reader.read_normals() {
+                    Some(normals) => {
+                        for (vertex, normal) in vertices.iter_mut().zip(normals) {
+                            vertex.normal = normal.into();
+                        }
+                        true
+                    }
+                    None => false,
+                };
+                let has_tangents = match reader.read_tangents() {
+                    Some(tangents) => {
+                        for (vertex, [x, y, z, w]) in vertices.iter_mut().zip(tangents) {
+                            vertex.tangent = glm::vec3(x, y, z);
+                            vertex.bitangent = vertex.normal.cross(&vertex.tangent) * w;
+                        }
+                        true
+                    }
+                    None => false,
+                };
+                // The origin of glTF texture coordinates is the top left corner, same as the
+                // first row of the images
+                let has_tex_coords = match reader.read_tex_coords(0) {
+                    Some(tex_coords) => {
+                        for (vertex, tex_coord) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+                            vertex.tex_coords = tex_coord.into();
+                        }
+                        true
+                    }
+                    None => false,
+                };
+                if let (Some(joints), Some(weights)) =
+                    (reader.read_joints(0), reader.read_weights(0))
+                {
+                    let influences = joints.into_u16().zip(weights.into_f32());
+                    for (vertex, (ids, weights)) in vertices.iter_mut().zip(influences) {
+                        vertex.bone_ids = ids.map(u32::from);
+                        vertex.bone_weights = weights;
+                        normalize_bone_weights(&mut vertex.bone_weights);
+                    }
+                }
+                let indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..vertices.len() as u32).collect(),
+                };
+                // Meshes are drawn as triangle lists, points and lines are skipped
+                let Some(mut indices) = triangle_list(primitive.mode(), indices) else {
+                    continue;
+                };
+
+                let mut morph_targets: Vec<MorphTarget> = reader
+                    .read_morph_targets()
+                    .map(|(positions, normals, _)| MorphTarget {
+                        name: String::new(),
+                        position_deltas: positions
+                            .map(|deltas| deltas.map(Into::into).collect())
+                            .unwrap_or_else(|| vec![glm::Vec3::zeros(); vertices.len()]),
+                        normal_deltas: normals
+                            .map(|deltas| deltas.map(Into::into).collect())
+                            .unwrap_or_default(),
+                    })
+                    .collect();
+
+                if options.flip_uvs {
+                    flip_uvs(&mut vertices);
+                }
+                if !has_normals && options.generate_normals {
+                    generate_normals(&mut vertices, &indices);
+                }
+                // The spec asks for MikkTSpace tangents when they are missing
+                if !has_tangents && has_tex_coords && options.calc_tangents {
+                    let copied = generate_tangents(&mut vertices, &mut indices);
+                    for target in morph_targets.iter_mut() {
+                        for deltas in [&mut target.position_deltas, &mut target.normal_deltas] {
+                            if !deltas.is_empty() {
+                                for &source in copied.iter() {
+                                    deltas.push(deltas[source]);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                meshes.push(data.meshes.len());
+                data.meshes.push(MeshData {
+                    name: mesh.name().unwrap_or_default().to_string(),
+                    vertices,
+                    indices,
+                    material: primitive.material().index(),
+                    skin: None,
+                    morph_targets,
+                    morph_weights: mesh.weights().map(<[f32]>::to_vec).unwrap_or_default(),
+                });
+            }
+            primitive_meshes.push(meshes);
+        }
+
+        let roots: Vec<usize> = match document
+            .default_scene()
+            .or_else(|| document.scenes().next())
+        {
+            Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+            // Without scenes, every node that isn't a child is shown
+            None => document
+                .nodes()
+                .filter(|node| {
+                    !document
+                        .nodes()
+                        .any(|parent| parent.children().any(|child| child.index() == node.index()))
+                })
+                .map(|node| node.index())
+                .collect(),
+        };
+        // Nodes are stored parents first, nodes outside of the scene are dropped
+        let gltf_nodes: Vec<gltf::Node> = document.nodes().collect();
+        let mut order = vec![];
+        let mut stack: Vec<usize> = roots.iter().rev().copied().collect();
+        while let Some(index) = stack.pop() {
+            order.push(index);
+            let children: Vec<usize> = gltf_nodes[index]
+                .children()
+                .map(|child| child.index())
+                .collect();
+            stack.extend(children.into_iter().rev());
+        }
+        let mut remap = vec![None; gltf_nodes.len()];
+        for (i, index) in order.iter().enumerate() {
+            remap[*index] = Some(i);
+        }
+        data.roots = roots.iter().filter_map(|root| remap[*root]).collect();
+        // The skin is part of the mesh data while glTF sets it on the node: the first node
+        // drawing a mesh gives it its skin, the nodes drawing it with another one get a copy
+        let mut mesh_skins: Vec<Option<Option<usize>>> = vec![None; data.meshes.len()];
+        let mut skinned_copies: HashMap<(usize, Option<usize>), usize> = HashMap::new();
+        for index in order.iter() {
+            let node = &gltf_nodes[*index];
+            let (translation, [x, y, z, w], scale) = node.transform().decomposed();
+            let skin = node.skin().map(|skin| skin.index());
+            let meshes = node
+                .mesh()
+                .map(|mesh| primitive_meshes[mesh.index()].clone())
+                .unwrap_or_default()
+                .into_iter()
+                .map(|mesh| match mesh_skins[mesh] {
+                    None => {
+                        mesh_skins[mesh] = Some(skin);
+                        data.meshes[mesh].skin = skin;
+                        mesh
+                    }
+                    Some(owner) if owner == skin => mesh,
+                    Some(_) => *skinned_copies.entry((mesh, skin)).or_insert_with(|| {
+                        let copy = MeshData {
+                            skin,
+                            ..data.meshes[mesh].clone()
+                        };
+                        data.meshes.push(copy);
+                        data.meshes.len() - 1
+                    }),
+                })
+                .collect();
+            data.nodes.push(NodeData {
+                name: node.name().unwrap_or_default().to_string(),
+                transform: Transform {
+                    translation: translation.into(),
+                    rotation: glm::quat(x, y, z, w),
+                    scale: scale.into(),
+                },
+                meshes,
+                children: node
+                    .children()
+                    .filter_map(|child| remap[child.index()])
+                    .collect(),
+            });
+        }
+
+        data.skins = document
+            .skins()
+            .map(|skin| {
+                // A skin can't be posed without all of its joints
+                let joints = skin
+                    .joints()
+                    .map(|joint| {
+                        remap[joint.index()]
+                            .map(NodeId)
+                            .ok_or_else(|| ModelError::Parse {
+                                path: path.to_path_buf(),
+                                message: format!(
+                                    "joint {} of skin {} is outside of the scene",
+                                    joint.index(),
+                                    skin.index()
+                                ),
+                            })
+                    })
+                    .collect::<Result<Vec<NodeId>, ModelError>>()?;
+                let reader = skin.reader(|buffer| Some(&buffers[buffer.index()]));
+                let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+                    Some(matrices) => matrices.map(glm::Mat4::from).collect(),
+                    None => vec![glm::Mat4::identity(); joints.len()],
+                };
+                Ok(Skeleton {
+                    joints,
+                    inverse_bind_matrices,
+                })
+            })
+            .collect::<Result<_, ModelError>>()?;
+        data.animations = document
+            .animations()
+            .map(|animation| convert_animation(animation, &buffers, &remap))
+            .collect();
+        Ok(data)
+    }
+}
+
+/// Channels targeting nodes outside of the scene or morph target weights are skipped
+fn convert_animation(
+    animation: gltf::Animation,
+    buffers: &[gltf::buffer::Data],
+    remap: &[Option<usize>],
+) -> AnimationClip {
+    let mut clip = AnimationClip {
+        name: animation.name().unwrap_or_default().to_string(),
+        ..AnimationClip::default()
+    };
+    for channel in animation.channels() {
+        let Some(node) = remap[channel.target().node().index()] else {
+            continue;
+        };
+        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+        let (Some(inputs), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+            continue;
+        };
+        let times: Vec<f32> = inputs.collect();
+        let interpolation = match channel.sampler().interpolation() {
+            gltf::animation::Interpolation::Step => Interpolation::Step,
+            gltf::animation::Interpolation::Linear
+            | gltf::animation::Interpolation::CubicSpline => Interpolation::Linear,
+        };
+        // Cubic splines store an in-tangent, the value and an out-tangent per keyframe, the
+        // tangents are ignored and the values are interpolated linearly
+        let cubic =
+            channel.sampler().interpolation() == gltf::animation::Interpolation::CubicSpline;
+        let keyframes = match outputs {
+            ReadOutputs::Translations(values) => {
+                Keyframes::Translation(keyframes(&times, values.map(Into::into), cubic))
+            }
+            ReadOutputs::Rotations(values) => Keyframes::Rotation(keyframes(
+                &times,
+                values.into_f32().map(|[x, y, z, w]| glm::quat(x, y, z, w)),
+                cubic,
+            )),
+            ReadOutputs::Scales(values) => {
+                Keyframes::Scale(keyframes(&times, values.map(Into::into), cubic))
+            }
+            ReadOutputs::MorphTargetWeights(_) => continue,
+        };
+        clip.duration = times.iter().copied().fold(clip.duration, f32::max);
+        clip.channels.push(Channel {
+            node: NodeId(node),
+            keyframes,
+            interpolation,
+        });
+    }
+    clip
+}
+
+fn keyframes<T>(times: &[f32], values: impl Iterator<Item = T>, cubic: bool) -> Vec<Keyframe<T>> {
+    let (skip, step) = if cubic { (1, 3) } else { (0, 1) };
+    times
+        .iter()
+        .zip(values.skip(skip).step_by(step))
+        .map(|(time, value)| Keyframe { time: *time, value })
+        .collect()
+}
+
+/// Converts strips and fans to a list of triangles, `None` for points and lines
+fn triangle_list(mode: Mode, indices: Vec<u32>) -> Option<Vec<u32>> {
+    match mode {
+        Mode::Triangles => Some(indices),
+        Mode::TriangleStrip => Some(
+            (0..indices.len().saturating_sub(2))
+                .flat_map(|i| {
+                    // Every other triangle is flipped to keep the winding order
+                    if i % 2 == 0 {
+                        [indices[i], indices[i + 1], indices[i + 2]]
+                    } else {
+                        [indices[i + 1], indices[i], indices[i + 2]]
+                    }
+                })
+                .collect(),
+        ),
+        Mode::TriangleFan => Some(
+            (1..indices.len().saturating_sub(1))
+                .flat_map(|i| [indices[0], indices[i], indices[i + 1]])
+                .collect(),
+        ),
+        Mode::Points | Mode::Lines | Mode::LineLoop | Mode::LineStrip => None,
+    }
+}
+
+fn convert_material(material: gltf::Material) -> MaterialData {
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, a] = pbr.base_color_factor();
+    let mut data = MaterialData {
+        name: material.name().unwrap_or_default().to_string(),
+        diffuse: glm::vec3(r, g, b),
+        opacity: a,
+        emissive: material.emissive_factor().into(),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        ..MaterialData::default()
+    };
+    let mut set = |slot, texture: gltf::Texture| {
+        data.set_texture(slot, TextureSource::Embedded(texture.source().index()))
+    };
+    if let Some(info) = pbr.base_color_texture() {
+        set(TextureSlot::Diffuse, info.texture());
+    }
+    if let Some(info) = pbr.metallic_roughness_texture() {
+        set(TextureSlot::MetallicRoughness, info.texture());
+    }
+    if let Some(normal) = material.normal_texture() {
+        set(TextureSlot::Normal, normal.texture());
+    }
+    if let Some(occlusion) = material.occlusion_texture() {
+        set(TextureSlot::AmbientOcclusion, occlusion.texture());
+    }
+    if let Some(info) = material.emissive_texture() {
+        set(TextureSlot::Emissive, info.texture());
+    }
+    data
+}
+
+/// Expands the decoded image to 8-bit RGBA. Grayscale is replicated to the color channels,
+/// 16-bit and float channels, in native byte order, are scaled down.
+fn convert_image(image: &gltf::image::Data) -> ImageData {
+    let (channels, bytes_per_channel) = match image.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let channel = |bytes: &[u8]| -> u8 {
+        match bytes_per_channel {
+            1 => bytes[0],
+            2 => (u16::from_ne_bytes([bytes[0], bytes[1]]) >> 8) as u8,
+            _ => {
+                let value = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                (value.clamp(0.0, 1.0) * 255.0).round() as u8
+            }
+        }
+    };
+
+    let mut pixels = Vec::with_capacity(image.width as usize * image.height as usize * 4);
+    for pixel in image.pixels.chunks_exact(channels * bytes_per_channel) {
+        let values: Vec<u8> = pixel.chunks_exact(bytes_per_channel).map(channel).collect();
+        let rgba = match values[..] {
+            [l] => [l, l, l, 255],
+            [l, a] => [l, l, l, a],
+            [r, g, b] => [r, g, b, 255],
+            [r, g, b, a] => [r, g, b, a],
+            _ => unreachable!(),
+        };
+        pixels.extend_from_slice(&rgba);
+    }
+    ImageData {
+        width: image.width,
+        height: image.height,
+        pixels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempDir;
+    use std::path::PathBuf;
+
+    /// A triangle whose second position is moved by a sparse accessor, drawn twice by a child
+    /// node with and without material. The third node isn't part of the scene.
+    fn document(buffer: &str, skins: &str) -> String {
+        format!(
+            r#"{{
+  "asset": {{ "version": "2.0" }},
+  "scene": 0,
+  "scenes": [{{ "nodes": [0] }}],
+  "nodes": [
+    {{ "name": "root", "children": [1], "translation": [0, 1, 0] }},
+    {{ "name": "child", "mesh": 0 }},
+    {{ "name": "orphan" }}
+  ],
+  "meshes": [{{
+    "primitives": [
+      {{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "material": 1 }},
+      {{ "attributes": {{ "POSITION": 0 }}, "indices": 1 }}
+    ]
+  }}],
+  "materials": [
+    {{ "name": "unused" }},
+    {{
+      "name": "red",
+      "pbrMetallicRoughness": {{
+        "baseColorFactor": [1, 0, 0, 1],
+        "metallicFactor": 0.25,
+        "roughnessFactor": 0.5
+      }}
+    }}
+  ],
+  "accessors": [
+    {{
+      "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+      "min": [0, 0, 0], "max": [2, 1, 0],
+      "sparse": {{
+        "count": 1,
+        "indices": {{ "bufferView": 2, "componentType": 5123 }},
+        "values": {{ "bufferView": 3 }}
+      }}
+    }},
+    {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+  ],
+  "bufferViews": [
+    {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+    {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }},
+    {{ "buffer": 0, "byteOffset": 44, "byteLength": 2 }},
+    {{ "buffer": 0, "byteOffset": 48, "byteLength": 12 }}
+  ],
+  "buffers": [{buffer}]{skins}
+}}"#
+        )
+    }
+
+    /// Positions, indices, then the sparse index and value, each 4-byte aligned
+    fn buffer() -> Vec<u8> {
+        let mut bytes = vec![];
+        for value in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for index in [0u16, 1, 2, 0, 1, 0] {
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+        for value in [2.0f32, 0.0, 0.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        assert_eq!(bytes.len(), 60);
+        bytes
+    }
+
+    /// Binary container with a JSON chunk and a BIN chunk
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = bin.to_vec();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+        let mut bytes = vec![];
+        bytes.extend_from_slice(b"glTF");
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&(length as u32).to_le_bytes());
+        for (chunk, kind) in [(json, b"JSON"), (bin, b"BIN\0")] {
+            bytes.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(kind);
+            bytes.extend_from_slice(&chunk);
+        }
+        bytes
+    }
+
+    fn write(dir: &TempDir, name: &str, contents: &[u8]) -> PathBuf {
+        let path = dir.path().join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn check(data: &ModelData) {
+        assert_eq!(data.roots, vec![0]);
+        let names: Vec<&str> = data.nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(names, ["root", "child"]);
+        assert_eq!(data.nodes[0].children, vec![1]);
+        assert_eq!(
+            data.nodes[0].transform.translation,
+            glm::vec3(0.0, 1.0, 0.0)
+        );
+        // One mesh per primitive
+        assert_eq!(data.nodes[1].meshes, vec![0, 1]);
+        assert_eq!(data.meshes.len(), 2);
+        assert_eq!(data.meshes[0].material, Some(1));
+        assert_eq!(data.meshes[1].material, None);
+
+        assert_eq!(data.materials.len(), 2);
+        let red = &data.materials[1];
+        assert_eq!(red.name, "red");
+        assert_eq!(red.diffuse, glm::vec3(1.0, 0.0, 0.0));
+        assert_eq!((red.metallic, red.roughness), (0.25, 0.5));
+
+        for mesh in data.meshes.iter() {
+            assert_eq!(mesh.indices, vec![0, 1, 2]);
+            let positions: Vec<glm::Vec3> = mesh.vertices.iter().map(|v| v.position).collect();
+            // The second position comes from the sparse accessor
+            assert_eq!(
+                positions,
+                vec![
+                    glm::vec3(0.0, 0.0, 0.0),
+                    glm::vec3(2.0, 0.0, 0.0),
+                    glm::vec3(0.0, 1.0, 0.0),
+                ]
+            );
+            assert!(mesh
+                .vertices
+                .iter()
+                .all(|v| (v.normal - glm::Vec3::z()).norm() < 1e-5));
+        }
+    }
+
+    #[test]
+    fn gltf_with_external_buffer() {
+        let dir = TempDir::new("gltf-external-test");
+        write(&dir, "triangle.bin", &buffer());
+        let json = document(r#"{ "byteLength": 60, "uri": "triangle.bin" }"#, "");
+        let path = write(&dir, "external.gltf", json.as_bytes());
+        check(
+            &GltfImporter
+                .import(&path, &ImportOptions::default())
+                .unwrap(),
+        );
+    }
+
+    #[test]
+    fn binary_glb() {
+        let dir = TempDir::new("gltf-binary-test");
+        let json = document(r#"{ "byteLength": 60 }"#, "");
+        let path = write(&dir, "binary.glb", &glb(&json, &buffer()));
+        check(
+            &GltfImporter
+                .import(&path, &ImportOptions::default())
+                .unwrap(),
+        );
+    }
+
+    #[test]
+    fn meshes_are_copied_for_each_skin() {
+        let json = document(
+            r#"{ "byteLength": 60 }"#,
+            r#", "skins": [{ "joints": [0] }, { "joints": [0, 1] }]"#,
+        )
+        .replace(r#""children": [1]"#, r#""children": [1, 3, 4, 5]"#)
+        .replace(
+            r#"{ "name": "orphan" }"#,
+            r#"{ "name": "orphan" },
+    { "name": "skinned", "mesh": 0, "skin": 0 },
+    { "name": "skinned again", "mesh": 0, "skin": 0 },
+    { "name": "other skin", "mesh": 0, "skin": 1 }"#,
+        );
+        let dir = TempDir::new("gltf-skins-test");
+        let path = write(&dir, "instanced-skins.glb", &glb(&json, &buffer()));
+        let data = GltfImporter
+            .import(&path, &ImportOptions::default())
+            .unwrap();
+
+        let names: Vec<&str> = data.nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["root", "child", "skinned", "skinned again", "other skin"]
+        );
+        let meshes: Vec<&[usize]> = data.nodes[1..]
+            .iter()
+            .map(|node| node.meshes.as_slice())
+            .collect();
+        assert_eq!(meshes, [&[0, 1][..], &[2, 3], &[2, 3], &[4, 5]]);
+        let skins: Vec<Option<usize>> = data.meshes.iter().map(|mesh| mesh.skin).collect();
+        assert_eq!(skins, [None, None, Some(0), Some(0), Some(1), Some(1)]);
+        assert_eq!(data.meshes[2].vertices, data.meshes[0].vertices);
+        assert_eq!(data.meshes[5].material, data.meshes[1].material);
+    }
+
+    #[test]
+    fn joints_outside_of_the_scene_are_an_error() {
+        let json = document(
+            r#"{ "byteLength": 60 }"#,
+            r#", "skins": [{ "joints": [2] }]"#,
+        );
+        let dir = TempDir::new("gltf-joint-test");
+        let path = write(&dir, "orphan-joint.glb", &glb(&json, &buffer()));
+        match GltfImporter.import(&path, &ImportOptions::default()) {
+            Err(ModelError::Parse { message, .. }) => assert!(message.contains("joint 2")),
+            other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,771 @@
+use crate::gl_objects::{Buffer, Texture2D, TextureCube, VertexArray};
+use crate::mesh::{PositionVertex, ScreenQuad};
+use crate::preprocessor::preprocess;
+use crate::program_cache::Fnv1a;
+use crate::shader::{Shader, ShaderBuilder, ShaderError};
+use crate::texture::{load_texture_with, TextureDescriptor, TextureError};
+use crate::vertex_layout::apply_layout;
+use gl::types::*;
+use std::ffi::c_void;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Texture units of the environment maps, after the units of the material slots
+pub const IRRADIANCE_UNIT: u32 = 7;
+pub const PREFILTERED_UNIT: u32 = 8;
+pub const BRDF_LUT_UNIT: u32 = 9;
+
+const CUBE_VERTEX_SHADER: &str = "src/shaders/cubemap-vertex.glsl";
+const EQUIRECT_TO_CUBE_SHADER: &str = "src/shaders/equirect-to-cube.glsl";
+const IRRADIANCE_SHADER: &str = "src/shaders/irradiance.glsl";
+const PREFILTER_SHADER: &str = "src/shaders/prefilter.glsl";
+const QUAD_VERTEX_SHADER: &str = "src/shaders/quad-vertex.glsl";
+const BRDF_SHADER: &str = "src/shaders/brdf.glsl";
+
+/// Sizes of the precomputed maps, in texels per side
+#[derive(Clone, Debug)]
+pub struct IblSettings {
+    /// Cube map the equirectangular image is projected to
+    pub environment_size: u32,
+    pub irradiance_size: u32,
+    /// Base level of the prefiltered specular map, each mip level is for a rougher surface
+    pub prefiltered_size: u32,
+    pub prefiltered_mips: u32,
+    pub brdf_size: u32,
+    /// Samples per texel of the prefiltered map and the BRDF lookup table
+    pub sample_count: u32,
+    /// Where the precomputed maps are saved, `None` computes them on every load
+    pub cache_dir: Option<PathBuf>,
+}
+impl Default for IblSettings {
+    fn default() -> Self {
+        Self {
+            environment_size: 512,
+            irradiance_size: 32,
+            prefiltered_size: 128,
+            prefiltered_mips: 5,
+            brdf_size: 512,
+            sample_count: 1024,
+            cache_dir: Some(std::env::temp_dir().join("opengl-rust-ibl-cache")),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum IblError {
+    Texture(TextureError),
+    Shader(ShaderError),
+    /// Status returned by `glCheckFramebufferStatus`
+    IncompleteFramebuffer(GLenum),
+}
+impl fmt::Display for IblError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IblError::Texture(error) => write!(f, "{}", error),
+            IblError::Shader(error) => write!(f, "{}", error),
+            IblError::IncompleteFramebuffer(status) => {
+                write!(f, "incomplete capture framebuffer: 0x{:x}", status)
+            }
+        }
+    }
+}
+impl std::error::Error for IblError {}
+impl From<TextureError> for IblError {
+    fn from(error: TextureError) -> Self {
+        IblError::Texture(error)
+    }
+}
+impl From<ShaderError> for IblError {
+    fn from(error: ShaderError) -> Self {
+        IblError::Shader(error)
+    }
+}
+
+/// Image-based lighting from an HDR environment: the diffuse irradiance, the specular radiance
+/// prefiltered by roughness and the split-sum BRDF lookup table
+pub struct Environment {
+    /// The environment itself, for skyboxes
+    pub environment: TextureCube,
+    pub irradiance: TextureCube,
+    pub prefiltered: TextureCube,
+    pub brdf_lut: Texture2D,
+    prefiltered_mips: u32,
+}
+impl Environment {
+    /// Projects an equirectangular HDR image to a cube map and precomputes the lighting maps,
+    /// or reads them from the cache when the image, the settings and the shaders computing them
+    /// didn't change
+    pub fn from_equirect(path: &Path, settings: &IblSettings) -> Result<Self, IblError> {
+        unsafe { gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS) };
+        let descriptor = TextureDescriptor {
+            mipmaps: false,
+            flip_vertically: true,
+            ..TextureDescriptor::default().wrap(gl::CLAMP_TO_EDGE)
+        };
+        let equirect = load_texture_with(path, &descriptor)?;
+        let capture = Capture::new();
+
+        let environment = empty_cube_map(settings.environment_size, true);
+        let shader = capture_shader(EQUIRECT_TO_CUBE_SHADER)?;
+        shader.use_shader();
+        let _ = shader.set_uniform("equirectangularMap", 0);
+        unsafe { gl::ActiveTexture(gl::TEXTURE0) };
+        equirect.bind();
+        capture.render_cube(&shader, &environment, settings.environment_size, 0)?;
+        // The mip levels are sampled by the prefiltering to avoid bright dots
+        environment.bind();
+        unsafe { gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP) };
+
+        let key = environment_key(path, settings);
+        let cached = |name: &str| {
+            let dir = settings.cache_dir.as_ref()?;
+            Some(dir.join(format!("{:016x}-{}.bin", key, name)))
+        };
+
+        let irradiance_path = cached("irradiance");
+        let irradiance = match irradiance_path.as_deref().and_then(CachedTexture::read) {
+            Some(cached) => cached.to_cube_map(),
+            None => {
+                let irradiance = empty_cube_map(settings.irradiance_size, false);
+                let shader = capture_shader(IRRADIANCE_SHADER)?;
+                shader.use_shader();
+                let _ = shader.set_uniform("environmentMap", 0);
+                environment.bind_to_unit(0);
+                capture.render_cube(&shader, &irradiance, settings.irradiance_size, 0)?;
+                if let Some(path) = irradiance_path {
+                    CachedTexture::from_cube_map(&irradiance, settings.irradiance_size, 1)
+                        .write(&path);
+                }
+                irradiance
+            }
+        };
+
+        let prefiltered_path = cached("prefiltered");
+        let prefiltered = match prefiltered_path.as_deref().and_then(CachedTexture::read) {
+            Some(cached) => cached.to_cube_map(),
+            None => {
+                let prefiltered = empty_cube_map(settings.prefiltered_size, true);
+                set_max_level(gl::TEXTURE_CUBE_MAP, settings.prefiltered_mips);
+                let shader = capture_shader(PREFILTER_SHADER)?;
+                shader.use_shader();
+                let _ = shader.set_uniform("environmentMap", 0);
+                let _ = shader.set_uniform("resolution", settings.environment_size as f32);
+                let _ = shader.set_uniform("sampleCount", settings.sample_count as i32);
+                environment.bind_to_unit(0);
+                for mip in 0..settings.prefiltered_mips {
+                    let roughness = mip as f32 / (settings.prefiltered_mips - 1).max(1) as f32;
+                    let _ = shader.set_uniform("roughness", roughness);
+                    capture.render_cube(&shader, &prefiltered, settings.prefiltered_size, mip)?;
+                }
+                if let Some(path) = prefiltered_path {
+                    CachedTexture::from_cube_map(
+                        &prefiltered,
+                        settings.prefiltered_size,
+                        settings.prefiltered_mips,
+                    )
+                    .write(&path);
+                }
+                prefiltered
+            }
+        };
+
+        // Independent of the environment
+        let brdf_path = settings
+            .cache_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("brdf-{:016x}.bin", brdf_key(settings))));
+        let brdf_lut = match brdf_path.as_deref().and_then(CachedTexture::read) {
+            Some(cached) => cached.to_texture_2d(),
+            None => {
+                let brdf_lut = empty_texture_2d(settings.brdf_size);
+                let shader = ShaderBuilder::new()
+                    .vertex(QUAD_VERTEX_SHADER)
+                    .fragment(BRDF_SHADER)
+                    .build()?;
+                shader.use_shader();
+                let _ = shader.set_uniform("sampleCount", settings.sample_count as i32);
+                capture.render_quad(&brdf_lut, settings.brdf_size)?;
+                if let Some(path) = brdf_path {
+                    CachedTexture::from_texture_2d(&brdf_lut, settings.brdf_size).write(&path);
+                }
+                brdf_lut
+            }
+        };
+        drop(capture);
+
+        Ok(Self {
+            environment,
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            prefiltered_mips: settings.prefiltered_mips,
+        })
+    }
+    /// Binds the maps to their units and sets the `irradianceMap`, `prefilterMap`, `brdfLUT`
+    /// and `maxReflectionLod` uniforms of the PBR shader
+    pub fn bind(&self, shader: &Shader) {
+        self.irradiance.bind_to_unit(IRRADIANCE_UNIT);
+        self.prefiltered.bind_to_unit(PREFILTERED_UNIT);
+        self.brdf_lut.bind_to_unit(BRDF_LUT_UNIT);
+        unsafe { gl::ActiveTexture(gl::TEXTURE0) };
+        let _ = shader.set_uniform("irradianceMap", IRRADIANCE_UNIT as i32);
+        let _ = shader.set_uniform("prefilterMap", PREFILTERED_UNIT as i32);
+        let _ = shader.set_uniform("brdfLUT", BRDF_LUT_UNIT as i32);
+        let _ = shader.set_uniform(
+            "maxReflectionLod",
+            self.prefiltered_mips.saturating_sub(1) as f32,
+        );
+    }
+}
+
+fn capture_shader(fragment: &str) -> Result<Shader, ShaderError> {
+    ShaderBuilder::new()
+        .vertex(CUBE_VERTEX_SHADER)
+        .fragment(fragment)
+        .build()
+}
+
+/// The cache entries of an environment change with its file, the settings and the shaders
+fn environment_key(path: &Path, settings: &IblSettings) -> u64 {
+    let mut hash = Fnv1a::new();
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    hash.write(path.to_string_lossy().as_bytes());
+    if let Ok(metadata) = fs::metadata(&path) {
+        hash.write(&metadata.len().to_le_bytes());
+        if let Ok(modified) = metadata.modified() {
+            let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+            hash.write(&since_epoch.as_nanos().to_le_bytes());
+        }
+    }
+    for value in [
+        settings.environment_size,
+        settings.irradiance_size,
+        settings.prefiltered_size,
+        settings.prefiltered_mips,
+        settings.sample_count,
+    ] {
+        hash.write(&value.to_le_bytes());
+    }
+    hash_sources(
+        &mut hash,
+        &[
+            CUBE_VERTEX_SHADER,
+            EQUIRECT_TO_CUBE_SHADER,
+            IRRADIANCE_SHADER,
+            PREFILTER_SHADER,
+        ],
+    );
+    hash.0
+}
+
+fn brdf_key(settings: &IblSettings) -> u64 {
+    let mut hash = Fnv1a::new();
+    hash.write(&settings.brdf_size.to_le_bytes());
+    hash.write(&settings.sample_count.to_le_bytes());
+    hash_sources(&mut hash, &[QUAD_VERTEX_SHADER, BRDF_SHADER]);
+    hash.0
+}
+
+/// Adds the preprocessed sources of `files` to `hash`, like `ProgramCache::key`, so editing a
+/// shader or one of its includes invalidates what it computed. Files that can't be read are
+/// skipped, their shader fails to build and nothing is cached.
+fn hash_sources(hash: &mut Fnv1a, files: &[impl AsRef<Path>]) {
+    for file in files {
+        if let Ok(code) = preprocess(file, &[], |path| fs::read_to_string(path)) {
+            hash.write(&code.source.len().to_le_bytes());
+            hash.write(code.source.as_bytes());
+        }
+    }
+}
+
+#[rustfmt::skip]
+const CUBE: [f32; 108] = [
+    -1.0,  1.0, -1.0,  -1.0, -1.0, -1.0,   1.0, -1.0, -1.0,
+     1.0, -1.0, -1.0,   1.0,  1.0, -1.0,  -1.0,  1.0, -1.0,
+    -1.0, -1.0,  1.0,  -1.0, -1.0, -1.0,  -1.0,  1.0, -1.0,
+    -1.0,  1.0, -1.0,  -1.0,  1.0,  1.0,  -1.0, -1.0,  1.0,
+     1.0, -1.0, -1.0,   1.0, -1.0,  1.0,   1.0,  1.0,  1.0,
+     1.0,  1.0,  1.0,   1.0,  1.0, -1.0,   1.0, -1.0, -1.0,
+    -1.0, -1.0,  1.0,  -1.0,  1.0,  1.0,   1.0,  1.0,  1.0,
+     1.0,  1.0,  1.0,   1.0, -1.0,  1.0,  -1.0, -1.0,  1.0,
+    -1.0,  1.0, -1.0,   1.0,  1.0, -1.0,   1.0,  1.0,  1.0,
+     1.0,  1.0,  1.0,  -1.0,  1.0,  1.0,  -1.0,  1.0, -1.0,
+    -1.0, -1.0, -1.0,  -1.0, -1.0,  1.0,   1.0, -1.0, -1.0,
+     1.0, -1.0, -1.0,  -1.0, -1.0,  1.0,   1.0, -1.0,  1.0,
+];
+/// Offscreen framebuffer the precomputation passes render to. Every target is checked for
+/// completeness before anything is drawn to it, so a failed capture is never cached. The
+/// previous framebuffer and viewport are restored when it's dropped.
+struct Capture {
+    framebuffer: GLuint,
+    renderbuffer: GLuint,
+    cube: VertexArray,
+    _cube_vbo: Buffer,
+    quad: ScreenQuad,
+    previous_framebuffer: GLint,
+    previous_viewport: [GLint; 4],
+}
+impl Capture {
+    fn new() -> Self {
+        let cube = VertexArray::new();
+        cube.bind();
+        let cube_vbo = Buffer::with_data(gl::ARRAY_BUFFER, &CUBE, gl::STATIC_DRAW);
+        apply_layout::<PositionVertex>();
+        VertexArray::unbind();
+
+        let mut capture = Self {
+            framebuffer: 0,
+            renderbuffer: 0,
+            cube,
+            _cube_vbo: cube_vbo,
+            quad: ScreenQuad::new(),
+            previous_framebuffer: 0,
+            previous_viewport: [0; 4],
+        };
+        unsafe {
+            gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut capture.previous_framebuffer);
+            gl::GetIntegerv(gl::VIEWPORT, capture.previous_viewport.as_mut_ptr());
+            gl::GenFramebuffers(1, &mut capture.framebuffer);
+            gl::GenRenderbuffers(1, &mut capture.renderbuffer);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, capture.framebuffer);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, capture.renderbuffer);
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                gl::RENDERBUFFER,
+                capture.renderbuffer,
+            );
+        }
+        capture
+    }
+    fn resize(&self, size: u32) {
+        unsafe {
+            gl::BindRenderbuffer(gl::RENDERBUFFER, self.renderbuffer);
+            gl::RenderbufferStorage(
+                gl::RENDERBUFFER,
+                gl::DEPTH_COMPONENT24,
+                size as GLsizei,
+                size as GLsizei,
+            );
+            gl::Viewport(0, 0, size as GLsizei, size as GLsizei);
+        }
+    }
+    /// Attaches `level` of `texture` as the color target, or of the face `target` of a cube map,
+    /// and checks the framebuffer can be drawn to
+    fn attach(&self, target: GLenum, texture: GLuint, level: u32) -> Result<(), IblError> {
+        unsafe {
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                target,
+                texture,
+                level as GLint,
+            );
+        }
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        if status == gl::FRAMEBUFFER_COMPLETE {
+            Ok(())
+        } else {
+            Err(IblError::IncompleteFramebuffer(status))
+        }
+    }
+    /// Draws the unit cube seen from its center into every face of `target`. `shader` must be
+    /// in use, its `projection` and `view` uniforms are set.
+    fn render_cube(
+        &self,
+        shader: &Shader,
+        target: &TextureCube,
+        size: u32,
+        mip: u32,
+    ) -> Result<(), IblError> {
+        let size = (size >> mip).max(1);
+        self.resize(size);
+        let projection = glm::perspective(1.0, 90f32.to_radians(), 0.1, 10.0);
+        let _ = shader.set_uniform("projection", projection);
+        let eye = glm::Vec3::zeros();
+        // Same orientation as the faces of a cube map, whose images have their origin at the
+        // top left
+        let faces = [
+            (glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, -1.0, 0.0)),
+            (glm::vec3(-1.0, 0.0, 0.0), glm::vec3(0.0, -1.0, 0.0)),
+            (glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, 0.0, 1.0)),
+            (glm::vec3(0.0, -1.0, 0.0), glm::vec3(0.0, 0.0, -1.0)),
+            (glm::vec3(0.0, 0.0, 1.0), glm::vec3(0.0, -1.0, 0.0)),
+            (glm::vec3(0.0, 0.0, -1.0), glm::vec3(0.0, -1.0, 0.0)),
+        ];
+        for (i, (center, up)) in faces.iter().enumerate() {
+            self.attach(
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as GLenum,
+                target.id(),
+                mip,
+            )?;
+            let _ = shader.set_uniform("view", glm::look_at(&eye, center, up));
+            self.cube.bind();
+            unsafe {
+                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+                gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            }
+            VertexArray::unbind();
+        }
+        Ok(())
+    }
+    /// Draws a quad covering `target` with the shader in use
+    fn render_quad(&self, target: &Texture2D, size: u32) -> Result<(), IblError> {
+        self.resize(size);
+        self.attach(gl::TEXTURE_2D, target.id(), 0)?;
+        unsafe { gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT) };
+        self.quad.draw();
+        Ok(())
+    }
+}
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let [x, y, width, height] = self.previous_viewport;
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.previous_framebuffer as GLuint);
+            gl::Viewport(x, y, width, height);
+            gl::DeleteFramebuffers(1, &self.framebuffer);
+            gl::DeleteRenderbuffers(1, &self.renderbuffer);
+        }
+    }
+}
+
+/// Half float RGB cube map, with storage for a full mip chain if `mipmaps` is set
+fn empty_cube_map(size: u32, mipmaps: bool) -> TextureCube {
+    let texture = TextureCube::new();
+    texture.bind();
+    unsafe {
+        for face in 0..6 {
+            gl::TexImage2D(
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                0,
+                gl::RGB16F as GLint,
+                size as GLsizei,
+                size as GLsizei,
+                0,
+                gl::RGB,
+                gl::FLOAT,
+                std::ptr::null(),
+            );
+        }
+        for wrap in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R] {
+            gl::TexParameteri(gl::TEXTURE_CUBE_MAP, wrap, gl::CLAMP_TO_EDGE as GLint);
+        }
+        let min_filter = if mipmaps {
+            gl::LINEAR_MIPMAP_LINEAR
+        } else {
+            gl::LINEAR
+        };
+        gl::TexParameteri(
+            gl::TEXTURE_CUBE_MAP,
+            gl::TEXTURE_MIN_FILTER,
+            min_filter as GLint,
+        );
+        gl::TexParameteri(
+            gl::TEXTURE_CUBE_MAP,
+            gl::TEXTURE_MAG_FILTER,
+            gl::LINEAR as GLint,
+        );
+        if mipmaps {
+            gl::GenerateMipmap(gl::TEXTURE_CUBE_MAP);
+        }
+    }
+    texture
+}
+
+/// Half float RG texture, for the BRDF lookup table
+fn empty_texture_2d(size: u32) -> Texture2D {
+    let texture = Texture2D::new();
+    texture.bind();
+    unsafe {
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RG16F as GLint,
+            size as GLsizei,
+            size as GLsizei,
+            0,
+            gl::RG,
+            gl::FLOAT,
+            std::ptr::null(),
+        );
+        gl::TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_WRAP_S,
+            gl::CLAMP_TO_EDGE as GLint,
+        );
+        gl::TexParameteri(
+            gl::TEXTURE_2D,
+            gl::TEXTURE_WRAP_T,
+            gl::CLAMP_TO_EDGE as GLint,
+        );
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+    }
+    texture
+}
+
+/// Only the first `levels` mip levels are used, the bound texture stays complete without the
+/// smaller ones
+fn set_max_level(target: GLenum, levels: u32) {
+    unsafe {
+        gl::TexParameteri(
+            target,
+            gl::TEXTURE_MAX_LEVEL,
+            levels.saturating_sub(1) as GLint,
+        )
+    };
+}
+
+/// Texels of a precomputed map, as saved in the cache: `IBL1`, then the size of the base level,
+/// the number of levels, layers and channels as little endian `u32`, then the `f32` texels of
+/// every layer of every level
+struct CachedTexture {
+    size: u32,
+    levels: u32,
+    layers: u32,
+    channels: u32,
+    data: Vec<f32>,
+}
+impl CachedTexture {
+    const MAGIC: &'static [u8; 4] = b"IBL1";
+
+    fn from_cube_map(texture: &TextureCube, size: u32, levels: u32) -> Self {
+        texture.bind();
+        let targets: Vec<GLenum> = (0..6)
+            .map(|face| gl::TEXTURE_CUBE_MAP_POSITIVE_X + face)
+            .collect();
+        Self::download(&targets, size, levels, 3)
+    }
+    fn from_texture_2d(texture: &Texture2D, size: u32) -> Self {
+        texture.bind();
+        Self::download(&[gl::TEXTURE_2D], size, 1, 2)
+    }
+    /// Reads the levels of the bound texture, one layer per target
+    fn download(targets: &[GLenum], size: u32, levels: u32, channels: u32) -> Self {
+        let format = if channels == 2 { gl::RG } else { gl::RGB };
+        let mut data = vec![];
+        unsafe { gl::PixelStorei(gl::PACK_ALIGNMENT, 1) };
+        for level in 0..levels {
+            let level_size = (size >> level).max(1) as usize;
+            for target in targets {
+                let start = data.len();
+                data.resize(start + level_size * level_size * channels as usize, 0.0);
+                unsafe {
+                    gl::GetTexImage(
+                        *target,
+                        level as GLint,
+                        format,
+                        gl::FLOAT,
+                        data[start..].as_mut_ptr() as *mut c_void,
+                    );
+                }
+            }
+        }
+        unsafe { gl::PixelStorei(gl::PACK_ALIGNMENT, 4) };
+        Self {
+            size,
+            levels,
+            layers: targets.len() as u32,
+            channels,
+            data,
+        }
+    }
+    /// `None` if the entry is missing or corrupt
+    fn read(path: &Path) -> Option<Self> {
+        let bytes = fs::read(path).ok()?;
+        if bytes.len() < 4 + 4 * 4 {
+            return None;
+        }
+        let (header, texels) = bytes.split_at(4 + 4 * 4);
+        if &header[..4] != Self::MAGIC {
+            return None;
+        }
+        let field = |i: usize| u32::from_le_bytes(header[4 + i * 4..8 + i * 4].try_into().unwrap());
+        let (size, levels, layers, channels) = (field(0), field(1), field(2), field(3));
+        // A full mip chain has `log2(size) + 1` levels, and only RG lookup tables and RGB cube
+        // maps are saved
+        let max_levels = u32::BITS - size.leading_zeros();
+        if levels == 0
+            || levels > max_levels
+            || !matches!(layers, 1 | 6)
+            || !matches!(channels, 2 | 3)
+        {
+            return None;
+        }
+        let texel_count = (0..levels)
+            .map(|level| (size >> level) as u64)
+            .try_fold(0u64, |count, level_size| {
+                count.checked_add(level_size.checked_mul(level_size)?)
+            })?;
+        let expected = texel_count
+            .checked_mul(u64::from(layers * channels))?
+            .checked_mul(4)?;
+        if texels.len() as u64 != expected {
+            return None;
+        }
+        let data = texels
+            .chunks_exact(4)
+            .map(|texel| f32::from_le_bytes(texel.try_into().unwrap()))
+            .collect();
+        Some(Self {
+            size,
+            levels,
+            layers,
+            channels,
+            data,
+        })
+    }
+    /// Failures only mean the maps are computed again next time and are ignored
+    fn write(&self, path: &Path) {
+        let mut bytes = Vec::with_capacity(4 + 4 * 4 + self.data.len() * 4);
+        bytes.extend_from_slice(Self::MAGIC);
+        for field in [self.size, self.levels, self.layers, self.channels] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        for texel in self.data.iter() {
+            bytes.extend_from_slice(&texel.to_le_bytes());
+        }
+        let Some(dir) = path.parent() else {
+            return;
+        };
+        if fs::create_dir_all(dir).is_err() {
+            return;
+        }
+        // Written aside then renamed so a crash never leaves a truncated entry
+        let tmp = path.with_extension("tmp");
+        if fs::write(&tmp, &bytes).is_ok() {
+            let _ = fs::rename(&tmp, path);
+        }
+    }
+    /// Uploads every layer of every level to the targets of the bound texture
+    fn upload(&self, targets: &[GLenum]) {
+        let (format, internal_format) = if self.channels == 2 {
+            (gl::RG, gl::RG16F)
+        } else {
+            (gl::RGB, gl::RGB16F)
+        };
+        let mut offset = 0;
+        for level in 0..self.levels {
+            let level_size = (self.size >> level).max(1);
+            let len = (level_size * level_size * self.channels) as usize;
+            for target in targets.iter().take(self.layers as usize) {
+                unsafe {
+                    gl::TexImage2D(
+                        *target,
+                        level as GLint,
+                        internal_format as GLint,
+                        level_size as GLsizei,
+                        level_size as GLsizei,
+                        0,
+                        format,
+                        gl::FLOAT,
+                        self.data[offset..offset + len].as_ptr() as *const c_void,
+                    );
+                }
+                offset += len;
+            }
+        }
+    }
+    fn to_cube_map(&self) -> TextureCube {
+        let texture = empty_cube_map(self.size, self.levels > 1);
+        set_max_level(gl::TEXTURE_CUBE_MAP, self.levels);
+        let targets: Vec<GLenum> = (0..6)
+            .map(|face| gl::TEXTURE_CUBE_MAP_POSITIVE_X + face)
+            .collect();
+        self.upload(&targets);
+        texture
+    }
+    fn to_texture_2d(&self) -> Texture2D {
+        let texture = empty_texture_2d(self.size);
+        self.upload(&[gl::TEXTURE_2D]);
+        texture
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempDir;
+
+    /// Header fields followed by `texels` zeroed `f32`
+    fn write_raw(path: &Path, fields: [u32; 4], texels: usize) {
+        let mut bytes = CachedTexture::MAGIC.to_vec();
+        for field in fields {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+        bytes.resize(bytes.len() + texels * 4, 0);
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn cached_texture_round_trip() {
+        let dir = TempDir::new("ibl-round-trip-test");
+        let path = dir.path().join("round-trip.bin");
+        // 4x4 and 2x2 and 1x1 levels of 6 RGB faces
+        let data: Vec<f32> = (0..(16 + 4 + 1) * 6 * 3).map(|i| i as f32).collect();
+        let texture = CachedTexture {
+            size: 4,
+            levels: 3,
+            layers: 6,
+            channels: 3,
+            data: data.clone(),
+        };
+        texture.write(&path);
+        let read = CachedTexture::read(&path).unwrap();
+        assert_eq!(
+            (read.size, read.levels, read.layers, read.channels),
+            (4, 3, 6, 3)
+        );
+        assert_eq!(read.data, data);
+    }
+
+    #[test]
+    fn editing_an_include_changes_the_source_hash() {
+        let dir = TempDir::new("ibl-hash-test");
+        let shader = dir.path().join("shader.glsl");
+        let include = dir.path().join("include.glsl");
+        fs::write(&shader, "#include \"include.glsl\"\nvoid main() {}\n").unwrap();
+        fs::write(&include, "const int SAMPLES = 1;\n").unwrap();
+        let key = || {
+            let mut hash = Fnv1a::new();
+            hash_sources(&mut hash, &[&shader]);
+            hash.0
+        };
+
+        let before = key();
+        assert_eq!(key(), before);
+        fs::write(&include, "const int SAMPLES = 2;\n").unwrap();
+        assert_ne!(key(), before);
+    }
+
+    #[test]
+    fn cached_texture_rejects_bad_headers() {
+        let dir = TempDir::new("ibl-header-test");
+        let path = dir.path().join("bad-header.bin");
+        // Valid, to make sure the other cases fail for their header only
+        write_raw(&path, [4, 1, 1, 2], 16 * 2);
+        assert!(CachedTexture::read(&path).is_some());
+        // More levels than the mip chain has, including shifts past the width of `u32`
+        write_raw(&path, [4, 4, 1, 2], (16 + 4 + 1) * 2);
+        assert!(CachedTexture::read(&path).is_none());
+        write_raw(&path, [1, 40, 1, 2], 0);
+        assert!(CachedTexture::read(&path).is_none());
+        write_raw(&path, [4, 0, 1, 2], 0);
+        assert!(CachedTexture::read(&path).is_none());
+        write_raw(&path, [0, 1, 1, 2], 0);
+        assert!(CachedTexture::read(&path).is_none());
+        write_raw(&path, [4, 1, 1, 4], 16 * 4);
+        assert!(CachedTexture::read(&path).is_none());
+        write_raw(&path, [4, 1, 2, 2], 16 * 2 * 2);
+        assert!(CachedTexture::read(&path).is_none());
+        // Truncated
+        write_raw(&path, [4, 1, 1, 2], 16 * 2 - 1);
+        assert!(CachedTexture::read(&path).is_none());
+        // Sizes whose texel count doesn't fit in memory
+        write_raw(&path, [u32::MAX, 32, 6, 3], 0);
+        assert!(CachedTexture::read(&path).is_none());
+    }
+}
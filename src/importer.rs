@@ -0,0 +1,869 @@
+use crate::animation::{
+    add_bone_influence, normalize_bone_weights, AnimationClip, Channel, Interpolation, Keyframe,
+    Keyframes, Skeleton,
+};
+use crate::gltf_importer::GltfImporter;
+use crate::material::{Material, TextureSlot};
+use crate::mesh::{MeshData, Vertex};
+use crate::model::ModelError;
+use crate::morph::MorphTarget;
+use crate::scene::{NodeId, Transform};
+use russimp::material::{PropertyTypeInfo, TextureType};
+use russimp::node::Node;
+use russimp::scene::{PostProcess, Scene};
+use std::path::Path;
+
+/// Processing applied to the meshes while importing, mapped to the closest option of each backend
+#[derive(Clone, Debug)]
+pub struct ImportOptions {
+    /// Split polygons into triangles
+    pub triangulate: bool,
+    /// Flip the V texture coordinate, for images stored top row first
+    pub flip_uvs: bool,
+    /// Compute smooth normals for meshes that have none
+    pub generate_normals: bool,
+    /// Compute tangents and bitangents for meshes with texture coordinates but no tangents
+    pub calc_tangents: bool,
+    /// Merge vertices sharing every attribute, so they are indexed once
+    pub join_identical_vertices: bool,
+}
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            triangulate: true,
+            flip_uvs: false,
+            generate_normals: true,
+            calc_tangents: true,
+            join_identical_vertices: true,
+        }
+    }
+}
+
+/// Everything read from a model file, before anything is uploaded to the GPU
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModelData {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<MaterialData>,
+    /// Images stored inside the model file, referenced by `TextureSource::Embedded`
+    pub images: Vec<ImageData>,
+    /// Parents come before their children, so the nodes keep their index once added to a
+    /// `Scene` in order: `NodeId(i)` is `nodes[i]`
+    pub nodes: Vec<NodeData>,
+    /// Nodes without a parent, indices into `nodes`
+    pub roots: Vec<usize>,
+    /// Referenced by `MeshData::skin`
+    pub skins: Vec<Skeleton>,
+    pub animations: Vec<AnimationClip>,
+}
+
+/// Material as read from a file, textures aren't loaded yet
+pub type MaterialData = Material<TextureSource>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TextureSource {
+    /// As written in the model file, relative to the model unless absolute. Resolved when the
+    /// texture is loaded.
+    File(String),
+    /// Index into `ModelData::images`
+    Embedded(usize),
+}
+
+/// Decoded pixels, RGBA with 8 bits per channel, top row first
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeData {
+    pub name: String,
+    /// Relative to the parent node
+    pub transform: Transform,
+    /// Indices into `ModelData::meshes`
+    pub meshes: Vec<usize>,
+    /// Indices into `ModelData::nodes`
+    pub children: Vec<usize>,
+}
+
+/// Reads a model file into CPU-side data
+pub trait ModelImporter {
+    fn import(&self, path: &Path, options: &ImportOptions) -> Result<ModelData, ModelError>;
+}
+
+/// Picks the importer for a file from its extension
+pub fn importer_for(path: &Path) -> Result<Box<dyn ModelImporter>, ModelError> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("obj") => Ok(Box::new(ObjImporter)),
+        Some("gltf" | "glb") => Ok(Box::new(GltfImporter)),
+        Some("fbx" | "dae" | "3ds" | "blend") => Ok(Box::new(AssimpImporter)),
+        _ => Err(ModelError::UnsupportedFormat(path.to_path_buf())),
+    }
+}
+
+/// Imports a model with the importer matching its extension
+pub fn import(path: &Path, options: &ImportOptions) -> Result<ModelData, ModelError> {
+    importer_for(path)?.import(path, options)
+}
+
+/// Wavefront OBJ files through tobj
+pub struct ObjImporter;
+impl ModelImporter for ObjImporter {
+    fn import(&self, path: &Path, options: &ImportOptions) -> Result<ModelData, ModelError> {
+        // A single index is required for the GPU, otherwise normals and texture coordinates have
+        // their own indices. It also joins identical vertices, tobj can't keep them apart.
+        let load_options = tobj::LoadOptions {
+            triangulate: options.triangulate,
+            ..tobj::GPU_LOAD_OPTIONS
+        };
+        let (models, materials) =
+            tobj::load_obj(path, &load_options).map_err(|error| ModelError::Parse {
+                path: path.to_path_buf(),
+                message: error.to_string(),
+            })?;
+        let materials = materials.map_err(|error| ModelError::MaterialLibrary {
+            path: path.to_path_buf(),
+            error,
+        })?;
+
+        let mut data = ModelData {
+            materials: materials.iter().map(convert_obj_material).collect(),
+            ..ModelData::default()
+        };
+        // OBJ has no hierarchy, every object becomes a root node
+        for model in models {
+            let mut mesh = model.mesh;
+            assert_eq!(mesh.positions.len() % 3, 0);
+            let vertices_count = mesh.positions.len() / 3;
+            let has_normals = mesh.normals.len() == mesh.positions.len();
+            let has_tex_coords = mesh.texcoords.len() / 2 == vertices_count;
+
+            let (p, n, t) = (&mesh.positions, &mesh.normals, &mesh.texcoords);
+            let mut vertices: Vec<Vertex> = (0..vertices_count)
+                .map(|i| Vertex {
+                    position: glm::vec3(p[i * 3], p[i * 3 + 1], p[i * 3 + 2]),
+                    normal: if has_normals {
+                        glm::vec3(n[i * 3], n[i * 3 + 1], n[i * 3 + 2])
+                    } else {
+                        glm::Vec3::zeros()
+                    },
+                    tex_coords: if has_tex_coords {
+                        glm::vec2(t[i * 2], t[i * 2 + 1])
+                    } else {
+                        glm::Vec2::zeros()
+                    },
+                    ..Vertex::default()
+                })
+                .collect();
+            if options.flip_uvs {
+                flip_uvs(&mut vertices);
+            }
+            if !has_normals && options.generate_normals {
+                generate_normals(&mut vertices, &mesh.indices);
+            }
+            // OBJ has no tangents
+            if has_tex_coords && options.calc_tangents {
+                generate_tangents(&mut vertices, &mut mesh.indices);
+            }
+
+            data.roots.push(data.nodes.len());
+            data.nodes.push(NodeData {
+                name: model.name.clone(),
+                transform: Transform::default(),
+                meshes: vec![data.meshes.len()],
+                children: vec![],
+            });
+            data.meshes.push(MeshData {
+                name: model.name,
+                vertices,
+                indices: mesh.indices,
+                material: mesh.material_id.filter(|id| *id < materials.len()),
+                skin: None,
+                morph_targets: vec![],
+                morph_weights: vec![],
+            });
+        }
+        Ok(data)
+    }
+}
+
+fn convert_obj_material(material: &tobj::Material) -> MaterialData {
+    let mut data = MaterialData {
+        name: material.name.clone(),
+        ..MaterialData::default()
+    };
+    if let Some(diffuse) = material.diffuse {
+        data.diffuse = diffuse.into();
+    }
+    if let Some(specular) = material.specular {
+        data.specular = specular.into();
+    }
+    if let Some(shininess) = material.shininess {
+        data.shininess = shininess;
+    }
+    if let Some(dissolve) = material.dissolve {
+        data.opacity = dissolve;
+    }
+    let unknown = |key: &str| material.unknown_param.get(key).cloned();
+    let factor = |key: &str| unknown(key).and_then(|value| value.trim().parse().ok());
+    // `Pm` and `Pr` from the PBR extension of MTL, otherwise a dielectric as shiny as `Ns`
+    data.metallic = factor("Pm").unwrap_or(0.0);
+    data.roughness = factor("Pr").unwrap_or_else(|| roughness_from_shininess(data.shininess));
+    // Multiplied with `map_Ke`, which makes no sense without it
+    let emissive: Vec<f32> = unknown("Ke")
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|value| value.parse().ok())
+        .collect();
+    if let [r, g, b] = emissive[..] {
+        data.emissive = glm::vec3(r, g, b);
+    } else if unknown("map_Ke").is_some() {
+        data.emissive = glm::vec3(1.0, 1.0, 1.0);
+    }
+    let textures = [
+        (TextureSlot::Diffuse, material.diffuse_texture.clone()),
+        (TextureSlot::Specular, material.specular_texture.clone()),
+        // tobj reads `map_Bump` and `bump` but not `norm`
+        (
+            TextureSlot::Normal,
+            material.normal_texture.clone().or_else(|| unknown("norm")),
+        ),
+        (TextureSlot::Opacity, material.dissolve_texture.clone()),
+        (TextureSlot::Emissive, unknown("map_Ke")),
+    ];
+    for (slot, texture) in textures {
+        if let Some(texture) = texture {
+            data.set_texture(slot, TextureSource::File(obj_texture_file(&texture)));
+        }
+    }
+    data
+}
+
+/// File name of an MTL texture statement, without its options such as `-bm 0.5`. File names
+/// with spaces are only kept whole when there are no options.
+fn obj_texture_file(statement: &str) -> String {
+    let statement = statement.trim();
+    if statement.starts_with('-') {
+        statement
+            .split_whitespace()
+            .last()
+            .unwrap_or_default()
+            .to_string()
+    } else {
+        statement.to_string()
+    }
+}
+
+/// FBX, Collada, 3DS and Blender files through assimp
+pub struct AssimpImporter;
+impl ModelImporter for AssimpImporter {
+    fn import(&self, path: &Path, options: &ImportOptions) -> Result<ModelData, ModelError> {
+        let mut steps = vec![];
+        if options.triangulate {
+            steps.push(PostProcess::Triangulate);
+        }
+        if options.flip_uvs {
+            steps.push(PostProcess::FlipUVs);
+        }
+        if options.generate_normals {
+            steps.push(PostProcess::GenerateSmoothNormals);
+        }
+        if options.calc_tangents {
+            steps.push(PostProcess::CalculateTangentSpace);
+        }
+        if options.join_identical_vertices {
+            steps.push(PostProcess::JoinIdenticalVertices);
+        }
+        let scene = Scene::from_file(&path.to_string_lossy(), steps).map_err(|error| {
+            ModelError::Parse {
+                path: path.to_path_buf(),
+                message: error.to_string(),
+            }
+        })?;
+
+        let mut data = ModelData {
+            materials: scene
+                .materials
+                .iter()
+                .map(convert_assimp_material)
+                .collect(),
+            ..ModelData::default()
+        };
+        if let Some(ref root) = scene.root {
+            let root = convert_assimp_node(root, &mut data.nodes);
+            data.roots.push(root);
+        }
+        // Bones and animation channels refer to nodes by name
+        let find_node = |name: &str| data.nodes.iter().position(|node| node.name == name);
+        let mut skins = vec![];
+        let mut meshes = vec![];
+        for mesh in scene.meshes.iter() {
+            let mut data = convert_assimp_mesh(mesh);
+            data.material = data.material.filter(|id| *id < scene.materials.len());
+            if !mesh.bones.is_empty() {
+                data.skin = Some(skins.len());
+                skins.push(convert_assimp_bones(
+                    path,
+                    mesh,
+                    &mut data.vertices,
+                    find_node,
+                )?);
+            }
+            meshes.push(data);
+        }
+        let animations = scene
+            .animations
+            .iter()
+            .map(|animation| convert_assimp_animation(animation, find_node))
+            .collect();
+        data.meshes = meshes;
+        data.skins = skins;
+        data.animations = animations;
+        Ok(data)
+    }
+}
+
+/// Adds `node` and its children to `nodes`, returning the index of `node`
+fn convert_assimp_node(node: &Node, nodes: &mut Vec<NodeData>) -> usize {
+    let index = nodes.len();
+    nodes.push(NodeData {
+        name: node.name.clone(),
+        transform: Transform::from_matrix(&assimp_matrix(&node.transformation)),
+        meshes: node.meshes.iter().map(|mesh| *mesh as usize).collect(),
+        children: vec![],
+    });
+    for child in node.children.borrow().iter() {
+        let child = convert_assimp_node(child, nodes);
+        nodes[index].children.push(child);
+    }
+    index
+}
+
+fn convert_assimp_mesh(mesh: &russimp::mesh::Mesh) -> MeshData {
+    let tex_coords = mesh
+        .texture_coords
+        .first()
+        .and_then(|coords| coords.as_ref());
+    let vertices = (0..mesh.vertices.len())
+        .map(|i| {
+            let position = mesh.vertices[i];
+            let normal = mesh
+                .normals
+                .get(i)
+                .map_or(glm::Vec3::zeros(), |n| glm::vec3(n.x, n.y, n.z));
+            let tex_coords = tex_coords
+                .and_then(|coords| coords.get(i))
+                .map_or(glm::Vec2::zeros(), |t| glm::vec2(t.x, t.y));
+            // Filled by `PostProcess::CalculateTangentSpace`
+            let vector = |vectors: &[russimp::Vector3D]| {
+                vectors
+                    .get(i)
+                    .map_or(glm::Vec3::zeros(), |v| glm::vec3(v.x, v.y, v.z))
+            };
+            Vertex {
+                position: glm::vec3(position.x, position.y, position.z),
+                normal,
+                tex_coords,
+                tangent: vector(&mesh.tangents),
+                bitangent: vector(&mesh.bitangents),
+                ..Vertex::default()
+            }
+        })
+        .collect();
+    let indices = mesh
+        .faces
+        .iter()
+        .flat_map(|face| face.0.iter().copied())
+        .collect();
+    let (morph_targets, morph_weights) = mesh
+        .anim_meshes
+        .iter()
+        .filter_map(|anim_mesh| convert_assimp_anim_mesh(mesh, anim_mesh))
+        .unzip();
+
+    MeshData {
+        name: mesh.name.clone(),
+        vertices,
+        indices,
+        material: Some(mesh.material_index as usize),
+        skin: None,
+        morph_targets,
+        morph_weights,
+    }
+}
+
+/// Anim meshes store the morphed vertices, they are turned into deltas from `mesh`. Anim meshes
+/// with a different vertex count are skipped.
+fn convert_assimp_anim_mesh(
+    mesh: &russimp::mesh::Mesh,
+    anim_mesh: &russimp::mesh::AnimMesh,
+) -> Option<(MorphTarget, f32)> {
+    if anim_mesh.vertices.len() != mesh.vertices.len() {
+        return None;
+    }
+    let deltas = |morphed: &[russimp::Vector3D], base: &[russimp::Vector3D]| {
+        morphed
+            .iter()
+            .zip(base.iter())
+            .map(|(m, b)| glm::vec3(m.x - b.x, m.y - b.y, m.z - b.z))
+            .collect()
+    };
+    let normal_deltas = if anim_mesh.normals.len() == mesh.normals.len() {
+        deltas(&anim_mesh.normals, &mesh.normals)
+    } else {
+        vec![]
+    };
+    let target = MorphTarget {
+        name: anim_mesh.name.clone(),
+        position_deltas: deltas(&anim_mesh.vertices, &mesh.vertices),
+        normal_deltas,
+    };
+    Some((target, anim_mesh.weight))
+}
+
+/// Fills the bone slots of `vertices` and returns the skeleton their ids refer to. Every bone
+/// must have a node of the same name.
+fn convert_assimp_bones(
+    path: &Path,
+    mesh: &russimp::mesh::Mesh,
+    vertices: &mut [Vertex],
+    find_node: impl Fn(&str) -> Option<usize>,
+) -> Result<Skeleton, ModelError> {
+    let mut skeleton = Skeleton::default();
+    for (id, bone) in mesh.bones.iter().enumerate() {
+        let node = find_node(&bone.name).ok_or_else(|| ModelError::Parse {
+            path: path.to_path_buf(),
+            message: format!("bone {} has no node", bone.name),
+        })?;
+        skeleton.joints.push(NodeId(node));
+        skeleton
+            .inverse_bind_matrices
+            .push(assimp_matrix(&bone.offset_matrix));
+        for weight in bone.weights.iter() {
+            if let Some(vertex) = vertices.get_mut(weight.vertex_id as usize) {
+                add_bone_influence(
+                    &mut vertex.bone_ids,
+                    &mut vertex.bone_weights,
+                    id as u32,
+                    weight.weight,
+                );
+            }
+        }
+    }
+    for vertex in vertices.iter_mut() {
+        normalize_bone_weights(&mut vertex.bone_weights);
+    }
+    Ok(skeleton)
+}
+
+fn convert_assimp_animation(
+    animation: &russimp::animation::Animation,
+    find_node: impl Fn(&str) -> Option<usize>,
+) -> AnimationClip {
+    // Times are in ticks, the rate is often left out
+    let ticks_per_second = if animation.ticks_per_second > 0.0 {
+        animation.ticks_per_second
+    } else {
+        25.0
+    };
+    let seconds = |ticks: f64| (ticks / ticks_per_second) as f32;
+
+    let mut channels = vec![];
+    for channel in animation.channels.iter() {
+        let Some(node) = find_node(&channel.name) else {
+            continue;
+        };
+        let vector_keys = |keys: &[russimp::animation::VectorKey]| {
+            keys.iter()
+                .map(|key| Keyframe {
+                    time: seconds(key.time),
+                    value: glm::vec3(key.value.x, key.value.y, key.value.z),
+                })
+                .collect()
+        };
+        let rotation_keys = channel
+            .rotation_keys
+            .iter()
+            .map(|key| Keyframe {
+                time: seconds(key.time),
+                value: glm::quat(key.value.x, key.value.y, key.value.z, key.value.w),
+            })
+            .collect();
+        for keyframes in [
+            Keyframes::Translation(vector_keys(&channel.position_keys)),
+            Keyframes::Rotation(rotation_keys),
+            Keyframes::Scale(vector_keys(&channel.scaling_keys)),
+        ] {
+            channels.push(Channel {
+                node: NodeId(node),
+                keyframes,
+                interpolation: Interpolation::Linear,
+            });
+        }
+    }
+    AnimationClip {
+        name: animation.name.clone(),
+        duration: seconds(animation.duration),
+        channels,
+    }
+}
+
+/// Assimp matrices are row major, as are the arguments of `glm::mat4`
+fn assimp_matrix(m: &russimp::Matrix4x4) -> glm::Mat4 {
+    glm::mat4(
+        m.a1, m.a2, m.a3, m.a4, m.b1, m.b2, m.b3, m.b4, m.c1, m.c2, m.c3, m.c4, m.d1, m.d2, m.d3,
+        m.d4,
+    )
+}
+
+fn convert_assimp_material(material: &russimp::material::Material) -> MaterialData {
+    let mut data = MaterialData::default();
+    let (mut metallic, mut roughness) = (None, None);
+    for property in material.properties.iter() {
+        match (&property.data, property.key.as_str()) {
+            (PropertyTypeInfo::String(value), "?mat.name") => data.name = value.clone(),
+            (PropertyTypeInfo::String(value), "$tex.file") => {
+                let slot = match property.semantic {
+                    TextureType::Diffuse | TextureType::BaseColor => TextureSlot::Diffuse,
+                    TextureType::Specular => TextureSlot::Specular,
+                    // OBJ `map_Bump` is read as a height map
+                    TextureType::Normals | TextureType::NormalCamera | TextureType::Height => {
+                        TextureSlot::Normal
+                    }
+                    TextureType::Emissive | TextureType::EmissionColor => TextureSlot::Emissive,
+                    TextureType::AmbientOcclusion | TextureType::LightMap => {
+                        TextureSlot::AmbientOcclusion
+                    }
+                    TextureType::Opacity => TextureSlot::Opacity,
+                    _ => continue,
+                };
+                data.set_texture(slot, TextureSource::File(value.clone()));
+            }
+            (PropertyTypeInfo::FloatArray(value), key) => {
+                let color = || glm::vec3(value[0], value[1], value[2]);
+                match key {
+                    "$clr.diffuse" | "$clr.base" if value.len() >= 3 => data.diffuse = color(),
+                    "$clr.specular" if value.len() >= 3 => data.specular = color(),
+                    "$clr.emissive" if value.len() >= 3 => data.emissive = color(),
+                    "$mat.shininess" if !value.is_empty() => data.shininess = value[0],
+                    "$mat.opacity" if !value.is_empty() => data.opacity = value[0],
+                    "$mat.metallicFactor" if !value.is_empty() => metallic = Some(value[0]),
+                    "$mat.roughnessFactor" if !value.is_empty() => roughness = Some(value[0]),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    // Formats without PBR factors describe Phong materials
+    data.metallic = metallic.unwrap_or(0.0);
+    data.roughness = roughness.unwrap_or_else(|| roughness_from_shininess(data.shininess));
+    data
+}
+
+/// Roughness with about the same highlight as a Blinn-Phong specular exponent
+fn roughness_from_shininess(shininess: f32) -> f32 {
+    (2.0 / (shininess.max(0.0) + 2.0)).sqrt()
+}
+
+/// Also mirrors the bitangents, which follow V
+pub fn flip_uvs(vertices: &mut [Vertex]) {
+    for vertex in vertices.iter_mut() {
+        vertex.tex_coords.y = 1.0 - vertex.tex_coords.y;
+        vertex.bitangent = -vertex.bitangent;
+    }
+}
+
+/// Smooth normals averaged from the faces around each vertex, weighted by their area
+pub fn generate_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let mut normals = vec![glm::Vec3::zeros(); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+        // Not normalized, its length is twice the area of the triangle
+        let normal = glm::cross(
+            &(vertices[b].position - vertices[a].position),
+            &(vertices[c].position - vertices[a].position),
+        );
+        normals[a] += normal;
+        normals[b] += normal;
+        normals[c] += normal;
+    }
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        vertex.normal = if normal == glm::Vec3::zeros() {
+            normal
+        } else {
+            normal.normalize()
+        };
+    }
+}
+
+/// Tangents and bitangents following the MikkTSpace conventions: every triangle adds its
+/// UV-aligned frame weighted by its angle at the vertex, the tangent is then made perpendicular
+/// to the normal and the bitangent is `±cross(normal, tangent)`, negative for mirrored UVs.
+/// Normals must be set first. Vertices shared by mirrored and unmirrored triangles are split,
+/// the copies are appended and `indices` of the mirrored triangles point to them. Returns the
+/// vertex each copy was made from, in order, so per-vertex data stored elsewhere can follow.
+/// Vertices without UV gradient get an arbitrary frame.
+pub fn generate_tangents(vertices: &mut Vec<Vertex>, indices: &mut [u32]) -> Vec<usize> {
+    // UV-aligned frame of each triangle and whether its UVs are mirrored
+    let frames: Vec<Option<(glm::Vec3, glm::Vec3, bool)>> = indices
+        .chunks_exact(3)
+        .map(|triangle| {
+            let [v0, v1, v2] = [0, 1, 2].map(|corner| vertices[triangle[corner] as usize]);
+            let (edge1, edge2) = (v1.position - v0.position, v2.position - v0.position);
+            let (duv1, duv2) = (v1.tex_coords - v0.tex_coords, v2.tex_coords - v0.tex_coords);
+            let det = duv1.x * duv2.y - duv2.x * duv1.y;
+            if det.abs() <= f32::EPSILON {
+                return None;
+            }
+            let tangent = (edge1 * duv2.y - edge2 * duv1.y) / det;
+            let bitangent = (edge2 * duv1.x - edge1 * duv2.x) / det;
+            Some((
+                tangent.try_normalize(f32::EPSILON)?,
+                bitangent.try_normalize(f32::EPSILON)?,
+                det < 0.0,
+            ))
+        })
+        .collect();
+
+    // A vertex can only have one handedness, mirrored triangles get their own copy of the
+    // vertices they share with unmirrored ones
+    let mut used = vec![[false; 2]; vertices.len()];
+    for (triangle, frame) in indices.chunks_exact(3).zip(frames.iter()) {
+        if let Some((_, _, mirrored)) = frame {
+            for &index in triangle {
+                used[index as usize][*mirrored as usize] = true;
+            }
+        }
+    }
+    let mut copies: Vec<Option<u32>> = vec![None; vertices.len()];
+    let mut sources = vec![];
+    for (triangle, frame) in indices.chunks_exact_mut(3).zip(frames.iter()) {
+        if !matches!(frame, Some((_, _, true))) {
+            continue;
+        }
+        for index in triangle.iter_mut() {
+            let source = *index as usize;
+            if used[source] != [true, true] {
+                continue;
+            }
+            *index = *copies[source].get_or_insert_with(|| {
+                vertices.push(vertices[source]);
+                sources.push(source);
+                (vertices.len() - 1) as u32
+            });
+        }
+    }
+
+    let mut tangents = vec![glm::Vec3::zeros(); vertices.len()];
+    let mut bitangents = vec![glm::Vec3::zeros(); vertices.len()];
+    for (triangle, frame) in indices.chunks_exact(3).zip(frames) {
+        let Some((tangent, bitangent, _)) = frame else {
+            continue;
+        };
+        let corners = [
+            triangle[0] as usize,
+            triangle[1] as usize,
+            triangle[2] as usize,
+        ];
+        for corner in 0..3 {
+            let position = vertices[corners[corner]].position;
+            let to_next = vertices[corners[(corner + 1) % 3]].position - position;
+            let to_previous = vertices[corners[(corner + 2) % 3]].position - position;
+            let (Some(to_next), Some(to_previous)) = (
+                to_next.try_normalize(f32::EPSILON),
+                to_previous.try_normalize(f32::EPSILON),
+            ) else {
+                continue;
+            };
+            let angle = to_next.dot(&to_previous).clamp(-1.0, 1.0).acos();
+            tangents[corners[corner]] += tangent * angle;
+            bitangents[corners[corner]] += bitangent * angle;
+        }
+    }
+    for (i, vertex) in vertices.iter_mut().enumerate() {
+        let normal = vertex.normal;
+        let tangent = (tangents[i] - normal * normal.dot(&tangents[i]))
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(|| perpendicular(&normal));
+        let handedness = if normal.cross(&tangent).dot(&bitangents[i]) < 0.0 {
+            -1.0
+        } else {
+            1.0
+        };
+        vertex.tangent = tangent;
+        vertex.bitangent = normal.cross(&tangent) * handedness;
+    }
+    sources
+}
+
+/// Some unit vector perpendicular to `normal`
+fn perpendicular(normal: &glm::Vec3) -> glm::Vec3 {
+    let axis = if normal.x.abs() < 0.9 {
+        glm::Vec3::x()
+    } else {
+        glm::Vec3::y()
+    };
+    normal
+        .cross(&axis)
+        .try_normalize(f32::EPSILON)
+        .unwrap_or_else(glm::Vec3::x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: [f32; 2], tex_coords: [f32; 2]) -> Vertex {
+        Vertex {
+            position: glm::vec3(position[0], position[1], 0.0),
+            normal: glm::Vec3::z(),
+            tex_coords: tex_coords.into(),
+            ..Vertex::default()
+        }
+    }
+
+    /// Unit quad in the XY plane facing +Z, with the given UV at each corner
+    fn quad(tex_coords: [[f32; 2]; 4]) -> (Vec<Vertex>, Vec<u32>) {
+        let corners = [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]];
+        let vertices = corners
+            .into_iter()
+            .zip(tex_coords)
+            .map(|(position, tex_coords)| vertex(position, tex_coords))
+            .collect();
+        (vertices, vec![0, 1, 2, 0, 2, 3])
+    }
+
+    const QUAD_OBJ: &str = "\
+mtllib quad.mtl
+o quad
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+usemtl red
+f 1/1/1 2/2/1 3/3/1 4/4/1
+o triangle
+v 0 0 1
+v 2 0 1
+v 0 2 1
+usemtl blue
+f 5/1 6/2 7/4
+";
+    const QUAD_MTL: &str = "\
+newmtl blue
+Kd 0 0 1
+
+newmtl red
+Kd 1 0 0
+Ns 10
+";
+
+    #[test]
+    fn obj_with_material_library() {
+        let dir = std::env::temp_dir().join(format!("opengl-rust-obj-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("quad.obj"), QUAD_OBJ).unwrap();
+        std::fs::write(dir.join("quad.mtl"), QUAD_MTL).unwrap();
+        let data = import(&dir.join("quad.obj"), &ImportOptions::default()).unwrap();
+
+        assert_eq!(data.materials.len(), 2);
+        assert_eq!(data.materials[1].name, "red");
+        assert_eq!(data.materials[1].diffuse, glm::vec3(1.0, 0.0, 0.0));
+        assert_eq!(data.roots, vec![0, 1]);
+        assert_eq!(data.meshes.len(), 2);
+
+        let quad = &data.meshes[0];
+        assert_eq!(quad.name, "quad");
+        assert_eq!(quad.material, Some(1));
+        // The quad is triangulated, its corners are shared by both triangles
+        assert_eq!(quad.vertices.len(), 4);
+        assert_eq!(quad.indices.len(), 6);
+        let triangle = &data.meshes[1];
+        assert_eq!(triangle.material, Some(0));
+        assert_eq!(triangle.vertices.len(), 3);
+        assert_eq!(triangle.indices.len(), 3);
+
+        for mesh in data.meshes.iter() {
+            assert!(mesh
+                .indices
+                .iter()
+                .all(|&index| (index as usize) < mesh.vertices.len()));
+            for vertex in mesh.vertices.iter() {
+                // Read from the file for the quad, generated for the triangle
+                assert!((vertex.normal.norm() - 1.0).abs() < 1e-5);
+                assert!((vertex.normal - glm::Vec3::z()).norm() < 1e-5);
+                assert!((vertex.tangent.norm() - 1.0).abs() < 1e-5);
+            }
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn handedness(vertex: &Vertex) -> f32 {
+        vertex.normal.cross(&vertex.tangent).dot(&vertex.bitangent)
+    }
+
+    #[test]
+    fn planar_quad_tangent_follows_u() {
+        let (mut vertices, mut indices) = quad([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]]);
+        let copied = generate_tangents(&mut vertices, &mut indices);
+        assert!(copied.is_empty());
+        assert_eq!(vertices.len(), 4);
+        for vertex in vertices.iter() {
+            assert!((vertex.tangent - glm::Vec3::x()).norm() < 1e-5);
+            assert!((vertex.bitangent - glm::Vec3::y()).norm() < 1e-5);
+            assert!(handedness(vertex) > 0.0);
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_flip_the_bitangent() {
+        let (mut vertices, mut indices) = quad([[1.0, 0.0], [0.0, 0.0], [0.0, 1.0], [1.0, 1.0]]);
+        generate_tangents(&mut vertices, &mut indices);
+        for vertex in vertices.iter() {
+            assert!((vertex.tangent + glm::Vec3::x()).norm() < 1e-5);
+            // Still V, which now runs against `cross(normal, tangent)`
+            assert!((vertex.bitangent - glm::Vec3::y()).norm() < 1e-5);
+            assert!(handedness(vertex) < 0.0);
+        }
+    }
+
+    #[test]
+    fn degenerate_uvs_get_a_perpendicular_frame() {
+        let (mut vertices, mut indices) = quad([[0.5, 0.5]; 4]);
+        generate_tangents(&mut vertices, &mut indices);
+        for vertex in vertices.iter() {
+            assert!((vertex.tangent.norm() - 1.0).abs() < 1e-5);
+            assert!(vertex.tangent.dot(&vertex.normal).abs() < 1e-5);
+            assert!((vertex.bitangent - vertex.normal.cross(&vertex.tangent)).norm() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn vertices_shared_with_mirrored_triangles_are_split() {
+        // The first triangle is unmirrored, the second mirrored, they share vertices 0 and 2
+        let (mut vertices, mut indices) = quad([[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [2.0, 1.0]]);
+        let copied = generate_tangents(&mut vertices, &mut indices);
+        assert_eq!(copied, vec![0, 2]);
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(indices, vec![0, 1, 2, 4, 5, 3]);
+        for (i, vertex) in vertices.iter().enumerate() {
+            assert_eq!(handedness(vertex) > 0.0, i < 3, "vertex {}", i);
+        }
+        assert_eq!(vertices[4].position, vertices[0].position);
+        assert_eq!(vertices[5].tex_coords, vertices[2].tex_coords);
+    }
+}
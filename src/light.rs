@@ -0,0 +1,270 @@
+use crate::gl_objects::Buffer;
+use crate::shader::Shader;
+use crate::uniform::UniformError;
+use gl::types::*;
+use std::fmt;
+use std::mem::{size_of, size_of_val};
+
+/// Binding point of the `Lights` uniform block
+pub const LIGHTS_BINDING: GLuint = 0;
+
+/// Falloff of point and spot lights: `1 / (constant + linear * d + quadratic * d²)`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+impl Default for Attenuation {
+    /// Reaches about 50 units
+    fn default() -> Self {
+        Self {
+            constant: 1.0,
+            linear: 0.09,
+            quadratic: 0.032,
+        }
+    }
+}
+
+/// Colors are linear and multiplied by the intensity
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Light {
+    /// Infinitely far away, like the sun
+    Directional {
+        /// From the light towards the scene
+        direction: glm::Vec3,
+        color: glm::Vec3,
+        intensity: f32,
+    },
+    Point {
+        position: glm::Vec3,
+        color: glm::Vec3,
+        intensity: f32,
+        attenuation: Attenuation,
+    },
+    /// Full intensity inside the inner cone, fading out to nothing at the outer cone. Both
+    /// cutoffs can be equal for a hard edge.
+    Spot {
+        position: glm::Vec3,
+        direction: glm::Vec3,
+        color: glm::Vec3,
+        intensity: f32,
+        attenuation: Attenuation,
+        /// Half angles of the cones, in radians. The inner one can't be larger.
+        inner_cutoff: f32,
+        outer_cutoff: f32,
+    },
+}
+
+/// Layout of `Light` in the std140 `Lights` block of `lights.glsl`
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(C)]
+struct GpuLight {
+    /// `w` is the kind of light: 0 directional, 1 point, 2 spot
+    position: [f32; 4],
+    direction: [f32; 4],
+    /// `w` is unused, the intensity is premultiplied
+    color: [f32; 4],
+    /// Constant, linear and quadratic terms
+    attenuation: [f32; 4],
+    /// Cosines of the inner and outer cutoffs
+    cutoff: [f32; 4],
+}
+impl From<&Light> for GpuLight {
+    fn from(light: &Light) -> Self {
+        let vec4 = |v: &glm::Vec3, w: f32| [v.x, v.y, v.z, w];
+        let attenuation = |a: &Attenuation| [a.constant, a.linear, a.quadratic, 0.0];
+        match light {
+            Light::Directional {
+                direction,
+                color,
+                intensity,
+            } => Self {
+                position: [0.0; 4],
+                direction: vec4(&direction.normalize(), 0.0),
+                color: vec4(&(color * *intensity), 0.0),
+                ..Self::default()
+            },
+            Light::Point {
+                position,
+                color,
+                intensity,
+                attenuation: a,
+            } => Self {
+                position: vec4(position, 1.0),
+                color: vec4(&(color * *intensity), 0.0),
+                attenuation: attenuation(a),
+                ..Self::default()
+            },
+            Light::Spot {
+                position,
+                direction,
+                color,
+                intensity,
+                attenuation: a,
+                inner_cutoff,
+                outer_cutoff,
+            } => Self {
+                position: vec4(position, 2.0),
+                direction: vec4(&direction.normalize(), 0.0),
+                color: vec4(&(color * *intensity), 0.0),
+                attenuation: attenuation(a),
+                cutoff: [inner_cutoff.cos(), outer_cutoff.cos(), 0.0, 0.0],
+            },
+        }
+    }
+}
+// The block relies on every member being a vec4
+const _: () = assert!(size_of::<GpuLight>() == 5 * 4 * size_of::<f32>());
+
+#[derive(Debug)]
+pub enum LightError {
+    /// A list must have room for at least one light
+    NoCapacity,
+    /// The list already holds `max_lights` lights
+    Full {
+        max_lights: usize,
+    },
+    /// The inner cone of a spot light is wider than its outer cone
+    InvertedCutoffs {
+        inner_cutoff: f32,
+        outer_cutoff: f32,
+    },
+    /// The `Lights` block of a shader is larger than the buffer, the shader was built with a
+    /// bigger `MAX_LIGHTS` than the list
+    BlockTooLarge {
+        block_size: usize,
+        buffer_size: usize,
+    },
+    Uniform(UniformError),
+}
+impl fmt::Display for LightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LightError::NoCapacity => write!(f, "a light list needs room for a light"),
+            LightError::Full { max_lights } => {
+                write!(f, "can't add more than {} lights", max_lights)
+            }
+            LightError::InvertedCutoffs {
+                inner_cutoff,
+                outer_cutoff,
+            } => write!(
+                f,
+                "the inner cutoff of a spot light ({}) is larger than its outer cutoff ({})",
+                inner_cutoff, outer_cutoff
+            ),
+            LightError::BlockTooLarge {
+                block_size,
+                buffer_size,
+            } => write!(
+                f,
+                "the Lights block takes {} bytes but the buffer holds {}",
+                block_size, buffer_size
+            ),
+            LightError::Uniform(error) => write!(f, "{}", error),
+        }
+    }
+}
+impl std::error::Error for LightError {}
+impl From<UniformError> for LightError {
+    fn from(error: UniformError) -> Self {
+        LightError::Uniform(error)
+    }
+}
+
+/// Lights of the scene, uploaded to a uniform buffer read by the lit shaders. The buffer is
+/// sized for `max_lights`, which shaders must be built with as their `MAX_LIGHTS` define.
+pub struct LightList {
+    lights: Vec<Light>,
+    max_lights: usize,
+    buffer: Buffer,
+    /// In bytes
+    buffer_size: usize,
+}
+impl LightList {
+    pub fn new(max_lights: usize) -> Result<Self, LightError> {
+        // GLSL has no empty arrays
+        if max_lights == 0 {
+            return Err(LightError::NoCapacity);
+        }
+        let buffer = Buffer::new(gl::UNIFORM_BUFFER);
+        buffer.bind();
+        // Count, padded to a vec4, then the lights
+        let size = 4 + max_lights * size_of::<GpuLight>() / size_of::<f32>();
+        buffer.data(&vec![0f32; size], gl::DYNAMIC_DRAW);
+        unsafe { gl::BindBuffer(gl::UNIFORM_BUFFER, 0) };
+        Ok(Self {
+            lights: vec![],
+            max_lights,
+            buffer,
+            buffer_size: size * size_of::<f32>(),
+        })
+    }
+    pub fn max_lights(&self) -> usize {
+        self.max_lights
+    }
+    /// `MAX_LIGHTS` define for the shaders using the list
+    pub fn define(&self) -> (&'static str, String) {
+        ("MAX_LIGHTS", self.max_lights.to_string())
+    }
+    /// Returns the index of the light
+    pub fn add(&mut self, light: Light) -> Result<usize, LightError> {
+        if self.lights.len() >= self.max_lights {
+            return Err(LightError::Full {
+                max_lights: self.max_lights,
+            });
+        }
+        if let Light::Spot {
+            inner_cutoff,
+            outer_cutoff,
+            ..
+        } = light
+        {
+            if inner_cutoff > outer_cutoff {
+                return Err(LightError::InvertedCutoffs {
+                    inner_cutoff,
+                    outer_cutoff,
+                });
+            }
+        }
+        self.lights.push(light);
+        Ok(self.lights.len() - 1)
+    }
+    /// The lights after it move down one index
+    pub fn remove(&mut self, index: usize) -> Light {
+        self.lights.remove(index)
+    }
+    pub fn clear(&mut self) {
+        self.lights.clear();
+    }
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+    /// Changes are only seen by the shaders after `upload`
+    pub fn lights_mut(&mut self) -> &mut [Light] {
+        &mut self.lights
+    }
+    /// Writes the lights to the uniform buffer, once per frame after they changed
+    pub fn upload(&self) {
+        let count = [self.lights.len() as i32, 0, 0, 0];
+        let lights: Vec<GpuLight> = self.lights.iter().map(GpuLight::from).collect();
+        self.buffer.bind();
+        self.buffer.sub_data(0, &count);
+        self.buffer.sub_data(size_of_val(&count), &lights);
+        unsafe { gl::BindBuffer(gl::UNIFORM_BUFFER, 0) };
+    }
+    /// Binds the buffer to `LIGHTS_BINDING` and points the `Lights` block of `shader` at it.
+    /// Fails if the block would read past the end of the buffer.
+    pub fn bind(&self, shader: &Shader) -> Result<(), LightError> {
+        let block_size = shader.uniform_block_size("Lights")?;
+        if block_size > self.buffer_size {
+            return Err(LightError::BlockTooLarge {
+                block_size,
+                buffer_size: self.buffer_size,
+            });
+        }
+        unsafe { gl::BindBufferBase(gl::UNIFORM_BUFFER, LIGHTS_BINDING, self.buffer.id()) };
+        shader.bind_uniform_block("Lights", LIGHTS_BINDING)?;
+        Ok(())
+    }
+}
@@ -1,9 +1,10 @@
 #![macro_use]
-/// Get offset to struct member, similar to `offset_of` in C/C++
-/// From https://stackoverflow.com/questions/40310483/how-to-get-pointer-offset-in-bytes/40310851#40310851
+/// Get offset to struct member, similar to `offset_of` in C/C++.
+/// Forwards to `core::mem::offset_of!`, which is evaluated at compile time without touching
+/// any memory, so it can be used in constants.
 #[macro_export]
 macro_rules! offset_of {
     ($ty:ty, $field:ident) => {
-        &(*(std::ptr::null() as *const $ty)).$field as *const _ as usize
+        ::core::mem::offset_of!($ty, $field)
     };
 }
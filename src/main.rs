@@ -2,25 +2,54 @@ extern crate gl;
 extern crate nalgebra_glm as glm;
 extern crate russimp;
 
+mod animation;
 mod camera;
+mod framebuffer;
+pub mod gl_objects;
+mod gltf_importer;
+mod ibl;
+mod importer;
+mod light;
 pub mod macros;
+mod material;
 mod mesh;
 mod model;
+mod morph;
+mod postprocess;
+pub mod preprocessor;
+pub mod program_cache;
+mod scene;
 pub mod shader;
+pub mod shader_registry;
+mod shadow;
+mod texture;
+mod texture_cache;
+pub mod uniform;
 pub mod utils;
+pub mod vertex_layout;
 
 use crate::model::Model;
 use camera::Camera;
-use gl::types::*;
 use glfw::Context;
 use image::EncodableLayout;
-use std::mem::{size_of, size_of_val};
 use std::ops::Mul;
-use std::os::raw::c_void;
 use utils::{framebuffer_size_callback, process_input, process_mouse};
 
-use crate::shader::Shader;
-use crate::utils::{load_texture, ToCVoid};
+use crate::gl_objects::{Buffer, VertexArray};
+use crate::ibl::{Environment, IblSettings};
+use crate::light::{Attenuation, Light, LightList};
+use crate::material::{Material, TextureSlot};
+use crate::mesh::{PositionNormalTexVertex, Texture};
+use crate::postprocess::{PostEffect, PostProcessChain};
+use crate::program_cache::ProgramCache;
+use crate::scene::{Scene, Transform};
+use crate::shader::{Shader, ShaderBuilder};
+use crate::shader_registry::ShaderRegistry;
+use crate::shadow::{CascadedShadowMap, PointShadowMap, ShadowRenderer, ShadowSettings};
+use crate::texture::{load_texture_with, TextureDescriptor};
+use crate::vertex_layout::apply_layout;
+use std::path::Path;
+use std::rc::Rc;
 
 const SRC_WIDTH: u32 = 1280;
 const SRC_HEIGHT: u32 = 720;
@@ -54,145 +83,263 @@ fn main() {
     gl::load_with(|symbol| glfw.get_proc_address_raw(symbol));
     window.make_current();
 
-    let shader = Shader::new("src/shaders/vertex.glsl", "src/shaders/fragment.glsl");
-    let single_shader = Shader::new(
-        "src/shaders/vertex.glsl",
-        "src/shaders/fragment-single.glsl",
+    // Set SHADER_CACHE=0 to always compile from source
+    let program_cache = match std::env::var("SHADER_CACHE").as_deref() {
+        Ok("0") => ProgramCache::disabled(),
+        _ => ProgramCache::default(),
+    };
+    let mut lights = LightList::new(4).unwrap_or_else(|error| panic!("{}", error));
+    let (max_lights, max_lights_value) = lights.define();
+    let mut shaders = ShaderRegistry::new();
+    let shader_id = shaders.add(
+        ShaderBuilder::new()
+            .vertex("src/shaders/lit-vertex.glsl")
+            .fragment("src/shaders/lit-fragment.glsl")
+            .define(max_lights, &max_lights_value)
+            .define("SHADOWS", "1")
+            // Materials without a normal map, like the ones of the cubes, use the vertex normal
+            .define("NORMAL_MAP", "1")
+            // Tone mapping and gamma are done by the post-processing
+            .define("LINEAR_OUTPUT", "1")
+            .cache(program_cache.clone())
+            .build()
+            .unwrap_or_else(|error| panic!("{}", error)),
+    );
+    // Image-based lighting of the PBR shader, from an equirectangular HDR image. Set
+    // ENVIRONMENT to use another one, without it the ambient light is a constant color.
+    let environment_path = std::env::var("ENVIRONMENT")
+        .unwrap_or_else(|_| "assets/textures/environment.hdr".to_string());
+    let environment =
+        match Environment::from_equirect(Path::new(&environment_path), &IblSettings::default()) {
+            Ok(environment) => Some(environment),
+            Err(error) => {
+                println!("No image-based lighting: {}", error);
+                None
+            }
+        };
+    let mut pbr_builder = ShaderBuilder::new()
+        .vertex("src/shaders/lit-vertex.glsl")
+        .fragment("src/shaders/pbr-fragment.glsl")
+        .define(max_lights, &max_lights_value)
+        .define("SHADOWS", "1")
+        .define("NORMAL_MAP", "1")
+        .define("LINEAR_OUTPUT", "1")
+        .cache(program_cache.clone());
+    if environment.is_some() {
+        pbr_builder = pbr_builder.define("IBL", "1");
+    }
+    let pbr_shader_id = shaders.add(
+        pbr_builder
+            .build()
+            .unwrap_or_else(|error| panic!("{}", error)),
+    );
+    let skybox_shader_id = environment.as_ref().map(|_| {
+        shaders.add(
+            ShaderBuilder::new()
+                .vertex("src/shaders/skybox-vertex.glsl")
+                .fragment("src/shaders/skybox-fragment.glsl")
+                .define("LINEAR_OUTPUT", "1")
+                .cache(program_cache.clone())
+                .build()
+                .unwrap_or_else(|error| panic!("{}", error)),
+        )
+    });
+    // Models aren't drawn in this scene yet, the skinning shader is still built so that its
+    // errors show up at startup and on reload
+    let _skinning_shader_id = shaders.add(
+        ShaderBuilder::new()
+            .vertex("src/shaders/skinning.glsl")
+            .fragment("src/shaders/lit-fragment.glsl")
+            .define(max_lights, &max_lights_value)
+            .define("LINEAR_OUTPUT", "1")
+            .cache(program_cache.clone())
+            .build()
+            .unwrap_or_else(|error| panic!("{}", error)),
+    );
+    let single_shader_id = shaders.add(
+        ShaderBuilder::new()
+            .vertex("src/shaders/lit-vertex.glsl")
+            .fragment("src/shaders/fragment-single.glsl")
+            .cache(program_cache)
+            .build()
+            .unwrap_or_else(|error| panic!("{}", error)),
     );
 
     #[rustfmt::skip]
-        let plane_vertices: &[f32] = &[
-        // positions          // texture Coords (note we set these higher than 1 (together with GL_REPEAT as texture wrapping mode). this will cause the floor texture to repeat)
-        5.0, -0.5,  5.0,  2.0, 0.0,
-        -5.0, -0.5,  5.0,  0.0, 0.0,
-        -5.0, -0.5, -5.0,  0.0, 2.0,
-
-        5.0, -0.5,  5.0,  2.0, 0.0,
-        -5.0, -0.5, -5.0,  0.0, 2.0,
-        5.0, -0.5, -5.0,  2.0, 2.0
+    let plane_vertices: &[f32] = &[
+        // positions         // normals       // texture Coords (note we set these higher than 1 (together with GL_REPEAT as texture wrapping mode). this will cause the floor texture to repeat)
+         5.0, -0.5,  5.0,  0.0, 1.0, 0.0,  2.0, 0.0,
+        -5.0, -0.5,  5.0,  0.0, 1.0, 0.0,  0.0, 0.0,
+        -5.0, -0.5, -5.0,  0.0, 1.0, 0.0,  0.0, 2.0,
+
+         5.0, -0.5,  5.0,  0.0, 1.0, 0.0,  2.0, 0.0,
+        -5.0, -0.5, -5.0,  0.0, 1.0, 0.0,  0.0, 2.0,
+         5.0, -0.5, -5.0,  0.0, 1.0, 0.0,  2.0, 2.0
     ];
     #[rustfmt::skip]
-        let cube_vertices: &[f32] = &[
-// positions          // texture Coords
-        -0.5, -0.5, -0.5,  0.0, 0.0,
-        0.5, -0.5, -0.5,  1.0, 0.0,
-        0.5,  0.5, -0.5,  1.0, 1.0,
-        0.5,  0.5, -0.5,  1.0, 1.0,
-        -0.5,  0.5, -0.5,  0.0, 1.0,
-        -0.5, -0.5, -0.5,  0.0, 0.0,
-
-        -0.5, -0.5,  0.5,  0.0, 0.0,
-        0.5, -0.5,  0.5,  1.0, 0.0,
-        0.5,  0.5,  0.5,  1.0, 1.0,
-        0.5,  0.5,  0.5,  1.0, 1.0,
-        -0.5,  0.5,  0.5,  0.0, 1.0,
-        -0.5, -0.5,  0.5,  0.0, 0.0,
-
-        -0.5,  0.5,  0.5,  1.0, 0.0,
-        -0.5,  0.5, -0.5,  1.0, 1.0,
-        -0.5, -0.5, -0.5,  0.0, 1.0,
-        -0.5, -0.5, -0.5,  0.0, 1.0,
-        -0.5, -0.5,  0.5,  0.0, 0.0,
-        -0.5,  0.5,  0.5,  1.0, 0.0,
-
-        0.5,  0.5,  0.5,  1.0, 0.0,
-        0.5,  0.5, -0.5,  1.0, 1.0,
-        0.5, -0.5, -0.5,  0.0, 1.0,
-        0.5, -0.5, -0.5,  0.0, 1.0,
-        0.5, -0.5,  0.5,  0.0, 0.0,
-        0.5,  0.5,  0.5,  1.0, 0.0,
-
-        -0.5, -0.5, -0.5,  0.0, 1.0,
-        0.5, -0.5, -0.5,  1.0, 1.0,
-        0.5, -0.5,  0.5,  1.0, 0.0,
-        0.5, -0.5,  0.5,  1.0, 0.0,
-        -0.5, -0.5,  0.5,  0.0, 0.0,
-        -0.5, -0.5, -0.5,  0.0, 1.0,
-
-        -0.5,  0.5, -0.5,  0.0, 1.0,
-        0.5,  0.5, -0.5,  1.0, 1.0,
-        0.5,  0.5,  0.5,  1.0, 0.0,
-        0.5,  0.5,  0.5,  1.0, 0.0,
-        -0.5,  0.5,  0.5,  0.0, 0.0,
-        -0.5,  0.5, -0.5,  0.0, 1.0
+    let cube_vertices: &[f32] = &[
+        // positions          // normals           // texture Coords
+        -0.5, -0.5, -0.5,  0.0,  0.0, -1.0,  0.0, 0.0,
+         0.5, -0.5, -0.5,  0.0,  0.0, -1.0,  1.0, 0.0,
+         0.5,  0.5, -0.5,  0.0,  0.0, -1.0,  1.0, 1.0,
+         0.5,  0.5, -0.5,  0.0,  0.0, -1.0,  1.0, 1.0,
+        -0.5,  0.5, -0.5,  0.0,  0.0, -1.0,  0.0, 1.0,
+        -0.5, -0.5, -0.5,  0.0,  0.0, -1.0,  0.0, 0.0,
+
+        -0.5, -0.5,  0.5,  0.0,  0.0,  1.0,  0.0, 0.0,
+         0.5, -0.5,  0.5,  0.0,  0.0,  1.0,  1.0, 0.0,
+         0.5,  0.5,  0.5,  0.0,  0.0,  1.0,  1.0, 1.0,
+         0.5,  0.5,  0.5,  0.0,  0.0,  1.0,  1.0, 1.0,
+        -0.5,  0.5,  0.5,  0.0,  0.0,  1.0,  0.0, 1.0,
+        -0.5, -0.5,  0.5,  0.0,  0.0,  1.0,  0.0, 0.0,
+
+        -0.5,  0.5,  0.5, -1.0,  0.0,  0.0,  1.0, 0.0,
+        -0.5,  0.5, -0.5, -1.0,  0.0,  0.0,  1.0, 1.0,
+        -0.5, -0.5, -0.5, -1.0,  0.0,  0.0,  0.0, 1.0,
+        -0.5, -0.5, -0.5, -1.0,  0.0,  0.0,  0.0, 1.0,
+        -0.5, -0.5,  0.5, -1.0,  0.0,  0.0,  0.0, 0.0,
+        -0.5,  0.5,  0.5, -1.0,  0.0,  0.0,  1.0, 0.0,
+
+         0.5,  0.5,  0.5,  1.0,  0.0,  0.0,  1.0, 0.0,
+         0.5,  0.5, -0.5,  1.0,  0.0,  0.0,  1.0, 1.0,
+         0.5, -0.5, -0.5,  1.0,  0.0,  0.0,  0.0, 1.0,
+         0.5, -0.5, -0.5,  1.0,  0.0,  0.0,  0.0, 1.0,
+         0.5, -0.5,  0.5,  1.0,  0.0,  0.0,  0.0, 0.0,
+         0.5,  0.5,  0.5,  1.0,  0.0,  0.0,  1.0, 0.0,
+
+        -0.5, -0.5, -0.5,  0.0, -1.0,  0.0,  0.0, 1.0,
+         0.5, -0.5, -0.5,  0.0, -1.0,  0.0,  1.0, 1.0,
+         0.5, -0.5,  0.5,  0.0, -1.0,  0.0,  1.0, 0.0,
+         0.5, -0.5,  0.5,  0.0, -1.0,  0.0,  1.0, 0.0,
+        -0.5, -0.5,  0.5,  0.0, -1.0,  0.0,  0.0, 0.0,
+        -0.5, -0.5, -0.5,  0.0, -1.0,  0.0,  0.0, 1.0,
+
+        -0.5,  0.5, -0.5,  0.0,  1.0,  0.0,  0.0, 1.0,
+         0.5,  0.5, -0.5,  0.0,  1.0,  0.0,  1.0, 1.0,
+         0.5,  0.5,  0.5,  0.0,  1.0,  0.0,  1.0, 0.0,
+         0.5,  0.5,  0.5,  0.0,  1.0,  0.0,  1.0, 0.0,
+        -0.5,  0.5,  0.5,  0.0,  1.0,  0.0,  0.0, 0.0,
+        -0.5,  0.5, -0.5,  0.0,  1.0,  0.0,  0.0, 1.0
     ];
-    let mut cubeVAO: u32 = 0;
-    let mut cubeVBO: u32 = 0;
-    let mut planeVAO: u32 = 0;
-    let mut planeVBO: u32 = 0;
+    let cube_vao = VertexArray::new();
+    let plane_vao = VertexArray::new();
 
     unsafe {
         // Configure global opengl state
         gl::Enable(gl::DEPTH_TEST);
         gl::Enable(gl::STENCIL_TEST);
+    }
 
-        gl::GenVertexArrays(1, &mut cubeVAO);
-        gl::GenBuffers(1, &mut cubeVBO);
-        gl::BindVertexArray(cubeVAO);
-        gl::BindBuffer(gl::ARRAY_BUFFER, cubeVBO);
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            size_of_val(cube_vertices) as GLsizeiptr,
-            cube_vertices.as_ptr() as *const c_void,
-            gl::STATIC_DRAW,
-        );
-        gl::EnableVertexAttribArray(0);
-        gl::VertexAttribPointer(
-            0,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            (5 * size_of::<f32>()) as GLsizei,
-            ToCVoid(0).into(),
-        );
-        gl::EnableVertexAttribArray(1);
-        gl::VertexAttribPointer(
-            1,
-            2,
-            gl::FLOAT,
-            gl::FALSE,
-            (5 * size_of::<f32>()) as GLsizei,
-            ToCVoid(3 * size_of::<f32>()).into(),
-        );
+    // The vertex arrays are flat floats laid out like `PositionNormalTexVertex`
+    cube_vao.bind();
+    let _cube_vbo = Buffer::with_data(gl::ARRAY_BUFFER, cube_vertices, gl::STATIC_DRAW);
+    apply_layout::<PositionNormalTexVertex>();
 
-        gl::GenVertexArrays(1, &mut planeVAO);
-        gl::GenBuffers(1, &mut planeVBO);
-        gl::BindVertexArray(planeVAO);
-        gl::BindBuffer(gl::ARRAY_BUFFER, planeVBO);
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            size_of_val(plane_vertices) as GLsizeiptr,
-            plane_vertices.as_ptr() as *const c_void,
-            gl::STATIC_DRAW,
-        );
-        gl::EnableVertexAttribArray(0);
-        gl::VertexAttribPointer(
-            0,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            (5 * size_of::<f32>()) as GLsizei,
-            ToCVoid(0).into(),
-        );
-        gl::EnableVertexAttribArray(1);
-        gl::VertexAttribPointer(
-            1,
-            2,
-            gl::FLOAT,
-            gl::FALSE,
-            (5 * size_of::<f32>()) as GLsizei,
-            ToCVoid(3 * size_of::<f32>()).into(),
-        );
-    }
+    plane_vao.bind();
+    let _plane_vbo = Buffer::with_data(gl::ARRAY_BUFFER, plane_vertices, gl::STATIC_DRAW);
+    apply_layout::<PositionNormalTexVertex>();
 
-    // Load textures
-    let cube_texture = load_texture("assets/textures/marble.jpg");
-    let floor_texture = load_texture("assets/textures/metal.png");
+    // Materials
+    // -------
+    let textured_material = |path: &str, metallic: f32, roughness: f32| {
+        let descriptor = TextureDescriptor {
+            srgb: true,
+            ..TextureDescriptor::default()
+        };
+        let texture = load_texture_with(Path::new(path), &descriptor)
+            .unwrap_or_else(|error| panic!("{}", error));
+        let mut material = Material {
+            name: path.to_string(),
+            specular: glm::vec3(0.5, 0.5, 0.5),
+            metallic,
+            roughness,
+            ..Material::default()
+        };
+        material.set_texture(
+            TextureSlot::Diffuse,
+            Texture {
+                texture: Rc::new(texture),
+                path: path.into(),
+            },
+        );
+        material
+    };
+    let cube_material = textured_material("assets/textures/marble.jpg", 0.0, 0.3);
+    let floor_material = textured_material("assets/textures/metal.png", 0.9, 0.5);
 
-    // Shader config
+    // Lights and shadows
+    // -------
+    lights
+        .add(Light::Directional {
+            direction: glm::vec3(-0.4, -1.0, -0.3),
+            color: glm::vec3(1.0, 0.95, 0.9),
+            intensity: 0.8,
+        })
+        .expect("the light list has room for the directional light");
+    let point_light = lights
+        .add(Light::Point {
+            position: glm::vec3(0.0, 1.5, 1.5),
+            color: glm::vec3(1.0, 0.6, 0.3),
+            intensity: 1.5,
+            attenuation: Attenuation::default(),
+        })
+        .expect("the light list has room for the point light");
+    let mut shadows =
+        ShadowRenderer::new(ShadowSettings::default()).unwrap_or_else(|error| panic!("{}", error));
+    let mut cascades =
+        CascadedShadowMap::new(0, &shadows.settings).unwrap_or_else(|error| panic!("{}", error));
+    let mut point_shadow = PointShadowMap::new(point_light, &shadows.settings)
+        .unwrap_or_else(|error| panic!("{}", error));
+    // F1 shows the shadow maps, P changes the size of the PCF kernel
+    let mut show_shadow_maps = false;
+    // M switches between the PBR and Blinn-Phong shading
+    let mut use_pbr = true;
+
+    // Post-processing
     // -------
-    shader.use_shader();
-    shader.set_int("texture1", 0);
+    let (width, height) = window.get_framebuffer_size();
+    let mut post_process = PostProcessChain::new(width as u32, height as u32)
+        .unwrap_or_else(|error| panic!("{}", error));
+    // Keys 1 to 8 toggle the effects, only tone mapping, gamma and FXAA start enabled
+    let effects = [
+        (PostEffect::tone_mapping(1.0), true),
+        (PostEffect::gamma(2.2), true),
+        (PostEffect::grayscale(), false),
+        (PostEffect::sharpen(), false),
+        (PostEffect::blur(), false),
+        (PostEffect::edge_detection(), false),
+        (PostEffect::vignette(0.5, 0.6), false),
+        (PostEffect::fxaa(), true),
+    ];
+    for (effect, enabled) in effects {
+        let mut effect = effect.unwrap_or_else(|error| panic!("{}", error));
+        effect.enabled = enabled;
+        post_process.push(effect);
+    }
+
+    // Scene
+    // ------------------
+    let mut scene = Scene::new();
+    let floor = scene.add_node("floor", Transform::default(), None);
+    // Each cube is drawn again slightly bigger, as an outline
+    let cubes = [
+        (
+            scene.add_node(
+                "cube_left",
+                Transform::from_translation(glm::vec3(-1., 0., -1.)),
+                None,
+            ),
+            1.1,
+        ),
+        (
+            scene.add_node(
+                "cube_right",
+                Transform::from_translation(glm::vec3(2., 0., 0.)),
+                None,
+            ),
+            1.2,
+        ),
+    ];
 
     // Camera
     // ------------------
@@ -217,10 +364,44 @@ fn main() {
         delta_time = time - last_frame;
         last_frame = time;
 
+        for (_, error) in shaders.reload_changed() {
+            println!("{}", error);
+        }
+        let shader = if use_pbr {
+            &shaders[pbr_shader_id]
+        } else {
+            &shaders[shader_id]
+        };
+        let single_shader = &shaders[single_shader_id];
+
         // Input
         // TODO: Make this a polling event, (just have to keep track of when it polled the PRESS event and when it polled the RELEASE event)
         process_input(&mut window, &mut camera, delta_time);
         for (_, event) in glfw::flush_messages(&events) {
+            if let glfw::WindowEvent::Key(key, _, glfw::Action::Press, _) = event {
+                match key {
+                    glfw::Key::F1 => show_shadow_maps = !show_shadow_maps,
+                    glfw::Key::M => use_pbr = !use_pbr,
+                    glfw::Key::P => {
+                        shadows.settings.pcf_radius = (shadows.settings.pcf_radius + 1) % 4
+                    }
+                    glfw::Key::Num1
+                    | glfw::Key::Num2
+                    | glfw::Key::Num3
+                    | glfw::Key::Num4
+                    | glfw::Key::Num5
+                    | glfw::Key::Num6
+                    | glfw::Key::Num7
+                    | glfw::Key::Num8 => {
+                        let index = key as usize - glfw::Key::Num1 as usize;
+                        if let Some(enabled) = post_process.toggle(index) {
+                            let name = &post_process.effects()[index].name;
+                            println!("{}: {}", name, if enabled { "on" } else { "off" });
+                        }
+                    }
+                    _ => {}
+                }
+            }
             process_mouse(
                 event,
                 &mut camera,
@@ -230,53 +411,106 @@ fn main() {
             );
         }
 
+        // The point light circles the cubes
+        if let Light::Point { position, .. } = &mut lights.lights_mut()[point_light] {
+            *position = glm::vec3(2.0 * time.cos(), 1.5, 2.0 * time.sin());
+        }
+        lights.upload();
+
+        let draw_floor = |shader: &Shader| {
+            floor_material.bind(shader);
+            let _ = shader.set_uniform("model", scene.world_transform(floor));
+            plane_vao.bind();
+            unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 6) };
+            VertexArray::unbind();
+        };
+        let draw_cubes = |shader: &Shader| {
+            cube_material.bind(shader);
+            cube_vao.bind();
+            for (cube, _) in cubes {
+                let _ = shader.set_uniform("model", scene.world_transform(cube));
+                unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 36) };
+            }
+            VertexArray::unbind();
+        };
+
+        // Model/View/projection
+        let view = camera.get_view_matrix();
+        let aspect = SRC_WIDTH as f32 / SRC_HEIGHT as f32;
+        let fov_y = f32::to_radians(camera.zoom);
+        let projection = glm::perspective(aspect, fov_y, 0.1, 100.);
+
+        // Shadow maps
         unsafe {
             gl::Enable(gl::DEPTH_TEST);
+            gl::DepthFunc(gl::LESS);
+            gl::Disable(gl::STENCIL_TEST);
+        }
+        cascades.update(&lights, &view, aspect, fov_y, 0.1, &shadows.settings);
+        point_shadow.update(&lights, &shadows.settings);
+        shadows.render_cascades(&cascades, |shader| {
+            draw_floor(shader);
+            draw_cubes(shader);
+        });
+        shadows.render_point(&point_shadow, |shader| {
+            draw_floor(shader);
+            draw_cubes(shader);
+        });
+
+        // The scene is rendered offscreen for the post-processing
+        let (width, height) = window.get_framebuffer_size();
+        if width > 0 && height > 0 {
+            if let Err(error) = post_process.resize(width as u32, height as u32) {
+                println!("{}", error);
+            }
+        }
+        post_process.scene_framebuffer().bind();
+        unsafe {
             gl::Enable(gl::STENCIL_TEST);
-            gl::DepthFunc(gl::DEPTH_TEST);
             gl::StencilFunc(gl::NOTEQUAL, 1, 0xFF);
             gl::StencilOp(gl::KEEP, gl::KEEP, gl::REPLACE);
             gl::ClearColor(0.1, 0.1, 0.1, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
         }
-        // Model/View/projection
-        let mut model = glm::Mat4::identity();
-        let view = camera.get_view_matrix();
-        let projection = glm::perspective(
-            SRC_WIDTH as f32 / SRC_HEIGHT as f32,
-            f32::to_radians(camera.zoom),
-            0.1,
-            100.,
-        );
-        shader.set_mat4("view", &view);
-        shader.set_mat4("projection", &projection);
-        single_shader.set_mat4("view", &view);
-        single_shader.set_mat4("projection", &projection);
+        shader.use_shader();
+        let _ = shader.set_uniform("view", view);
+        let _ = shader.set_uniform("projection", projection);
+        let _ = shader.set_uniform("viewPos", camera.position);
+        let _ = lights.bind(shader);
+        shadows.bind(shader, Some(&cascades), Some(&point_shadow));
+        if let (true, Some(environment)) = (use_pbr, &environment) {
+            environment.bind(shader);
+        }
+        single_shader.use_shader();
+        let _ = single_shader.set_uniform("view", view);
+        let _ = single_shader.set_uniform("projection", projection);
 
         unsafe {
             shader.use_shader();
 
+            // make sure we dont update the stencil buffer while drawing the floor
             gl::StencilMask(0x00);
-            // Floor
-            gl::BindVertexArray(planeVAO);
-            gl::BindTexture(gl::TEXTURE_2D, floor_texture);
-            shader.set_mat4("model", &glm::Mat4::identity());
-            gl::DrawArrays(gl::TRIANGLES, 0, 6);
-            gl::BindVertexArray(0);
+            draw_floor(shader);
 
             gl::StencilFunc(gl::ALWAYS, 1, 0xFF);
             gl::StencilMask(0xFF);
-            // Cubes
-            gl::BindVertexArray(cubeVAO);
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, cube_texture);
-            model = glm::translate(&model, &glm::vec3(-1., 0., -1.));
-            shader.set_mat4("model", &model);
-            gl::DrawArrays(gl::TRIANGLES, 0, 36);
-            model = glm::identity();
-            model = glm::translate(&model, &glm::vec3(2., 0., 0.));
-            shader.set_mat4("model", &model);
-            gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            draw_cubes(shader);
+
+            // The sky fills what is left, it is drawn inside the cube at the far plane
+            if let (Some(environment), Some(skybox_shader_id)) = (&environment, skybox_shader_id) {
+                let skybox_shader = &shaders[skybox_shader_id];
+                gl::StencilMask(0x00);
+                gl::DepthFunc(gl::LEQUAL);
+                skybox_shader.use_shader();
+                let rotation = glm::mat3_to_mat4(&glm::mat4_to_mat3(&view));
+                let _ = skybox_shader.set_uniform("view", rotation);
+                let _ = skybox_shader.set_uniform("projection", projection);
+                let _ = skybox_shader.set_uniform("environmentMap", 0);
+                environment.environment.bind_to_unit(0);
+                cube_vao.bind();
+                gl::DrawArrays(gl::TRIANGLES, 0, 36);
+                gl::DepthFunc(gl::LESS);
+            }
 
             // Upscaled cubes
             gl::StencilFunc(gl::NOTEQUAL, 1, 0xFF);
@@ -284,36 +518,23 @@ fn main() {
             gl::Disable(gl::DEPTH_TEST);
             single_shader.use_shader();
 
-            gl::BindVertexArray(cubeVAO);
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, cube_texture);
-            model = glm::Mat4::identity();
-            model = glm::translate(&model, &glm::vec3(-1., 0., -1.));
-            model = glm::scale(&model, &glm::vec3(1.1, 1.1, 1.1));
-            single_shader.set_mat4("model", &model);
-            gl::DrawArrays(gl::TRIANGLES, 0, 36);
-            model = glm::identity();
-            model = glm::translate(&model, &glm::vec3(2., 0., 0.));
-            model = glm::scale(&model, &glm::vec3(1.2, 1.2, 1.2));
-            single_shader.set_mat4("model", &model);
-
-            gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            cube_vao.bind();
+            for (cube, outline_scale) in cubes {
+                let model = scene.world_transform(cube)
+                    * glm::scaling(&glm::vec3(outline_scale, outline_scale, outline_scale));
+                let _ = single_shader.set_uniform("model", model);
+                gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            }
             gl::BindVertexArray(0);
             gl::StencilMask(0xFF);
             gl::StencilFunc(gl::ALWAYS, 0, 0xFF);
             gl::Enable(gl::DEPTH_TEST);
-
-            // make sure we dont update the stencil buffer while drawing the floor
+        }
+        post_process.apply();
+        if show_shadow_maps {
+            shadows.draw_debug(Some(&cascades), Some(&point_shadow), width, height);
         }
         window.swap_buffers();
         glfw.poll_events();
     }
-    // for mesh in our_model.meshes.iter() {
-    //     unsafe {
-    //         gl::DeleteVertexArrays(1, &mesh.vao);
-    //         gl::DeleteBuffers(1, &mesh.vbo);
-    //         gl::DeleteBuffers(1, &mesh.ebo);
-    //     }
-    // }
-    // }
 }
@@ -0,0 +1,169 @@
+use crate::mesh::Texture;
+use crate::shader::Shader;
+
+/// What a material texture is used for. Each slot has its own texture unit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TextureSlot {
+    /// Albedo for PBR materials
+    Diffuse,
+    Specular,
+    /// Tangent space normals
+    Normal,
+    Emissive,
+    AmbientOcclusion,
+    /// Roughness in green and metalness in blue, as in glTF
+    MetallicRoughness,
+    Opacity,
+}
+impl TextureSlot {
+    pub const ALL: [TextureSlot; 7] = [
+        TextureSlot::Diffuse,
+        TextureSlot::Specular,
+        TextureSlot::Normal,
+        TextureSlot::Emissive,
+        TextureSlot::AmbientOcclusion,
+        TextureSlot::MetallicRoughness,
+        TextureSlot::Opacity,
+    ];
+    /// `material.<slot>_map`, the sampler of the slot in the `Material` struct of the shaders
+    pub fn map_uniform(self) -> &'static str {
+        match self {
+            TextureSlot::Diffuse => "material.diffuse_map",
+            TextureSlot::Specular => "material.specular_map",
+            TextureSlot::Normal => "material.normal_map",
+            TextureSlot::Emissive => "material.emissive_map",
+            TextureSlot::AmbientOcclusion => "material.occlusion_map",
+            TextureSlot::MetallicRoughness => "material.metallic_roughness_map",
+            TextureSlot::Opacity => "material.opacity_map",
+        }
+    }
+    /// `material.has_<slot>_map`, whether the slot has a texture
+    pub fn has_map_uniform(self) -> &'static str {
+        match self {
+            TextureSlot::Diffuse => "material.has_diffuse_map",
+            TextureSlot::Specular => "material.has_specular_map",
+            TextureSlot::Normal => "material.has_normal_map",
+            TextureSlot::Emissive => "material.has_emissive_map",
+            TextureSlot::AmbientOcclusion => "material.has_occlusion_map",
+            TextureSlot::MetallicRoughness => "material.has_metallic_roughness_map",
+            TextureSlot::Opacity => "material.has_opacity_map",
+        }
+    }
+    pub fn unit(self) -> u32 {
+        self as u32
+    }
+    /// Slots holding colors, stored in sRGB and decoded when sampled. The others hold data.
+    pub fn is_srgb(self) -> bool {
+        matches!(self, TextureSlot::Diffuse | TextureSlot::Emissive)
+    }
+}
+
+/// Textures and factors of a surface. `T` is how the textures are referenced, a
+/// `TextureSource` while importing and a loaded `Texture` once uploaded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material<T = Texture> {
+    pub name: String,
+    /// Indexed by `TextureSlot`
+    pub(crate) textures: [Option<T>; TextureSlot::ALL.len()],
+    /// `Kd`, multiplied with the diffuse texture. The base color of PBR materials.
+    pub diffuse: glm::Vec3,
+    /// `Ks`, multiplied with the specular texture
+    pub specular: glm::Vec3,
+    /// `Ns`, the specular exponent
+    pub shininess: f32,
+    /// `d`, 1 is opaque
+    pub opacity: f32,
+    pub emissive: glm::Vec3,
+    pub metallic: f32,
+    pub roughness: f32,
+}
+impl<T> Default for Material<T> {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            textures: Default::default(),
+            diffuse: glm::vec3(1.0, 1.0, 1.0),
+            specular: glm::vec3(1.0, 1.0, 1.0),
+            shininess: 32.0,
+            opacity: 1.0,
+            emissive: glm::Vec3::zeros(),
+            metallic: 1.0,
+            roughness: 1.0,
+        }
+    }
+}
+impl<T> Material<T> {
+    pub fn texture(&self, slot: TextureSlot) -> Option<&T> {
+        self.textures[slot as usize].as_ref()
+    }
+    pub fn set_texture(&mut self, slot: TextureSlot, texture: T) {
+        self.textures[slot as usize] = Some(texture);
+    }
+    /// Every slot with a texture
+    pub fn textures(&self) -> impl Iterator<Item = (TextureSlot, &T)> {
+        TextureSlot::ALL
+            .into_iter()
+            .filter_map(|slot| self.texture(slot).map(|texture| (slot, texture)))
+    }
+    /// Same factors with every texture converted by `f`, stops at the first error
+    pub fn map_textures<U, E>(
+        &self,
+        mut f: impl FnMut(TextureSlot, &T) -> Result<U, E>,
+    ) -> Result<Material<U>, E> {
+        let mut material = Material {
+            name: self.name.clone(),
+            textures: Default::default(),
+            diffuse: self.diffuse,
+            specular: self.specular,
+            shininess: self.shininess,
+            opacity: self.opacity,
+            emissive: self.emissive,
+            metallic: self.metallic,
+            roughness: self.roughness,
+        };
+        for (slot, texture) in self.textures() {
+            material.set_texture(slot, f(slot, texture)?);
+        }
+        Ok(material)
+    }
+}
+impl Material {
+    /// Binds the textures to the unit of their slot and sets the `material` uniform: a
+    /// `sampler2D <slot>_map` and a `bool has_<slot>_map` per slot, and the factors. Shaders
+    /// may leave out the members they don't use.
+    pub fn bind(&self, shader: &Shader) {
+        for slot in TextureSlot::ALL {
+            let texture = self.texture(slot);
+            if let Some(texture) = texture {
+                texture.texture.bind_to_unit(slot.unit());
+            }
+            let _ = shader.set_uniform(slot.map_uniform(), slot.unit() as i32);
+            let _ = shader.set_uniform(slot.has_map_uniform(), texture.is_some());
+        }
+        unsafe { gl::ActiveTexture(gl::TEXTURE0) };
+        let _ = shader.set_uniform("material.diffuse", self.diffuse);
+        let _ = shader.set_uniform("material.specular", self.specular);
+        let _ = shader.set_uniform("material.shininess", self.shininess);
+        let _ = shader.set_uniform("material.opacity", self.opacity);
+        let _ = shader.set_uniform("material.emissive", self.emissive);
+        let _ = shader.set_uniform("material.metallic", self.metallic);
+        let _ = shader.set_uniform("material.roughness", self.roughness);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slot_uniforms_are_members_of_the_shader_struct() {
+        let glsl = include_str!("shaders/material.glsl");
+        for slot in TextureSlot::ALL {
+            let map = slot.map_uniform().strip_prefix("material.").unwrap();
+            let has_map = slot.has_map_uniform().strip_prefix("material.").unwrap();
+            assert!(glsl.contains(&format!("sampler2D {};", map)), "{}", map);
+            assert!(glsl.contains(&format!("bool {};", has_map)), "{}", has_map);
+            assert_eq!(has_map, format!("has_{}", map));
+        }
+    }
+}
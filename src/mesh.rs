@@ -1,141 +1,266 @@
-use crate::offset_of;
+use crate::animation::MAX_BONE_INFLUENCE;
+use crate::gl_objects::{Buffer, Texture2D, VertexArray};
+use crate::material::Material;
+use crate::morph::MorphTarget;
 use crate::shader::Shader;
 use crate::utils::ToCVoid;
+use crate::vertex_layout;
+use crate::vertex_layout::{apply_layout, VertexLayout};
 use gl::types::*;
-use std::ffi::c_void;
-use std::mem::{size_of, size_of_val};
+use std::marker::PhantomData;
+use std::mem::{align_of, size_of};
+use std::path::PathBuf;
+use std::rc::Rc;
 
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 #[repr(C)]
 pub struct Vertex {
     pub position: glm::Vec3,
     pub normal: glm::Vec3,
     pub tex_coords: glm::Vec2,
+    /// Direction of increasing U in the surface, perpendicular to the normal
+    pub tangent: glm::Vec3,
+    /// Direction of increasing V, mirrored UVs make it `-cross(normal, tangent)`
+    pub bitangent: glm::Vec3,
+    /// Indices into the joints of the skeleton of the mesh, unused slots have a weight of 0
+    pub bone_ids: [u32; MAX_BONE_INFLUENCE],
+    /// All 0 for meshes without skeleton
+    pub bone_weights: [f32; MAX_BONE_INFLUENCE],
 }
+vertex_layout!(Vertex {
+    0 => position,
+    1 => normal,
+    2 => tex_coords,
+    3 => bone_ids,
+    4 => bone_weights,
+    5 => tangent,
+    6 => bitangent,
+});
+// The attribute layout relies on tightly packed 4 byte components
+const _: () = assert!(size_of::<Vertex>() == 22 * size_of::<f32>());
+const _: () = assert!(align_of::<Vertex>() == align_of::<f32>());
+const _: () = assert!(offset_of!(Vertex, position) == 0);
+const _: () = assert!(offset_of!(Vertex, normal) == 3 * size_of::<f32>());
+const _: () = assert!(offset_of!(Vertex, tex_coords) == 6 * size_of::<f32>());
+const _: () = assert!(offset_of!(Vertex, tangent) == 8 * size_of::<f32>());
+const _: () = assert!(offset_of!(Vertex, bitangent) == 11 * size_of::<f32>());
+const _: () = assert!(offset_of!(Vertex, bone_ids) == 14 * size_of::<f32>());
+const _: () = assert!(offset_of!(Vertex, bone_weights) == 18 * size_of::<f32>());
+
+/// Position, normal and texture coordinates, used by the cube and plane drawn in `main`
 #[repr(C)]
+pub struct PositionNormalTexVertex {
+    pub position: glm::Vec3,
+    pub normal: glm::Vec3,
+    pub tex_coords: glm::Vec2,
+}
+vertex_layout!(PositionNormalTexVertex {
+    0 => position,
+    1 => normal,
+    2 => tex_coords,
+});
+// Must match the 8 floats per vertex of the arrays in `main`
+const _: () = assert!(size_of::<PositionNormalTexVertex>() == 8 * size_of::<f32>());
+const _: () = assert!(align_of::<PositionNormalTexVertex>() == align_of::<f32>());
+const _: () = assert!(offset_of!(PositionNormalTexVertex, position) == 0);
+const _: () = assert!(offset_of!(PositionNormalTexVertex, normal) == 3 * size_of::<f32>());
+const _: () = assert!(offset_of!(PositionNormalTexVertex, tex_coords) == 6 * size_of::<f32>());
+
+/// Position only, for geometry drawn without shading such as cube map captures
+#[repr(C)]
+pub struct PositionVertex {
+    pub position: glm::Vec3,
+}
+vertex_layout!(PositionVertex {
+    0 => position,
+});
+// `ScreenQuad::VERTICES` is a flat array of 3 floats per vertex
+const _: () = assert!(size_of::<PositionVertex>() == 3 * size_of::<f32>());
+const _: () = assert!(align_of::<PositionVertex>() == align_of::<f32>());
+const _: () = assert!(offset_of!(PositionVertex, position) == 0);
+
+/// Two triangles covering the viewport, for full-screen passes. The vertex shader gets the
+/// corners in normalized device coordinates at location 0, with a z of 0.
+pub struct ScreenQuad {
+    vao: VertexArray,
+    _vbo: Buffer,
+}
+impl ScreenQuad {
+    #[rustfmt::skip]
+    const VERTICES: [f32; 18] = [
+        -1.0,  1.0, 0.0,  -1.0, -1.0, 0.0,   1.0, -1.0, 0.0,
+        -1.0,  1.0, 0.0,   1.0, -1.0, 0.0,   1.0,  1.0, 0.0,
+    ];
+
+    pub fn new() -> Self {
+        let vao = VertexArray::new();
+        vao.bind();
+        let vbo = Buffer::with_data(gl::ARRAY_BUFFER, &Self::VERTICES, gl::STATIC_DRAW);
+        apply_layout::<PositionVertex>();
+        VertexArray::unbind();
+        Self { vao, _vbo: vbo }
+    }
+    pub fn draw(&self) {
+        self.vao.bind();
+        unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 6) };
+        VertexArray::unbind();
+    }
+}
+impl Default for ScreenQuad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 #[derive(Clone, Debug)]
 pub struct Texture {
-    pub id: GLuint,
-    pub tex_type: &'static str,
-    pub path: String,
+    /// Shared between every mesh using it, deleted with the last one
+    pub texture: Rc<Texture2D>,
+    pub path: PathBuf,
 }
 
-#[repr(C)]
-pub struct Mesh {
-    pub vertices: Vec<Vertex>,
+/// A mesh as read from a file, with a single material. Plain data, nothing is uploaded to the
+/// GPU until it's passed to `GpuMesh::upload`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshData<V = Vertex> {
+    pub name: String,
+    pub vertices: Vec<V>,
     pub indices: Vec<u32>,
-    pub textures: Vec<Texture>,
-    pub vao: GLuint,
-    pub vbo: GLuint,
-    pub ebo: GLuint,
+    /// Index into the materials of the model
+    pub material: Option<usize>,
+    /// Index into the skeletons of the model, for skinned meshes
+    pub skin: Option<usize>,
+    /// Blend shapes, with their deltas for every vertex
+    pub morph_targets: Vec<MorphTarget>,
+    /// Initial weight of each morph target
+    pub morph_weights: Vec<f32>,
 }
-impl Default for Mesh {
-    fn default() -> Self {
+
+/// A mesh uploaded to the GPU, ready to be drawn. Any vertex type with a `VertexLayout` can be
+/// used.
+pub struct GpuMesh<V: VertexLayout = Vertex> {
+    /// Shared between the meshes of a model using it
+    pub material: Option<Rc<Material>>,
+    pub index_count: usize,
+    pub vao: VertexArray,
+    pub vbo: Buffer,
+    pub ebo: Buffer,
+    vertex: PhantomData<V>,
+}
+impl<V: VertexLayout> GpuMesh<V> {
+    /// Creates the buffers of the mesh, without material
+    pub fn upload(data: &MeshData<V>) -> Self {
+        let vao = VertexArray::new();
+        vao.bind();
+        let vbo = Buffer::with_data(gl::ARRAY_BUFFER, &data.vertices, gl::STATIC_DRAW);
+        let ebo = Buffer::with_data(gl::ELEMENT_ARRAY_BUFFER, &data.indices, gl::STATIC_DRAW);
+        apply_layout::<V>();
+        VertexArray::unbind();
+
         Self {
-            vbo: 0,
-            vao: 0,
-            ebo: 0,
-            textures: vec![],
-            indices: vec![],
-            vertices: vec![],
+            material: None,
+            index_count: data.indices.len(),
+            vao,
+            vbo,
+            ebo,
+            vertex: PhantomData,
         }
     }
-}
-impl Mesh {
-    pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, textures: Vec<Texture>) -> Self {
-        let mut mesh = Self {
-            vertices,
-            indices,
-            textures,
-            ..Mesh::default()
-        };
-        mesh.setup_mesh();
-        mesh
+    pub fn with_material(mut self, material: Rc<Material>) -> Self {
+        self.material = Some(material);
+        self
     }
+    /// Meshes without material are drawn with the default one, not with whatever the previous
+    /// mesh left bound
     pub fn draw(&self, shader: &Shader) {
-        let mut diffuse_nr: GLuint = 1;
-        let mut specular_nr: GLuint = 1;
-        for (i, texture) in self.textures.iter().enumerate() {
-            unsafe {
-                gl::ActiveTexture(gl::TEXTURE0 + i as u32);
-            }
-            let mut number = String::new();
-            let name = texture.tex_type;
-
-            if name == "texture_diffuse" {
-                number = diffuse_nr.to_string();
-                diffuse_nr += 1;
-            } else if name == "texture_specular" {
-                number = specular_nr.to_string();
-                specular_nr += 1;
-            };
-
-            shader.set_int(&format!("material.{}", name), i as i32);
-            unsafe { gl::BindTexture(gl::TEXTURE_2D, self.textures[i].id) }
+        match &self.material {
+            Some(material) => material.bind(shader),
+            None => Material::default().bind(shader),
         }
         unsafe {
-            gl::BindVertexArray(self.vao);
+            self.vao.bind();
             gl::DrawElements(
                 gl::TRIANGLES,
-                self.indices.len() as GLsizei,
+                self.index_count as GLsizei,
                 gl::UNSIGNED_INT,
                 ToCVoid(0).into(),
             );
-            gl::BindVertexArray(0);
-            gl::ActiveTexture(gl::TEXTURE0);
+            VertexArray::unbind();
         }
     }
-    fn setup_mesh(&mut self) {
-        println!("SETTING UP MESH");
-        unsafe {
-            gl::GenVertexArrays(1, &mut self.vao);
-            gl::GenBuffers(1, &mut self.vbo);
-            gl::GenBuffers(1, &mut self.ebo);
+}
 
-            gl::BindVertexArray(self.vao);
-            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vertex_layout::VertexAttribute;
+    use std::mem::size_of_val;
 
-            let size = (self.vertices.len() * size_of::<Vertex>()) as isize;
-            let data = self.vertices.as_ptr() as *const c_void;
+    const F: usize = size_of::<f32>();
 
-            gl::BufferData(gl::ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
+    /// Location, component count, type and offset of each attribute
+    fn layout<V: VertexLayout>() -> Vec<(GLuint, GLint, GLenum, usize)> {
+        V::attributes()
+            .iter()
+            .map(|a: &VertexAttribute| (a.location, a.components, a.gl_type, a.offset))
+            .collect()
+    }
 
-            let size = (self.indices.len() * size_of::<u32>()) as isize;
-            let data = self.indices.as_ptr() as *const c_void;
-            gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
+    #[test]
+    fn vertex_layout() {
+        assert_eq!(size_of::<Vertex>(), 22 * F);
+        assert_eq!(align_of::<Vertex>(), F);
+        assert_eq!(Vertex::stride(), 22 * F);
+        assert_eq!(offset_of!(Vertex, position), 0);
+        assert_eq!(offset_of!(Vertex, normal), 3 * F);
+        assert_eq!(offset_of!(Vertex, tex_coords), 6 * F);
+        assert_eq!(offset_of!(Vertex, tangent), 8 * F);
+        assert_eq!(offset_of!(Vertex, bitangent), 11 * F);
+        assert_eq!(offset_of!(Vertex, bone_ids), 14 * F);
+        assert_eq!(offset_of!(Vertex, bone_weights), 18 * F);
+        assert_eq!(
+            layout::<Vertex>(),
+            vec![
+                (0, 3, gl::FLOAT, 0),
+                (1, 3, gl::FLOAT, 3 * F),
+                (2, 2, gl::FLOAT, 6 * F),
+                (3, 4, gl::UNSIGNED_INT, 14 * F),
+                (4, 4, gl::FLOAT, 18 * F),
+                (5, 3, gl::FLOAT, 8 * F),
+                (6, 3, gl::FLOAT, 11 * F),
+            ]
+        );
+        let attributes = Vertex::attributes();
+        assert!(attributes[3].integer);
+        assert!(attributes.iter().all(|a| !a.normalized));
+        assert_eq!(attributes.iter().filter(|a| a.integer).count(), 1);
+    }
 
-            let size = size_of::<Vertex>() as i32;
-            // Vertex positions
-            gl::EnableVertexAttribArray(0);
-            gl::VertexAttribPointer(
-                0,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                size,
-                offset_of!(Vertex, position) as *const c_void,
-            );
+    #[test]
+    fn position_normal_tex_vertex_layout() {
+        assert_eq!(size_of::<PositionNormalTexVertex>(), 8 * F);
+        assert_eq!(align_of::<PositionNormalTexVertex>(), F);
+        assert_eq!(offset_of!(PositionNormalTexVertex, position), 0);
+        assert_eq!(offset_of!(PositionNormalTexVertex, normal), 3 * F);
+        assert_eq!(offset_of!(PositionNormalTexVertex, tex_coords), 6 * F);
+        assert_eq!(
+            layout::<PositionNormalTexVertex>(),
+            vec![
+                (0, 3, gl::FLOAT, 0),
+                (1, 3, gl::FLOAT, 3 * F),
+                (2, 2, gl::FLOAT, 6 * F),
+            ]
+        );
+    }
 
-            // Vertex normal
-            gl::EnableVertexAttribArray(1);
-            gl::VertexAttribPointer(
-                1,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                size,
-                offset_of!(Vertex, normal) as *const c_void,
-            );
-            gl::EnableVertexAttribArray(2);
-            gl::VertexAttribPointer(
-                2,
-                2,
-                gl::FLOAT,
-                gl::FALSE,
-                size,
-                offset_of!(Vertex, tex_coords) as *const c_void,
-            );
-            gl::BindVertexArray(0);
-        }
+    #[test]
+    fn position_vertex_layout() {
+        assert_eq!(size_of::<PositionVertex>(), 3 * F);
+        assert_eq!(align_of::<PositionVertex>(), F);
+        assert_eq!(offset_of!(PositionVertex, position), 0);
+        assert_eq!(
+            PositionVertex::stride() * 6,
+            size_of_val(&ScreenQuad::VERTICES)
+        );
+        assert_eq!(layout::<PositionVertex>(), vec![(0, 3, gl::FLOAT, 0)]);
     }
 }
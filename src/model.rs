@@ -1,233 +1,309 @@
-use crate::mesh::{Mesh, Texture, Vertex};
+use crate::animation::{AnimationClip, Animator, Skeleton, MAX_BONES};
+use crate::importer::{import, ImageData, ImportOptions, ModelData, TextureSource};
+use crate::material::TextureSlot;
+use crate::mesh::{GpuMesh, Texture};
+use crate::morph::{disable_morph_targets, MorphMode, MorphTargets};
+use crate::scene::{NodeId, Scene, Transform};
 use crate::shader::Shader;
-use crate::utils::{load_texture, to_c_str};
-use russimp::material::{PropertyTypeInfo, TextureType};
-use russimp::node::Node;
-use russimp::scene::{PostProcess, PostProcessSteps, Scene};
-use std::ops::Index;
-use std::path::Path;
+use crate::texture::{TextureDescriptor, TextureError};
+use crate::texture_cache::TextureCache;
+use crate::utils::{checker_texture, normalize_path, texture_from_rgba};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::time::SystemTime;
 
 pub struct Model {
-    pub meshes: Vec<Mesh>,
-    pub directory: &'static str,
-    loaded_textures: Vec<Texture>,
+    pub meshes: Vec<GpuMesh>,
+    /// Hierarchy placing the meshes, as read from the file. Node meshes index `meshes`.
+    pub scene: Scene,
+    pub skins: Vec<Skeleton>,
+    /// Clips animating the nodes of `scene`
+    pub animations: Vec<AnimationClip>,
+    /// Local transforms of the nodes as read from the file
+    pub rest_pose: Vec<Transform>,
+    /// Skin of each mesh, skinned meshes are placed by their joints instead of their node
+    mesh_skins: Vec<Option<usize>>,
+    /// Morph targets of each mesh, `None` for meshes without any
+    morphs: Vec<Option<MorphTargets>>,
+    pub directory: PathBuf,
+    /// Textures that couldn't be found and were replaced with the checker pattern, with
+    /// `LoadOptions::fallback_texture`
+    pub missing_textures: Vec<PathBuf>,
+    options: LoadOptions,
 }
 
-impl Model {
-    pub fn new(path: &'static str) -> Self {
-        let mut model = Self {
-            meshes: vec![],
-            directory: "",
-            loaded_textures: vec![],
-        };
-        model.load_model_obj(path);
-        model
-    }
-    pub fn draw(&self, shader: &Shader) {
-        for mesh in self.meshes.iter() {
-            mesh.draw(shader);
-        }
-    }
-    fn load_model_obj(&mut self, path: &'static str) {
-        let last_sep = path.rfind("/").unwrap();
-        self.directory = &path[0..last_sep];
-
-        // Using tobj's default options the vertex normals will be not the same number of vertex positions :\
-        let (models, materials) =
-            tobj::load_obj(&Path::new(path), &tobj::GPU_LOAD_OPTIONS).unwrap();
-        let materials = materials.unwrap();
-
-        for model in models.iter() {
-            let mesh = &model.mesh;
-            assert_eq!(mesh.positions.len() % 3, 0);
-            let vertices_count = mesh.positions.len() / 3;
-            println!("MESH {}", vertices_count);
-            let mut vertices: Vec<Vertex> = Vec::with_capacity(vertices_count);
-            let mut indices: Vec<u32> = mesh.indices.clone();
-            let mut textures: Vec<Texture> = vec![];
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    /// Replace textures that can't be found with a checker pattern instead of failing, they are
+    /// listed in `Model::missing_textures`
+    pub fallback_texture: bool,
+    /// Where morph targets are applied, on the CPU for shaders without morph target support
+    pub morph_mode: MorphMode,
+    pub import: ImportOptions,
+}
 
-            let (p, n, t) = (&mesh.positions, &mesh.normals, &mesh.texcoords);
-            // Process vertices
-            for i in 0..vertices_count {
-                vertices.push(Vertex {
-                    position: glm::vec3(p[i * 3], p[i * 3 + 1], p[i * 3 + 2]),
-                    normal: glm::vec3(n[i * 3], n[i * 3 + 1], n[i * 3 + 2]),
-                    tex_coords: glm::vec2(t[i * 2], t[i * 2 + 1]),
-                })
+#[derive(Debug)]
+pub enum ModelError {
+    NotFound(PathBuf),
+    /// No importer handles the extension of the file
+    UnsupportedFormat(PathBuf),
+    /// The path uses `\\` separators, which this platform reads as part of a file name
+    WindowsSeparators(PathBuf),
+    Parse {
+        path: PathBuf,
+        message: String,
+    },
+    /// The material library referenced by an OBJ file couldn't be opened or parsed
+    MaterialLibrary {
+        path: PathBuf,
+        error: tobj::LoadError,
+    },
+    /// A texture referenced by a material doesn't exist
+    MissingTexture(PathBuf),
+    /// A texture referenced by a material couldn't be decoded
+    Texture(TextureError),
+    /// A skin has more joints than the `bones` array of the skinning shader holds
+    TooManyJoints {
+        path: PathBuf,
+        skin: usize,
+        joints: usize,
+    },
+}
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelError::NotFound(path) => write!(f, "model {} not found", path.display()),
+            ModelError::UnsupportedFormat(path) => {
+                write!(f, "unsupported model format {}", path.display())
             }
-
-            // Process textures
-            if let Some(material_id) = mesh.material_id {
-                let material = &materials[material_id];
-                textures = self.load_material_textures_tobj(&material);
+            ModelError::WindowsSeparators(path) => write!(
+                f,
+                "model {} not found, use `/` as path separator",
+                path.display()
+            ),
+            ModelError::Parse { path, message } => {
+                write!(f, "couldn't parse model {}: {}", path.display(), message)
             }
+            ModelError::MaterialLibrary { path, error } => write!(
+                f,
+                "couldn't load the material library of {}: {}",
+                path.display(),
+                error
+            ),
+            ModelError::MissingTexture(path) => write!(f, "texture {} not found", path.display()),
+            ModelError::Texture(error) => write!(f, "{}", error),
+            ModelError::TooManyJoints { path, skin, joints } => write!(
+                f,
+                "skin {} of {} has {} joints, at most {} are supported",
+                skin,
+                path.display(),
+                joints,
+                MAX_BONES
+            ),
+        }
+    }
+}
+impl std::error::Error for ModelError {}
+impl From<TextureError> for ModelError {
+    fn from(error: TextureError) -> Self {
+        ModelError::Texture(error)
+    }
+}
 
-            let mesh = Mesh::new(vertices, indices, textures);
-            self.meshes.push(mesh);
-            // for vertex in mesh.positions
+impl Model {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self::load(path).unwrap_or_else(|error| panic!("{}", error))
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ModelError> {
+        Self::load_with(path, LoadOptions::default())
+    }
+    pub fn load_with(path: impl AsRef<Path>, options: LoadOptions) -> Result<Self, ModelError> {
+        let path = path.as_ref();
+        if !path.is_file() {
+            let has_backslash = path.to_string_lossy().contains('\\');
+            return Err(if has_backslash && !cfg!(windows) {
+                ModelError::WindowsSeparators(path.to_path_buf())
+            } else {
+                ModelError::NotFound(path.to_path_buf())
+            });
         }
+        let data = import(path, &options.import)?;
+        Self::upload(path, data, options)
     }
-    fn load_material_textures_tobj(&mut self, material: &tobj::Material) -> Vec<Texture> {
-        let mut textures: Vec<Texture> = vec![];
-        // Diffuse texture
-        if let Some(ref diffuse_texture) = material.diffuse_texture {
-            let path = format!("{}/{}", self.directory, diffuse_texture);
-            let mut skip = false;
-            for loaded_texture in self.loaded_textures.iter() {
-                if &loaded_texture.path == &path {
-                    textures.push(loaded_texture.clone());
-                    skip = true;
-                }
+    /// Creates the GPU resources of a model imported with `importer::import`, which doesn't
+    /// need a GL context and may run on another thread. `path` is the file it was read from,
+    /// textures are resolved against it.
+    pub fn upload(path: &Path, data: ModelData, options: LoadOptions) -> Result<Self, ModelError> {
+        for (skin, skeleton) in data.skins.iter().enumerate() {
+            if skeleton.joints.len() > MAX_BONES {
+                return Err(ModelError::TooManyJoints {
+                    path: path.to_path_buf(),
+                    skin,
+                    joints: skeleton.joints.len(),
+                });
             }
+        }
+        let mut model = Self {
+            meshes: vec![],
+            scene: Scene::new(),
+            skins: data.skins,
+            animations: data.animations,
+            rest_pose: data.nodes.iter().map(|node| node.transform).collect(),
+            mesh_skins: data.meshes.iter().map(|mesh| mesh.skin).collect(),
+            morphs: vec![],
+            directory: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+            missing_textures: vec![],
+            options,
+        };
 
-            if !skip {
-                let texture = Texture {
-                    id: load_texture(&path),
-                    path,
-                    tex_type: "texture_diffuse",
-                };
-                self.loaded_textures.push(texture.clone());
-                textures.push(texture);
+        let mut materials = Vec::with_capacity(data.materials.len());
+        for material in data.materials.iter() {
+            let material = material.map_textures(|slot, source| {
+                model.load_texture(path, slot, source, &data.images)
+            })?;
+            materials.push(Rc::new(material));
+        }
+        for mesh in data.meshes.iter() {
+            let mut gpu_mesh = GpuMesh::upload(mesh);
+            if let Some(material) = mesh.material {
+                gpu_mesh = gpu_mesh.with_material(materials[material].clone());
             }
+            model.meshes.push(gpu_mesh);
+            let morph = (!mesh.morph_targets.is_empty()).then(|| {
+                MorphTargets::new(
+                    &mesh.vertices,
+                    mesh.morph_targets.clone(),
+                    mesh.morph_weights.clone(),
+                    model.options.morph_mode,
+                )
+            });
+            model.morphs.push(morph);
         }
-        if let Some(ref specular_texture) = material.specular_texture {
-            let path = format!("{}/{}", self.directory, specular_texture);
-            let mut skip = false;
-            for loaded_texture in self.loaded_textures.iter() {
-                if &loaded_texture.path == &path {
-                    textures.push(loaded_texture.clone());
-                    skip = true;
-                }
+        // The vertex buffers hold the meshes without their initial morph weights
+        for i in 0..model.meshes.len() {
+            if let Some(morph) = &model.morphs[i] {
+                let weights = morph.weights().to_vec();
+                model.set_morph_weights(i, &weights);
             }
-
-            if !skip {
-                let texture = Texture {
-                    id: load_texture(&path),
-                    path,
-                    tex_type: "texture_specular",
-                };
-                self.loaded_textures.push(texture.clone());
-                textures.push(texture);
+        }
+        // Nodes are stored parents first, so node ids match the indices of the data
+        let mut parents = vec![None; data.nodes.len()];
+        for (i, node) in data.nodes.iter().enumerate() {
+            for child in node.children.iter() {
+                parents[*child] = Some(NodeId(i));
             }
         }
-        textures
+        for (node, parent) in data.nodes.iter().zip(parents) {
+            let id = model
+                .scene
+                .add_node(node.name.clone(), node.transform, parent);
+            model.scene.node_mut(id).meshes = node.meshes.clone();
+        }
+        Ok(model)
     }
-
-    fn load_model_russimp(&mut self, path: &'static str) {
-        println!("LOADING MODEL");
-        match Scene::from_file(path, vec![PostProcess::Triangulate, PostProcess::FlipUVs]) {
-            Err(error) => {
-                println!("ERROR::ASSIMP::{:?}", error);
-            }
-            Ok(scene) => {
-                let last_sep = path.rfind("/").unwrap();
-                self.directory = &path[0..last_sep];
-                if let Some(ref node) = scene.root {
-                    self.process_node(node.clone(), &scene);
+    /// Draws every mesh with the transform of its node, relative to `transform`. Sets the
+    /// `model` uniform, and the `bones` uniform for skinned meshes.
+    pub fn draw(&self, shader: &Shader, transform: &glm::Mat4) {
+        let palettes: Vec<Vec<glm::Mat4>> = self
+            .skins
+            .iter()
+            .map(|skin| skin.palette(&self.scene))
+            .collect();
+        for id in self.scene.ids() {
+            let node = self.scene.node(id);
+            for mesh in node.meshes.iter() {
+                match self.mesh_skins[*mesh] {
+                    Some(skin) => {
+                        let _ = shader.set_uniform("model", *transform);
+                        let _ = shader.set_uniform_array("bones", &palettes[skin]);
+                    }
+                    None => {
+                        let _ =
+                            shader.set_uniform("model", transform * self.scene.world_transform(id));
+                    }
                 }
-                println!("FINISHED PROCESSING NODES");
+                match &self.morphs[*mesh] {
+                    Some(morph) => morph.bind(shader),
+                    None => disable_morph_targets(shader),
+                }
+                self.meshes[*mesh].draw(shader);
             }
         }
     }
-    fn process_node(&mut self, node: Rc<Node>, scene: &Scene) {
-        let start = SystemTime::now();
-        println!("PROCESSING NODE {} {:?}", node.name, start);
-        for mesh in node.meshes.iter() {
-            let m = &scene.meshes[*mesh as usize];
-            let mesh = self.process_mesh(m, scene);
-            self.meshes.push(mesh);
-        }
-        println!("FINISHED - {:?}", start.elapsed());
-
-        let children = node.children.borrow();
-        for child in children.iter() {
-            self.process_node(child.clone(), scene);
-        }
+    pub fn morph_targets(&self, mesh: usize) -> Option<&MorphTargets> {
+        self.morphs[mesh].as_ref()
     }
-    fn process_mesh(&mut self, mesh: &russimp::mesh::Mesh, scene: &Scene) -> Mesh {
-        let start = SystemTime::now();
-        println!("PROCESSING MESH {:?}", start);
-        let mut vertices: Vec<Vertex> = vec![];
-        let mut indices: Vec<u32> = vec![];
-        let mut textures: Vec<Texture> = vec![];
-
-        for i in 0..mesh.vertices.len() {
-            let tex_coords = if let Some(ref tex_coord) = mesh.texture_coords[0] {
-                glm::vec2(tex_coord[0].x, tex_coord[0].y)
-            } else {
-                glm::vec2(0.0, 0.0)
-            };
-
-            let vertex = Vertex {
-                position: glm::vec3(mesh.vertices[i].x, mesh.vertices[i].y, mesh.vertices[i].z),
-                normal: glm::vec3(mesh.normals[i].x, mesh.normals[i].y, mesh.normals[i].z),
-                tex_coords,
-            };
-            vertices.push(vertex);
-        }
-
-        for face in mesh.faces.iter() {
-            for index in face.0.iter() {
-                indices.push(*index);
-            }
-        }
-
-        println!("PROCESSING MESH {:?}", start.elapsed());
-        if mesh.material_index >= 0 {
-            let material = &scene.materials[mesh.material_index as usize];
-
-            let mut diffuse_maps =
-                self.load_material_textures(material, TextureType::Diffuse, "texture_diffuse");
-            let mut specular_maps =
-                self.load_material_textures(material, TextureType::Specular, "texture_specular");
-            textures.append(&mut diffuse_maps);
-            textures.append(&mut specular_maps);
+    /// Sets the weight of each morph target of a mesh. On the CPU path the morphed vertices are
+    /// uploaded right away.
+    pub fn set_morph_weights(&mut self, mesh: usize, weights: &[f32]) {
+        let Some(morph) = &mut self.morphs[mesh] else {
+            return;
+        };
+        if let Some(vertices) = morph.set_weights(weights) {
+            let vbo = &self.meshes[mesh].vbo;
+            vbo.bind();
+            vbo.data(&vertices, gl::DYNAMIC_DRAW);
         }
-        println!("FINISHED PROCESSING MESH {:?}", start.elapsed());
-        return Mesh::new(vertices, indices, textures);
     }
-
-    fn load_material_textures(
+    /// Moves the nodes to the pose blended by `animator` from the clips of this model
+    pub fn animate(&mut self, animator: &Animator) {
+        animator.apply(&self.animations, &self.rest_pose, &mut self.scene);
+    }
+    /// Loads a texture referenced by a material through the global cache, so textures are
+    /// shared with the other meshes and models using them. Color slots are decoded from sRGB.
+    fn load_texture(
         &mut self,
-        material: &russimp::material::Material,
-        tex_type: russimp::material::TextureType,
-        tex_name: &'static str,
-    ) -> Vec<Texture> {
-        let mut textures: Vec<Texture> = vec![];
-        for property in material.properties.iter() {
-            if property.semantic == tex_type {
-                match property.data {
-                    PropertyTypeInfo::String(ref str) => {
-                        let file = format!("{}/{}", self.directory, str);
-                        let mut skip = false;
-
-                        println!("LOADED {:?}", self.loaded_textures);
-                        for loaded_textures in self.loaded_textures.iter() {
-                            if file == loaded_textures.path {
-                                textures.push(loaded_textures.clone());
-                                skip = true;
-                                println!("SKIPPING");
-                                break;
-                            }
-                        }
-
-                        if !skip {
-                            println!("NOT SKIPPING");
-                            let texture = Texture {
-                                id: load_texture(&file),
-                                tex_type: tex_name,
-                                path: file.clone(),
-                            };
-                            self.loaded_textures.push(texture.clone());
-                            textures.push(texture.clone());
-                        }
-                    }
-                    _ => {}
-                };
+        model_path: &Path,
+        slot: TextureSlot,
+        source: &TextureSource,
+        images: &[ImageData],
+    ) -> Result<Texture, ModelError> {
+        let path = match source {
+            TextureSource::File(name) => self.resolve_texture_path(name),
+            // Only used to tell the images apart
+            TextureSource::Embedded(index) => {
+                PathBuf::from(format!("{}#{}", model_path.display(), index))
             }
-        }
-        textures
+        };
+        let descriptor = TextureDescriptor {
+            srgb: slot.is_srgb(),
+            ..TextureDescriptor::default()
+        };
+        let texture = if let TextureSource::Embedded(index) = source {
+            let image = &images[*index];
+            let key = model_path
+                .canonicalize()
+                .unwrap_or_else(|_| model_path.to_path_buf());
+            let key = PathBuf::from(format!("{}#{}", key.display(), index));
+            TextureCache::with_global(|cache| {
+                cache.get_or_insert_with(key, descriptor, || {
+                    texture_from_rgba(&path, image.width, image.height, &image.pixels, &descriptor)
+                })
+            })?
+        } else if path.is_file() {
+            TextureCache::with_global(|cache| cache.load(&path, descriptor))?
+        } else if self.options.fallback_texture {
+            if !self.missing_textures.contains(&path) {
+                self.missing_textures.push(path.clone());
+            }
+            TextureCache::with_global(|cache| {
+                cache.get_or_insert_with(PathBuf::from("#checker"), descriptor, checker_texture)
+            })?
+        } else {
+            return Err(ModelError::MissingTexture(path));
+        };
+        Ok(Texture { texture, path })
+    }
+    /// Texture paths in material files are relative to the model, unless absolute. They may
+    /// go through `..` and, when written on Windows, use `\\` separators.
+    fn resolve_texture_path(&self, name: &str) -> PathBuf {
+        let name = name.trim();
+        let name = if cfg!(windows) {
+            name.to_string()
+        } else {
+            name.replace('\\', "/")
+        };
+        // `join` keeps absolute paths as they are
+        normalize_path(&self.directory.join(name))
     }
 }
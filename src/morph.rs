@@ -0,0 +1,267 @@
+use crate::gl_objects::BufferTexture;
+use crate::mesh::Vertex;
+use crate::shader::Shader;
+
+/// Size of the `morphWeights` array of the skinning shader
+pub const MAX_MORPH_TARGETS: usize = 32;
+/// Texture unit of the deltas, above the units used by materials
+pub const MORPH_TEXTURE_UNIT: u32 = 15;
+
+/// Blend shape of a mesh, offsets added to its vertices scaled by the weight of the target
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MorphTarget {
+    pub name: String,
+    /// One per vertex of the mesh
+    pub position_deltas: Vec<glm::Vec3>,
+    /// One per vertex of the mesh, or empty when the target doesn't change the normals
+    pub normal_deltas: Vec<glm::Vec3>,
+}
+
+/// Vertices of a mesh with the weighted deltas of its targets added. Targets without a weight
+/// are skipped. When normals move, tangents and bitangents are made perpendicular to them again.
+pub fn apply_morph_targets(
+    base: &[Vertex],
+    targets: &[MorphTarget],
+    weights: &[f32],
+) -> Vec<Vertex> {
+    let mut vertices = base.to_vec();
+    for (target, weight) in targets.iter().zip(weights.iter()) {
+        if *weight == 0.0 {
+            continue;
+        }
+        for (vertex, delta) in vertices.iter_mut().zip(target.position_deltas.iter()) {
+            vertex.position += delta * *weight;
+        }
+        for (vertex, delta) in vertices.iter_mut().zip(target.normal_deltas.iter()) {
+            vertex.normal += delta * *weight;
+        }
+    }
+    if targets
+        .iter()
+        .any(|target| !target.normal_deltas.is_empty())
+    {
+        for vertex in vertices.iter_mut() {
+            vertex.normal = vertex
+                .normal
+                .try_normalize(f32::EPSILON)
+                .unwrap_or(vertex.normal);
+            // Gram-Schmidt, keeping the handedness of the bitangent
+            let normal = vertex.normal;
+            let tangent = vertex.tangent - normal * normal.dot(&vertex.tangent);
+            let Some(tangent) = tangent.try_normalize(f32::EPSILON) else {
+                continue;
+            };
+            let bitangent = normal.cross(&tangent);
+            vertex.tangent = tangent;
+            vertex.bitangent = if bitangent.dot(&vertex.bitangent) < 0.0 {
+                -bitangent
+            } else {
+                bitangent
+            };
+        }
+    }
+    vertices
+}
+
+/// Deltas laid out for the skinning shader: the positions then the normals of each target,
+/// `vertex_count` texels each
+pub fn morph_texels(targets: &[MorphTarget], vertex_count: usize) -> Vec<glm::Vec4> {
+    let mut texels = Vec::with_capacity(targets.len() * 2 * vertex_count);
+    for target in targets.iter() {
+        for deltas in [&target.position_deltas, &target.normal_deltas] {
+            texels.extend((0..vertex_count).map(|i| {
+                let delta = deltas.get(i).copied().unwrap_or_else(glm::Vec3::zeros);
+                glm::vec4(delta.x, delta.y, delta.z, 0.0)
+            }));
+        }
+    }
+    texels
+}
+
+/// Where the weighted deltas are added to the vertices
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MorphMode {
+    /// In the vertex shader, reading the deltas from a texture buffer. Meshes whose deltas
+    /// don't fit in one use the CPU path.
+    #[default]
+    Gpu,
+    /// Before drawing, the vertex buffer is uploaded again every time the weights change. For
+    /// shaders without morph target support.
+    Cpu,
+}
+
+/// Morph targets of an uploaded mesh and their current weights
+pub struct MorphTargets {
+    targets: Vec<MorphTarget>,
+    weights: Vec<f32>,
+    vertex_count: usize,
+    /// Vertices without any target applied, kept for the CPU path
+    base: Vec<Vertex>,
+    /// Deltas sampled by the shader on the GPU path
+    deltas: Option<BufferTexture>,
+}
+impl MorphTargets {
+    /// The GPU path falls back to the CPU one when the deltas don't fit in a texture buffer
+    pub fn new(
+        base: &[Vertex],
+        targets: Vec<MorphTarget>,
+        weights: Vec<f32>,
+        mode: MorphMode,
+    ) -> Self {
+        let mut weights = weights;
+        weights.resize(targets.len(), 0.0);
+        let vertex_count = base.len();
+        let texel_count = 2 * targets.len() * vertex_count;
+        let mode = match mode {
+            MorphMode::Gpu if texel_count > max_texture_buffer_size() => MorphMode::Cpu,
+            mode => mode,
+        };
+        let (base, deltas) = match mode {
+            MorphMode::Gpu => {
+                let texels = morph_texels(&targets, base.len());
+                (vec![], Some(BufferTexture::with_data(gl::RGBA32F, &texels)))
+            }
+            MorphMode::Cpu => (base.to_vec(), None),
+        };
+        Self {
+            targets,
+            weights,
+            vertex_count,
+            base,
+            deltas,
+        }
+    }
+    pub fn targets(&self) -> &[MorphTarget] {
+        &self.targets
+    }
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+    /// Extra weights are ignored, missing ones are zero. Returns the morphed vertices to upload
+    /// on the CPU path.
+    pub fn set_weights(&mut self, weights: &[f32]) -> Option<Vec<Vertex>> {
+        for (i, weight) in self.weights.iter_mut().enumerate() {
+            *weight = weights.get(i).copied().unwrap_or(0.0);
+        }
+        match self.deltas {
+            Some(_) => None,
+            None => Some(apply_morph_targets(
+                &self.base,
+                &self.targets,
+                &self.weights,
+            )),
+        }
+    }
+    /// Sets the morph uniforms of the skinning shader. Only the first `MAX_MORPH_TARGETS`
+    /// targets are applied on the GPU.
+    pub fn bind(&self, shader: &Shader) {
+        let Some(deltas) = &self.deltas else {
+            disable_morph_targets(shader);
+            return;
+        };
+        let count = self.targets.len().min(MAX_MORPH_TARGETS);
+        deltas.bind_to_unit(MORPH_TEXTURE_UNIT);
+        let _ = shader.set_uniform("morphDeltas", MORPH_TEXTURE_UNIT as i32);
+        let _ = shader.set_uniform("morphTargetCount", count as i32);
+        let _ = shader.set_uniform("morphVertexCount", self.vertex_count as i32);
+        let _ = shader.set_uniform_array("morphWeights", &self.weights[..count]);
+    }
+}
+
+/// Texels a texture buffer can hold, at least 65536 with OpenGL 3.3
+fn max_texture_buffer_size() -> usize {
+    let mut size = 0;
+    unsafe { gl::GetIntegerv(gl::MAX_TEXTURE_BUFFER_SIZE, &mut size) };
+    size.max(0) as usize
+}
+
+/// Draws the next meshes without morph targets. The sampler still gets its own unit, samplers
+/// of different types sharing a unit make the draw fail.
+pub fn disable_morph_targets(shader: &Shader) {
+    let _ = shader.set_uniform("morphDeltas", MORPH_TEXTURE_UNIT as i32);
+    let _ = shader.set_uniform("morphTargetCount", 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(position: glm::Vec3) -> Vertex {
+        Vertex {
+            position,
+            normal: glm::Vec3::z(),
+            tangent: glm::Vec3::x(),
+            bitangent: glm::Vec3::y(),
+            ..Vertex::default()
+        }
+    }
+
+    fn targets() -> Vec<MorphTarget> {
+        vec![
+            MorphTarget {
+                name: "up".to_string(),
+                position_deltas: vec![glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, 2.0, 0.0)],
+                normal_deltas: vec![],
+            },
+            MorphTarget {
+                name: "tilt".to_string(),
+                position_deltas: vec![glm::vec3(1.0, 0.0, 0.0), glm::Vec3::zeros()],
+                normal_deltas: vec![glm::vec3(1.0, 0.0, -1.0), glm::Vec3::zeros()],
+            },
+        ]
+    }
+
+    #[test]
+    fn deltas_are_scaled_by_the_weights() {
+        let base = [vertex(glm::Vec3::zeros()), vertex(glm::vec3(1.0, 0.0, 0.0))];
+        let vertices = apply_morph_targets(&base, &targets(), &[0.5, 1.0]);
+        assert_eq!(vertices[0].position, glm::vec3(1.0, 0.5, 0.0));
+        assert_eq!(vertices[1].position, glm::vec3(1.0, 1.0, 0.0));
+        // z + (x - z) is x once normalized
+        assert!((vertices[0].normal - glm::Vec3::x()).norm() < 1e-6);
+        assert_eq!(vertices[1].normal, glm::Vec3::z());
+    }
+
+    #[test]
+    fn zero_weights_leave_the_mesh_unchanged() {
+        let base = [vertex(glm::Vec3::zeros()), vertex(glm::vec3(1.0, 0.0, 0.0))];
+        assert_eq!(apply_morph_targets(&base, &targets(), &[0.0, 0.0]), base);
+        // Missing weights count as zero
+        assert_eq!(apply_morph_targets(&base, &targets(), &[]), base);
+    }
+
+    #[test]
+    fn tangents_follow_the_morphed_normals() {
+        let base = [vertex(glm::Vec3::zeros()), vertex(glm::Vec3::zeros())];
+        let vertices = apply_morph_targets(&base, &targets(), &[0.0, 0.5]);
+        let normal = glm::vec3(1.0, 0.0, 1.0).normalize();
+        let vertex = &vertices[0];
+        assert!((vertex.normal - normal).norm() < 1e-6);
+        assert!(vertex.tangent.dot(&vertex.normal).abs() < 1e-6);
+        assert!((vertex.tangent.norm() - 1.0).abs() < 1e-6);
+        assert!((vertex.bitangent - glm::Vec3::y()).norm() < 1e-6);
+        // Left alone when the normal doesn't move
+        assert_eq!(vertices[1].tangent, glm::Vec3::x());
+    }
+
+    #[test]
+    fn texels_hold_positions_then_normals_of_each_target() {
+        let texels = morph_texels(&targets(), 2);
+        let w = |v: glm::Vec3| glm::vec4(v.x, v.y, v.z, 0.0);
+        assert_eq!(
+            texels,
+            vec![
+                // Positions of "up"
+                w(glm::vec3(0.0, 1.0, 0.0)),
+                w(glm::vec3(0.0, 2.0, 0.0)),
+                // "up" has no normal deltas
+                w(glm::Vec3::zeros()),
+                w(glm::Vec3::zeros()),
+                w(glm::vec3(1.0, 0.0, 0.0)),
+                w(glm::Vec3::zeros()),
+                w(glm::vec3(1.0, 0.0, -1.0)),
+                w(glm::Vec3::zeros()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use crate::framebuffer::{DepthAttachment, Framebuffer, FramebufferDescriptor, FramebufferError};
+use crate::mesh::ScreenQuad;
+use crate::shader::{Shader, ShaderBuilder, ShaderError};
+use gl::types::*;
+use std::fmt;
+
+/// Value of a uniform of an effect
+#[derive(Clone, Debug, PartialEq)]
+pub enum EffectParam {
+    Int(i32),
+    Float(f32),
+    Vec3(glm::Vec3),
+    /// Uniform array of floats, such as a convolution kernel
+    Floats(Vec<f32>),
+}
+
+/// A full-screen pass. Its fragment shader samples the previous pass from `screenTexture` and
+/// gets the size of a texel in `texelSize`, the parameters are set as uniforms of the same
+/// name before each run.
+pub struct PostEffect {
+    pub name: String,
+    pub enabled: bool,
+    shader: Shader,
+    params: Vec<(String, EffectParam)>,
+}
+impl PostEffect {
+    /// Effect made of `fragment`, drawn over `quad-vertex.glsl`
+    pub fn new(name: &str, fragment: &str) -> Result<Self, ShaderError> {
+        let shader = ShaderBuilder::new()
+            .vertex("src/shaders/quad-vertex.glsl")
+            .fragment(fragment)
+            .build()?;
+        Ok(Self {
+            name: name.to_string(),
+            enabled: true,
+            shader,
+            params: vec![],
+        })
+    }
+    /// HDR to displayable colors, with the ACES filmic curve after scaling by `exposure`
+    pub fn tone_mapping(exposure: f32) -> Result<Self, ShaderError> {
+        Ok(Self::new("tone mapping", "src/shaders/post-tonemap.glsl")?
+            .with_param("exposure", EffectParam::Float(exposure)))
+    }
+    /// Encodes linear colors for the display
+    pub fn gamma(gamma: f32) -> Result<Self, ShaderError> {
+        Ok(Self::new("gamma", "src/shaders/post-gamma.glsl")?
+            .with_param("gamma", EffectParam::Float(gamma)))
+    }
+    pub fn grayscale() -> Result<Self, ShaderError> {
+        Self::new("grayscale", "src/shaders/post-grayscale.glsl")
+    }
+    /// 3x3 convolution, `kernel` is row major with the top row first
+    pub fn kernel(name: &str, kernel: [f32; 9]) -> Result<Self, ShaderError> {
+        Ok(Self::new(name, "src/shaders/post-kernel.glsl")?
+            .with_param("kernel", EffectParam::Floats(kernel.to_vec())))
+    }
+    #[rustfmt::skip]
+    pub fn sharpen() -> Result<Self, ShaderError> {
+        Self::kernel("sharpen", [
+            -1.0, -1.0, -1.0,
+            -1.0,  9.0, -1.0,
+            -1.0, -1.0, -1.0,
+        ])
+    }
+    #[rustfmt::skip]
+    pub fn blur() -> Result<Self, ShaderError> {
+        Self::kernel("blur", [
+            1.0 / 16.0, 2.0 / 16.0, 1.0 / 16.0,
+            2.0 / 16.0, 4.0 / 16.0, 2.0 / 16.0,
+            1.0 / 16.0, 2.0 / 16.0, 1.0 / 16.0,
+        ])
+    }
+    #[rustfmt::skip]
+    pub fn edge_detection() -> Result<Self, ShaderError> {
+        Self::kernel("edge detection", [
+            1.0,  1.0, 1.0,
+            1.0, -8.0, 1.0,
+            1.0,  1.0, 1.0,
+        ])
+    }
+    /// Darkens the corners. The darkening starts at `radius` from the center, where the
+    /// corners are at 1, and reaches `strength` at the corners.
+    pub fn vignette(radius: f32, strength: f32) -> Result<Self, ShaderError> {
+        Ok(Self::new("vignette", "src/shaders/post-vignette.glsl")?
+            .with_param("radius", EffectParam::Float(radius))
+            .with_param("strength", EffectParam::Float(strength)))
+    }
+    /// Fast approximate anti-aliasing, on colors already encoded for the display
+    pub fn fxaa() -> Result<Self, ShaderError> {
+        Self::new("fxaa", "src/shaders/post-fxaa.glsl")
+    }
+    pub fn with_param(mut self, name: &str, value: EffectParam) -> Self {
+        self.set_param(name, value);
+        self
+    }
+    pub fn set_param(&mut self, name: &str, value: EffectParam) {
+        match self.params.iter_mut().find(|(param, _)| param == name) {
+            Some((_, param)) => *param = value,
+            None => self.params.push((name.to_string(), value)),
+        }
+    }
+    pub fn param(&self, name: &str) -> Option<&EffectParam> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value)
+    }
+    /// Uses the shader and sets its uniforms, the previous pass must be bound to unit 0
+    fn prepare(&self, texel_size: glm::Vec2) {
+        let shader = &self.shader;
+        shader.use_shader();
+        let _ = shader.set_uniform("screenTexture", 0);
+        let _ = shader.set_uniform("texelSize", texel_size);
+        for (name, value) in self.params.iter() {
+            let _ = match value {
+                EffectParam::Int(value) => shader.set_uniform(name, *value),
+                EffectParam::Float(value) => shader.set_uniform(name, *value),
+                EffectParam::Vec3(value) => shader.set_uniform(name, *value),
+                EffectParam::Floats(values) => shader.set_uniform_array(name, values.as_slice()),
+            };
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PostProcessError {
+    Framebuffer(FramebufferError),
+    Shader(ShaderError),
+}
+impl fmt::Display for PostProcessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PostProcessError::Framebuffer(error) => write!(f, "{}", error),
+            PostProcessError::Shader(error) => write!(f, "{}", error),
+        }
+    }
+}
+impl std::error::Error for PostProcessError {}
+impl From<FramebufferError> for PostProcessError {
+    fn from(error: FramebufferError) -> Self {
+        PostProcessError::Framebuffer(error)
+    }
+}
+impl From<ShaderError> for PostProcessError {
+    fn from(error: ShaderError) -> Self {
+        PostProcessError::Shader(error)
+    }
+}
+
+/// Effects applied in order to the scene, each reading the output of the previous enabled one.
+/// The scene is rendered to `scene_framebuffer`, an HDR target with depth and stencil, and
+/// `apply` draws the result to the default framebuffer.
+pub struct PostProcessChain {
+    effects: Vec<PostEffect>,
+    /// Tone mapping and gamma in one pass, run instead of the effects when none is enabled
+    encode: PostEffect,
+    scene: Framebuffer,
+    /// Intermediate targets, each pass reads one and writes the other
+    ping_pong: [Framebuffer; 2],
+    quad: ScreenQuad,
+}
+impl PostProcessChain {
+    pub fn new(width: u32, height: u32) -> Result<Self, PostProcessError> {
+        let descriptor = FramebufferDescriptor {
+            width,
+            height,
+            color_formats: vec![gl::RGBA16F],
+            depth: DepthAttachment::Renderbuffer,
+        };
+        let intermediate = FramebufferDescriptor {
+            depth: DepthAttachment::None,
+            ..descriptor.clone()
+        };
+        Ok(Self {
+            effects: vec![],
+            encode: PostEffect::new("encode", "src/shaders/post-encode.glsl")?,
+            scene: Framebuffer::new(descriptor)?,
+            ping_pong: [
+                Framebuffer::new(intermediate.clone())?,
+                Framebuffer::new(intermediate)?,
+            ],
+            quad: ScreenQuad::new(),
+        })
+    }
+    pub fn push(&mut self, effect: PostEffect) {
+        self.effects.push(effect);
+    }
+    pub fn effects(&self) -> &[PostEffect] {
+        &self.effects
+    }
+    pub fn effects_mut(&mut self) -> &mut [PostEffect] {
+        &mut self.effects
+    }
+    pub fn effect_mut(&mut self, name: &str) -> Option<&mut PostEffect> {
+        self.effects.iter_mut().find(|effect| effect.name == name)
+    }
+    /// Enables or disables the effect at `index`, returns whether it's now enabled
+    pub fn toggle(&mut self, index: usize) -> Option<bool> {
+        let effect = self.effects.get_mut(index)?;
+        effect.enabled = !effect.enabled;
+        Some(effect.enabled)
+    }
+    /// Bind it before rendering the scene
+    pub fn scene_framebuffer(&self) -> &Framebuffer {
+        &self.scene
+    }
+    /// Follows the size of the window, does nothing if it didn't change
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), FramebufferError> {
+        let (previous_width, previous_height) = (self.scene.width(), self.scene.height());
+        let result = self.resize_targets(width, height);
+        if result.is_err() {
+            // The targets resized before the failing one go back, they must all be the same size
+            let _ = self.resize_targets(previous_width, previous_height);
+        }
+        result
+    }
+    fn resize_targets(&mut self, width: u32, height: u32) -> Result<(), FramebufferError> {
+        self.scene.resize(width, height)?;
+        for target in self.ping_pong.iter_mut() {
+            target.resize(width, height)?;
+        }
+        Ok(())
+    }
+    /// Runs the enabled effects on the scene and draws the result to the default
+    /// framebuffer. With no effect enabled the scene is still tone mapped and gamma encoded,
+    /// its linear HDR colors can't be displayed as they are.
+    pub fn apply(&self) {
+        let (width, height) = (self.scene.width(), self.scene.height());
+        let texel_size = glm::vec2(1.0 / width as f32, 1.0 / height as f32);
+        let mut enabled: Vec<&PostEffect> = self.effects.iter().filter(|e| e.enabled).collect();
+        if enabled.is_empty() {
+            enabled.push(&self.encode);
+        }
+        unsafe { gl::Disable(gl::DEPTH_TEST) };
+
+        let mut source = &self.scene;
+        for (i, effect) in enabled.iter().enumerate() {
+            let last = i + 1 == enabled.len();
+            let target = &self.ping_pong[i % 2];
+            if last {
+                Framebuffer::unbind();
+                unsafe { gl::Viewport(0, 0, width as GLsizei, height as GLsizei) };
+            } else {
+                target.bind();
+            }
+            if let Some(texture) = source.color(0) {
+                texture.bind_to_unit(0);
+            }
+            effect.prepare(texel_size);
+            self.quad.draw();
+            source = target;
+        }
+        unsafe { gl::Enable(gl::DEPTH_TEST) };
+    }
+}
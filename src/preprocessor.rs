@@ -0,0 +1,273 @@
+use crate::shader::ShaderError;
+use crate::utils::normalize_path;
+use std::path::{Path, PathBuf};
+
+/// A shader source with all its includes resolved
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Preprocessed {
+    pub source: String,
+    /// Files that make up `source`, indexed by the source string number used in `#line`
+    /// directives (and reported back by the driver in its info log)
+    pub files: Vec<PathBuf>,
+}
+
+/// Resolves `#include "file.glsl"` relative to the including file and injects `defines` right
+/// after the `#version` directive. `read` loads a file, which keeps this free of any GL or
+/// filesystem state: pass `|path| std::fs::read_to_string(path)` for real shaders.
+///
+/// `#line` directives are emitted around every include, using the GLSL 3.30+ meaning of
+/// `#line <line> <file>`: the line following the directive is `<line>`.
+pub fn preprocess<F>(
+    path: impl AsRef<Path>,
+    defines: &[(&str, &str)],
+    mut read: F,
+) -> Result<Preprocessed, ShaderError>
+where
+    F: FnMut(&Path) -> std::io::Result<String>,
+{
+    let mut preprocessed = Preprocessed {
+        source: String::new(),
+        files: vec![],
+    };
+    let mut stack = vec![];
+    expand(
+        &normalize_path(path.as_ref()),
+        Some(defines),
+        &mut stack,
+        &mut preprocessed,
+        &mut read,
+    )?;
+    Ok(preprocessed)
+}
+
+fn expand<F>(
+    path: &Path,
+    // Only set for the root file, included files can't change the header
+    mut defines: Option<&[(&str, &str)]>,
+    stack: &mut Vec<PathBuf>,
+    out: &mut Preprocessed,
+    read: &mut F,
+) -> Result<(), ShaderError>
+where
+    F: FnMut(&Path) -> std::io::Result<String>,
+{
+    let code = read(path).map_err(|source| ShaderError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let file_id = match out.files.iter().position(|file| file == path) {
+        Some(id) => id,
+        None => {
+            out.files.push(path.to_path_buf());
+            out.files.len() - 1
+        }
+    };
+    stack.push(path.to_path_buf());
+
+    let is_root = defines.is_some();
+    if !is_root {
+        out.source.push_str(&format!("#line 1 {}\n", file_id));
+    }
+    // Defines go after `#version`, or at the very top when there is none
+    if is_root && !code.lines().any(|line| is_directive(line, "version")) {
+        push_defines(out, defines.take().unwrap_or_default(), 1, file_id);
+    }
+
+    for (i, line) in code.lines().enumerate() {
+        let line_nr = i + 1;
+        if is_directive(line, "include") {
+            let name = parse_include(line).ok_or_else(|| ShaderError::Preprocess {
+                path: path.to_path_buf(),
+                line: line_nr as u32,
+                message: format!("malformed include `{}`", line.trim()),
+            })?;
+            let include = normalize_path(&path.parent().unwrap_or(Path::new("")).join(name));
+            if stack.contains(&include) {
+                let mut chain = stack.clone();
+                chain.push(include);
+                return Err(ShaderError::IncludeCycle { chain });
+            }
+            expand(&include, None, stack, out, read)?;
+            out.source
+                .push_str(&format!("#line {} {}\n", line_nr + 1, file_id));
+        } else if is_directive(line, "version") {
+            if let Some(defines) = defines.take() {
+                out.source.push_str(line);
+                out.source.push('\n');
+                push_defines(out, defines, line_nr + 1, file_id);
+            } else {
+                // Included files may carry their own `#version` so editors can lint them
+                out.source.push('\n');
+            }
+        } else {
+            out.source.push_str(line);
+            out.source.push('\n');
+        }
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+fn push_defines(
+    out: &mut Preprocessed,
+    defines: &[(&str, &str)],
+    next_line: usize,
+    file_id: usize,
+) {
+    if defines.is_empty() {
+        return;
+    }
+    for (name, value) in defines {
+        out.source
+            .push_str(&format!("#define {} {}\n", name, value));
+    }
+    out.source
+        .push_str(&format!("#line {} {}\n", next_line, file_id));
+}
+
+/// Whether `line` is `#<name>`, allowing whitespace around the `#`
+fn is_directive(line: &str, name: &str) -> bool {
+    line.trim_start()
+        .strip_prefix('#')
+        .map(|rest| rest.trim_start())
+        .and_then(|rest| rest.strip_prefix(name))
+        .map_or(false, |rest| {
+            rest.is_empty() || rest.starts_with(char::is_whitespace)
+        })
+}
+
+/// Extracts `file.glsl` from `#include "file.glsl"`
+fn parse_include(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix('#')?.trim_start();
+    let rest = rest.strip_prefix("include")?.trim();
+    let name = rest.strip_prefix('"')?.strip_suffix('"')?;
+    (!name.is_empty()).then_some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io;
+
+    /// Reads from `files` instead of the disk
+    fn reader(files: &[(&str, &str)]) -> impl FnMut(&Path) -> io::Result<String> {
+        let files: HashMap<PathBuf, String> = files
+            .iter()
+            .map(|(path, code)| (PathBuf::from(path), code.to_string()))
+            .collect();
+        move |path| {
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        }
+    }
+
+    #[test]
+    fn nested_includes_are_relative_to_the_including_file() {
+        let read = reader(&[
+            (
+                "shaders/main.glsl",
+                "#version 330 core\n#include \"lib/a.glsl\"\nvoid main() {}\n",
+            ),
+            (
+                "shaders/lib/a.glsl",
+                "#include \"../common/b.glsl\"\nfloat a;\n",
+            ),
+            ("shaders/common/b.glsl", "float b;\n"),
+        ]);
+        let preprocessed = preprocess("shaders/main.glsl", &[], read).unwrap();
+        assert_eq!(
+            preprocessed.files,
+            vec![
+                PathBuf::from("shaders/main.glsl"),
+                PathBuf::from("shaders/lib/a.glsl"),
+                PathBuf::from("shaders/common/b.glsl"),
+            ]
+        );
+        assert_eq!(
+            preprocessed.source,
+            "#version 330 core\n\
+             #line 1 1\n\
+             #line 1 2\n\
+             float b;\n\
+             #line 2 1\n\
+             float a;\n\
+             #line 3 0\n\
+             void main() {}\n"
+        );
+    }
+
+    #[test]
+    fn include_cycles_are_reported() {
+        let read = reader(&[
+            ("main.glsl", "#include \"a.glsl\"\n"),
+            ("a.glsl", "#include \"b.glsl\"\n"),
+            ("b.glsl", "#include \"a.glsl\"\n"),
+        ]);
+        match preprocess("main.glsl", &[], read) {
+            Err(ShaderError::IncludeCycle { chain }) => assert_eq!(
+                chain,
+                ["main.glsl", "a.glsl", "b.glsl", "a.glsl"].map(PathBuf::from)
+            ),
+            other => panic!("expected an include cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn defines_follow_the_version() {
+        let read = reader(&[(
+            "main.glsl",
+            "// header\n#version 330 core\nvoid main() {}\n",
+        )]);
+        let defines = [("MAX_LIGHTS", "4"), ("SHADOWS", "1")];
+        let preprocessed = preprocess("main.glsl", &defines, read).unwrap();
+        assert_eq!(
+            preprocessed.source,
+            "// header\n\
+             #version 330 core\n\
+             #define MAX_LIGHTS 4\n\
+             #define SHADOWS 1\n\
+             #line 3 0\n\
+             void main() {}\n"
+        );
+
+        // Without `#version`, at the very top
+        let read = reader(&[("main.glsl", "void main() {}\n")]);
+        let preprocessed = preprocess("main.glsl", &defines[..1], read).unwrap();
+        assert_eq!(
+            preprocessed.source,
+            "#define MAX_LIGHTS 4\n#line 1 0\nvoid main() {}\n"
+        );
+    }
+
+    #[test]
+    fn line_numbers_resume_after_an_include() {
+        let read = reader(&[
+            (
+                "main.glsl",
+                "#version 330 core\nfloat x;\n#include \"lib.glsl\"\nfloat y;\n#include \"lib.glsl\"\n",
+            ),
+            ("lib.glsl", "#version 330 core\nfloat lib;\n"),
+        ]);
+        let preprocessed = preprocess("main.glsl", &[], read).unwrap();
+        // The same file keeps its id, its own `#version` is blanked
+        assert_eq!(preprocessed.files.len(), 2);
+        assert_eq!(
+            preprocessed.source,
+            "#version 330 core\n\
+             float x;\n\
+             #line 1 1\n\
+             \n\
+             float lib;\n\
+             #line 4 0\n\
+             float y;\n\
+             #line 1 1\n\
+             \n\
+             float lib;\n\
+             #line 6 0\n"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+extern crate gl;
+use crate::shader::ShaderStage;
+use gl::types::*;
+use std::ffi::CStr;
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// On-disk cache of linked program binaries, so startup doesn't compile every shader again.
+/// Entries are keyed by the preprocessed sources and the driver, a driver update or a changed
+/// `#include` simply misses the cache. Binaries the driver rejects are deleted and the program
+/// is compiled from source.
+#[derive(Clone, Debug)]
+pub struct ProgramCache {
+    pub dir: PathBuf,
+    /// Oldest entries are removed once the cache grows past this size
+    pub max_bytes: u64,
+    pub enabled: bool,
+}
+impl Default for ProgramCache {
+    fn default() -> Self {
+        Self {
+            dir: std::env::temp_dir().join("opengl-rust-shader-cache"),
+            max_bytes: 64 * 1024 * 1024,
+            enabled: true,
+        }
+    }
+}
+
+impl ProgramCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            ..Self::default()
+        }
+    }
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+    /// Whether the cache is enabled and the driver can hand out program binaries
+    pub fn is_usable(&self) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let mut formats = 0;
+        unsafe { gl::GetIntegerv(gl::NUM_PROGRAM_BINARY_FORMATS, &mut formats) };
+        formats > 0
+    }
+    /// Key of a program made of the given preprocessed stages on the current driver
+    pub fn key(&self, sources: &[(ShaderStage, &str)]) -> u64 {
+        let mut hash = Fnv1a::new();
+        hash.write(driver_id().as_bytes());
+        for (stage, source) in sources {
+            hash.write(&stage.gl_enum().to_le_bytes());
+            hash.write(&source.len().to_le_bytes());
+            hash.write(source.as_bytes());
+        }
+        hash.0
+    }
+    /// Creates a program from the cached binary, if there is one and the driver accepts it
+    pub fn load(&self, key: u64) -> Option<GLuint> {
+        let path = self.entry_path(key);
+        let bytes = fs::read(&path).ok()?;
+        if bytes.len() < 4 {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        let (format, binary) = bytes.split_at(4);
+        let format = GLenum::from_le_bytes(format.try_into().unwrap());
+
+        unsafe {
+            let program = gl::CreateProgram();
+            gl::ProgramBinary(
+                program,
+                format,
+                binary.as_ptr() as *const _,
+                binary.len() as GLsizei,
+            );
+            let mut success = gl::FALSE as GLint;
+            gl::GetProgramiv(program, gl::LINK_STATUS, &mut success);
+            if success == gl::FALSE as GLint {
+                gl::DeleteProgram(program);
+                let _ = fs::remove_file(&path);
+                return None;
+            }
+            // Bumps the entry for eviction
+            if let Ok(file) = fs::File::options().append(true).open(&path) {
+                let _ = file.set_modified(SystemTime::now());
+            }
+            Some(program)
+        }
+    }
+    /// Saves the binary of a linked program. It must have been linked with
+    /// `PROGRAM_BINARY_RETRIEVABLE_HINT` set. Failures only mean a cache miss next time and are
+    /// ignored.
+    pub fn store(&self, key: u64, program: GLuint) {
+        let bytes = unsafe {
+            let mut len = 0;
+            gl::GetProgramiv(program, gl::PROGRAM_BINARY_LENGTH, &mut len);
+            if len <= 0 {
+                return;
+            }
+            let mut bytes = vec![0u8; 4 + len as usize];
+            let mut format: GLenum = 0;
+            gl::GetProgramBinary(
+                program,
+                len,
+                &mut len,
+                &mut format,
+                bytes[4..].as_mut_ptr() as *mut _,
+            );
+            bytes[..4].copy_from_slice(&format.to_le_bytes());
+            bytes.truncate(4 + len as usize);
+            bytes
+        };
+        if fs::create_dir_all(&self.dir).is_err() {
+            return;
+        }
+        // Written aside then renamed so a crash never leaves a truncated entry
+        let path = self.entry_path(key);
+        let tmp = path.with_extension("tmp");
+        if fs::write(&tmp, &bytes).is_ok() && fs::rename(&tmp, &path).is_ok() {
+            self.evict();
+        }
+    }
+    /// Removes every cached binary
+    pub fn clear(&self) -> std::io::Result<()> {
+        for (path, _, _) in self.entries() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+    /// Removes the least recently used entries until the cache fits in `max_bytes`
+    fn evict(&self) {
+        let mut entries = self.entries();
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort_by_key(|(_, _, used)| *used);
+        for (path, size, _) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= size;
+            }
+        }
+    }
+    fn entries(&self) -> Vec<(PathBuf, u64, SystemTime)> {
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return vec![];
+        };
+        dir.filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().map_or(false, |ext| ext == "bin"))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((entry.path(), metadata.len(), metadata.modified().ok()?))
+            })
+            .collect()
+    }
+    fn entry_path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.bin", key))
+    }
+}
+
+/// Vendor, renderer and version of the current context, binaries are only valid for the exact
+/// same driver
+fn driver_id() -> String {
+    let get = |name| unsafe {
+        let value = gl::GetString(name);
+        if value.is_null() {
+            String::new()
+        } else {
+            CStr::from_ptr(value as *const _)
+                .to_string_lossy()
+                .into_owned()
+        }
+    };
+    format!(
+        "{}|{}|{}",
+        get(gl::VENDOR),
+        get(gl::RENDERER),
+        get(gl::VERSION)
+    )
+}
+
+/// 64-bit FNV-1a, used over `DefaultHasher` because keys must stay the same across builds
+pub(crate) struct Fnv1a(pub u64);
+impl Fnv1a {
+    pub fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+    pub fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
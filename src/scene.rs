@@ -0,0 +1,276 @@
+use std::cell::Cell;
+
+/// Index of a node in its scene, nodes are numbered in the order they were added
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(pub usize);
+
+/// Translation, rotation and scale, applied in reverse order
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: glm::Vec3,
+    pub rotation: glm::Quat,
+    pub scale: glm::Vec3,
+}
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: glm::Vec3::zeros(),
+            rotation: glm::Quat::identity(),
+            scale: glm::vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+impl Transform {
+    pub fn from_translation(translation: glm::Vec3) -> Self {
+        Self {
+            translation,
+            ..Self::default()
+        }
+    }
+    /// Splits an affine matrix into its parts. Shearing can't be represented and is lost.
+    pub fn from_matrix(matrix: &glm::Mat4) -> Self {
+        let column = |i: usize| glm::vec3(matrix[(0, i)], matrix[(1, i)], matrix[(2, i)]);
+        let mut scale = glm::vec3(column(0).norm(), column(1).norm(), column(2).norm());
+        // A mirrored basis is stored as a negative scale on x
+        if glm::determinant(&glm::mat4_to_mat3(matrix)) < 0.0 {
+            scale.x = -scale.x;
+        }
+        let mut rotation = glm::Mat4::identity();
+        for i in 0..3 {
+            let axis = if scale[i] == 0.0 {
+                glm::Vec3::zeros()
+            } else {
+                column(i) / scale[i]
+            };
+            rotation.fixed_view_mut::<3, 1>(0, i).copy_from(&axis);
+        }
+        Self {
+            translation: column(3),
+            rotation: glm::to_quat(&rotation),
+            scale,
+        }
+    }
+    pub fn matrix(&self) -> glm::Mat4 {
+        glm::translation(&self.translation)
+            * glm::quat_to_mat4(&self.rotation)
+            * glm::scaling(&self.scale)
+    }
+}
+
+pub struct SceneNode {
+    pub name: String,
+    transform: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    /// Meshes drawn at this node, indices into the meshes of the owner of the scene. A node may
+    /// have several, one per material.
+    pub meshes: Vec<usize>,
+    /// Index into the lights of the renderer
+    pub light: Option<usize>,
+    /// Index into the cameras of the application
+    pub camera: Option<usize>,
+    /// Cached, recomputed when the node or one of its ancestors moved
+    world: Cell<glm::Mat4>,
+    dirty: Cell<bool>,
+}
+impl SceneNode {
+    /// Relative to the parent node
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+/// Hierarchy of nodes, each placed relative to its parent
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<SceneNode>,
+    roots: Vec<NodeId>,
+}
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a node without attachments, as a root when `parent` is `None`
+    pub fn add_node(
+        &mut self,
+        name: impl Into<String>,
+        transform: Transform,
+        parent: Option<NodeId>,
+    ) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(SceneNode {
+            name: name.into(),
+            transform,
+            parent,
+            children: vec![],
+            meshes: vec![],
+            light: None,
+            camera: None,
+            world: Cell::new(glm::Mat4::identity()),
+            dirty: Cell::new(true),
+        });
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        id
+    }
+    pub fn node(&self, id: NodeId) -> &SceneNode {
+        &self.nodes[id.0]
+    }
+    /// The transform can't be changed through the node, use `set_transform`
+    pub fn node_mut(&mut self, id: NodeId) -> &mut SceneNode {
+        &mut self.nodes[id.0]
+    }
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+    /// Every node, in the order they were added. Parents come before their children unless
+    /// nodes were moved with `set_parent`.
+    pub fn ids(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.nodes.len()).map(NodeId)
+    }
+    /// First node with the given name
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .position(|node| node.name == name)
+            .map(NodeId)
+    }
+    pub fn set_transform(&mut self, id: NodeId, transform: Transform) {
+        self.nodes[id.0].transform = transform;
+        self.mark_dirty(id);
+    }
+    /// Moves a node and its descendants under `parent`, or to the roots. The local transform is
+    /// kept, so the node moves with its new parent. Returns false and leaves the scene as it is
+    /// if `parent` is the node itself or one of its descendants.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> bool {
+        let mut ancestor = parent;
+        while let Some(node) = ancestor {
+            if node == id {
+                return false;
+            }
+            ancestor = self.nodes[node.0].parent;
+        }
+        match self.nodes[id.0].parent {
+            Some(old) => self.nodes[old.0].children.retain(|child| *child != id),
+            None => self.roots.retain(|root| *root != id),
+        }
+        match parent {
+            Some(parent) => self.nodes[parent.0].children.push(id),
+            None => self.roots.push(id),
+        }
+        self.nodes[id.0].parent = parent;
+        self.mark_dirty(id);
+        true
+    }
+    /// The world transform of every descendant depends on the one of `id`
+    fn mark_dirty(&self, id: NodeId) {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let node = &self.nodes[id.0];
+            node.dirty.set(true);
+            stack.extend_from_slice(&node.children);
+        }
+    }
+    /// Transform from the node to the root of the scene
+    pub fn world_transform(&self, id: NodeId) -> glm::Mat4 {
+        let node = &self.nodes[id.0];
+        if node.dirty.get() {
+            let local = node.transform.matrix();
+            let world = match node.parent {
+                Some(parent) => self.world_transform(parent) * local,
+                None => local,
+            };
+            node.world.set(world);
+            node.dirty.set(false);
+        }
+        node.world.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translation(world: glm::Mat4) -> glm::Vec3 {
+        glm::vec3(world[(0, 3)], world[(1, 3)], world[(2, 3)])
+    }
+
+    /// `root` at x = 1 holding `arm` at y = 1 holding `hand` at z = 1, and `other` at x = 10
+    fn scene() -> (Scene, [NodeId; 4]) {
+        let mut scene = Scene::new();
+        let at = |x, y, z| Transform::from_translation(glm::vec3(x, y, z));
+        let root = scene.add_node("root", at(1.0, 0.0, 0.0), None);
+        let arm = scene.add_node("arm", at(0.0, 1.0, 0.0), Some(root));
+        let hand = scene.add_node("hand", at(0.0, 0.0, 1.0), Some(arm));
+        let other = scene.add_node("other", at(10.0, 0.0, 0.0), None);
+        (scene, [root, arm, hand, other])
+    }
+
+    #[test]
+    fn moving_an_ancestor_moves_the_descendants() {
+        let (mut scene, [root, _, hand, _]) = scene();
+        assert_eq!(
+            translation(scene.world_transform(hand)),
+            glm::vec3(1.0, 1.0, 1.0)
+        );
+
+        let scaled = Transform {
+            scale: glm::vec3(2.0, 2.0, 2.0),
+            ..*scene.node(root).transform()
+        };
+        scene.set_transform(root, scaled);
+        // Attachments can change without invalidating the cache
+        scene.node_mut(root).meshes.push(0);
+        assert_eq!(
+            translation(scene.world_transform(hand)),
+            glm::vec3(1.0, 2.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn reparenting_moves_the_subtree() {
+        let (mut scene, [root, arm, hand, other]) = scene();
+        scene.world_transform(hand);
+
+        assert!(scene.set_parent(arm, Some(other)));
+        assert_eq!(scene.node(root).children(), &[]);
+        assert_eq!(scene.node(other).children(), &[arm]);
+        assert_eq!(scene.node(arm).parent(), Some(other));
+        assert_eq!(
+            translation(scene.world_transform(hand)),
+            glm::vec3(10.0, 1.0, 1.0)
+        );
+
+        assert!(scene.set_parent(arm, None));
+        assert_eq!(scene.roots(), &[root, other, arm]);
+        assert_eq!(
+            translation(scene.world_transform(hand)),
+            glm::vec3(0.0, 1.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn nodes_cant_be_moved_under_themselves() {
+        let (mut scene, [root, arm, hand, _]) = scene();
+        assert!(!scene.set_parent(arm, Some(hand)));
+        assert!(!scene.set_parent(arm, Some(arm)));
+        assert_eq!(scene.node(arm).parent(), Some(root));
+        assert_eq!(scene.node(hand).children(), &[]);
+    }
+
+    #[test]
+    fn find_returns_the_first_node_with_the_name() {
+        let (mut scene, [_, arm, _, _]) = scene();
+        scene.add_node("arm", Transform::default(), None);
+        assert_eq!(scene.find("arm"), Some(arm));
+        assert_eq!(scene.find("leg"), None);
+    }
+}
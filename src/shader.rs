@@ -1,214 +1,901 @@
 extern crate gl;
+use crate::gl_objects::Program;
+use crate::preprocessor::{preprocess, Preprocessed};
+use crate::program_cache::ProgramCache;
+use crate::uniform::{is_sampler, Uniform, UniformError, UniformInfo};
 use crate::utils::to_c_str;
 use gl::types::*;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::{c_char, CStr};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
 pub struct Shader {
-    pub id: u32,
+    program: Program,
+    uniforms: HashMap<String, UniformInfo>,
+    /// Binding point of each uniform block, replayed when the program is rebuilt
+    block_bindings: RefCell<HashMap<String, GLuint>>,
+    stages: Vec<(ShaderStage, ShaderSource)>,
+    defines: Vec<(String, String)>,
+    cache: Option<ProgramCache>,
+    /// Every file the program was built from, includes too, and the ones read by failed reloads
+    /// since
+    files: Vec<PathBuf>,
 }
+
 impl Shader {
+    pub fn id(&self) -> GLuint {
+        self.program.id()
+    }
     pub fn use_shader(&self) -> () {
         unsafe {
-            gl::UseProgram(self.id);
+            gl::UseProgram(self.program.id());
         }
     }
-    pub fn set_bool(&self, name: &str, value: bool) -> () {
-        unsafe {
-            gl::Uniform1i(
-                gl::GetUniformLocation(self.id, to_c_str(name).as_ptr()),
-                value as i32,
-            );
+    /// Sets a uniform of the program, which must be in use.
+    /// Fails if `name` is not an active uniform or `T` doesn't match its GLSL type.
+    pub fn set_uniform<T: Uniform>(&self, name: &str, value: T) -> Result<(), UniformError> {
+        self.set_uniform_array(name, std::slice::from_ref(&value))
+    }
+    /// Sets `values.len()` consecutive elements of a uniform array, starting at `name`
+    /// (either `lights` or `lights[2]`)
+    pub fn set_uniform_array<T: Uniform>(
+        &self,
+        name: &str,
+        values: &[T],
+    ) -> Result<(), UniformError> {
+        let info = self.uniform(name)?;
+        if !T::accepts(info.gl_type) {
+            return Err(UniformError::TypeMismatch {
+                name: name.to_string(),
+                expected: info.gl_type,
+                found: T::GL_TYPE,
+            });
         }
+        if values.len() > info.size as usize {
+            return Err(UniformError::OutOfBounds {
+                name: name.to_string(),
+                size: info.size,
+                len: values.len(),
+            });
+        }
+        unsafe { T::upload(info.location, values) }
+        info.written.set(true);
+        Ok(())
+    }
+    pub fn uniform(&self, name: &str) -> Result<&UniformInfo, UniformError> {
+        self.uniforms
+            .get(name)
+            .ok_or_else(|| UniformError::Unknown(name.to_string()))
+    }
+    pub fn has_uniform(&self, name: &str) -> bool {
+        self.uniforms.contains_key(name)
     }
-    pub fn set_int(&self, name: &str, value: i32) -> () {
+    /// Size in bytes of the uniform block `name`, as laid out by the driver. Fails if the block
+    /// isn't active.
+    pub fn uniform_block_size(&self, name: &str) -> Result<usize, UniformError> {
+        let program = self.program.id();
+        let mut size = 0;
         unsafe {
-            gl::Uniform1i(
-                gl::GetUniformLocation(self.id, to_c_str(name).as_ptr()),
-                value,
-            );
+            let index = gl::GetUniformBlockIndex(program, to_c_str(name).as_ptr());
+            if index == gl::INVALID_INDEX {
+                return Err(UniformError::Unknown(name.to_string()));
+            }
+            gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_DATA_SIZE, &mut size);
         }
+        Ok(size as usize)
     }
-    pub fn set_float(&self, name: &str, value: f32) -> () {
-        unsafe {
-            gl::Uniform1f(
-                gl::GetUniformLocation(self.id, to_c_str(name).as_ptr()),
-                value,
-            );
+    /// Reads the uniform block `name` from the buffer bound to `binding` with
+    /// `gl::BindBufferBase`. Fails if the block isn't active.
+    pub fn bind_uniform_block(&self, name: &str, binding: GLuint) -> Result<(), UniformError> {
+        if !bind_block(self.program.id(), name, binding) {
+            return Err(UniformError::Unknown(name.to_string()));
         }
+        self.block_bindings
+            .borrow_mut()
+            .insert(name.to_string(), binding);
+        Ok(())
     }
-    pub fn set_vec2_g(&self, name: &str, value: &glm::Vec2) {
+    /// Queries every active uniform of a linked program. Array elements get their own
+    /// entry (`lights[1]`), with `size` being the number of elements left from there.
+    fn reflect_uniforms(program: GLuint) -> HashMap<String, UniformInfo> {
+        let mut uniforms = HashMap::new();
         unsafe {
-            gl::Uniform2fv(
-                gl::GetUniformLocation(self.id, to_c_str(name).as_ptr()),
-                1,
-                &value[0],
-            )
+            let mut count = 0;
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+            let mut max_len = 0;
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_len);
+
+            let mut name_buf: Vec<u8> = vec![0; max_len.max(1) as usize];
+            for index in 0..count as GLuint {
+                let (mut len, mut size, mut gl_type) = (0, 0, 0);
+                gl::GetActiveUniform(
+                    program,
+                    index,
+                    name_buf.len() as GLsizei,
+                    &mut len,
+                    &mut size,
+                    &mut gl_type,
+                    name_buf.as_mut_ptr() as *mut GLchar,
+                );
+                let name = String::from_utf8_lossy(&name_buf[..len as usize]).into_owned();
+                // Arrays are reported as `name[0]`
+                let base = name.strip_suffix("[0]").unwrap_or(&name).to_string();
+
+                for i in 0..size {
+                    let element = if size > 1 || base != name {
+                        format!("{}[{}]", base, i)
+                    } else {
+                        base.clone()
+                    };
+                    let location = gl::GetUniformLocation(program, to_c_str(&element).as_ptr());
+                    // Uniforms inside uniform blocks have no location
+                    if location < 0 {
+                        continue;
+                    }
+                    let info = UniformInfo {
+                        location,
+                        gl_type,
+                        size: size - i,
+                        written: Cell::new(false),
+                    };
+                    if i == 0 && element != base {
+                        uniforms.insert(base.clone(), info.clone());
+                    }
+                    uniforms.insert(element, info);
+                }
+            }
         }
+        uniforms
+    }
+    pub fn new(
+        vertex_path: impl AsRef<Path>,
+        fragment_path: impl AsRef<Path>,
+    ) -> Result<Self, ShaderError> {
+        Self::with_defines(vertex_path, fragment_path, &[])
+    }
+    /// Like `new`, with `#define <name> <value>` injected in both stages
+    pub fn with_defines(
+        vertex_path: impl AsRef<Path>,
+        fragment_path: impl AsRef<Path>,
+        defines: &[(&str, &str)],
+    ) -> Result<Self, ShaderError> {
+        defines
+            .iter()
+            .fold(ShaderBuilder::new(), |builder, (name, value)| {
+                builder.define(name, value)
+            })
+            .vertex(vertex_path.as_ref())
+            .fragment(fragment_path.as_ref())
+            .build()
+    }
+    pub fn is_compute(&self) -> bool {
+        self.stages
+            .iter()
+            .any(|(stage, _)| *stage == ShaderStage::Compute)
     }
-    pub fn set_vec2_f(&self, name: &str, x: f32, y: f32) {
+    /// Uses the program and launches `x * y * z` work groups. Synchronising with whatever reads
+    /// the results (`gl::MemoryBarrier`) is left to the caller. Compute programs can only be
+    /// built on contexts supporting them, see `ShaderStage::is_supported`. Fails without
+    /// dispatching anything if the program has no compute stage.
+    pub fn dispatch_compute(&self, x: u32, y: u32, z: u32) -> Result<(), ShaderError> {
+        if !self.is_compute() {
+            return Err(ShaderError::InvalidStages(
+                "dispatching a program without a compute stage",
+            ));
+        }
         unsafe {
-            gl::Uniform2f(
-                gl::GetUniformLocation(self.id, to_c_str(name).as_ptr()),
-                x,
-                y,
-            );
+            gl::UseProgram(self.program.id());
+            gl::DispatchCompute(x, y, z);
         }
+        Ok(())
+    }
+    /// Source files of the program, including the ones pulled in by `#include`. After a failed
+    /// reload it also lists the files that attempt read, a new include among them.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
     }
-    pub fn set_vec3_g(&self, name: &str, value: &glm::Vec3) {
+    /// Rebuilds the program from its source files. The old program is only replaced if the new
+    /// one links, otherwise it stays in place and the error is returned.
+    /// Uniform values set so far are read back from the old program and written to the new one,
+    /// the others keep the initializers of the new source. The new program is put in use if the
+    /// old one was.
+    pub fn reload(&mut self) -> Result<(), ShaderError> {
+        let mut files = vec![];
+        let program_id =
+            match build_program(&self.stages, &self.defines, self.cache.as_ref(), &mut files) {
+                Ok(program_id) => program_id,
+                Err(error) => {
+                    // Still watched, fixing an error in a newly included file must be noticed
+                    merge_files(&mut self.files, files);
+                    return Err(error);
+                }
+            };
+        let uniforms = Self::reflect_uniforms(program_id);
+
         unsafe {
-            gl::Uniform3fv(
-                gl::GetUniformLocation(self.id, to_c_str(name).as_ptr()),
-                1,
-                &value[0],
-            )
+            let mut current = 0;
+            gl::GetIntegerv(gl::CURRENT_PROGRAM, &mut current);
+
+            gl::UseProgram(program_id);
+            for (name, old) in self.uniforms.iter() {
+                if !old.written.get() {
+                    continue;
+                }
+                // Uniforms removed from the source or whose type changed are dropped
+                if let Some(new) = uniforms.get(name) {
+                    if new.gl_type == old.gl_type {
+                        // Array elements have consecutive locations
+                        for i in 0..old.size.min(new.size) {
+                            let (from, to) = (old.location + i, new.location + i);
+                            copy_uniform(self.program.id(), from, to, new.gl_type);
+                        }
+                        new.written.set(true);
+                    }
+                }
+            }
+            for (name, binding) in self.block_bindings.borrow().iter() {
+                bind_block(program_id, name, *binding);
+            }
+            let was_current = current as GLuint == self.program.id();
+            gl::UseProgram(if was_current {
+                program_id
+            } else {
+                current as GLuint
+            });
         }
+        // Drops the old program
+        self.program = Program::from_raw(program_id);
+        self.uniforms = uniforms;
+        self.files = files;
+        Ok(())
     }
-    pub fn set_vec3_f(&self, name: &str, x: f32, y: f32, z: f32) {
-        unsafe {
-            gl::Uniform3f(
-                gl::GetUniformLocation(self.id, to_c_str(name).as_ptr()),
-                x,
-                y,
-                z,
-            );
+}
+
+/// Writes the value of the uniform at `from` in `program` to the uniform at `to` of the program
+/// in use. Both are a single element of type `gl_type`.
+unsafe fn copy_uniform(program: GLuint, from: GLint, to: GLint, gl_type: GLenum) {
+    // Large enough for a mat4
+    let mut floats = [0f32; 16];
+    let mut ints = [0i32; 4];
+    let mut uints = [0u32; 4];
+    match gl_type {
+        gl::FLOAT | gl::FLOAT_VEC2 | gl::FLOAT_VEC3 | gl::FLOAT_VEC4 => {
+            gl::GetUniformfv(program, from, floats.as_mut_ptr());
+            match gl_type {
+                gl::FLOAT => gl::Uniform1fv(to, 1, floats.as_ptr()),
+                gl::FLOAT_VEC2 => gl::Uniform2fv(to, 1, floats.as_ptr()),
+                gl::FLOAT_VEC3 => gl::Uniform3fv(to, 1, floats.as_ptr()),
+                _ => gl::Uniform4fv(to, 1, floats.as_ptr()),
+            }
+        }
+        gl::FLOAT_MAT2 | gl::FLOAT_MAT3 | gl::FLOAT_MAT4 => {
+            gl::GetUniformfv(program, from, floats.as_mut_ptr());
+            match gl_type {
+                gl::FLOAT_MAT2 => gl::UniformMatrix2fv(to, 1, gl::FALSE, floats.as_ptr()),
+                gl::FLOAT_MAT3 => gl::UniformMatrix3fv(to, 1, gl::FALSE, floats.as_ptr()),
+                _ => gl::UniformMatrix4fv(to, 1, gl::FALSE, floats.as_ptr()),
+            }
         }
+        gl::UNSIGNED_INT => {
+            gl::GetUniformuiv(program, from, uints.as_mut_ptr());
+            gl::Uniform1uiv(to, 1, uints.as_ptr());
+        }
+        gl::INT_VEC2 | gl::BOOL_VEC2 => {
+            gl::GetUniformiv(program, from, ints.as_mut_ptr());
+            gl::Uniform2iv(to, 1, ints.as_ptr());
+        }
+        gl::INT_VEC3 | gl::BOOL_VEC3 => {
+            gl::GetUniformiv(program, from, ints.as_mut_ptr());
+            gl::Uniform3iv(to, 1, ints.as_ptr());
+        }
+        gl::INT_VEC4 | gl::BOOL_VEC4 => {
+            gl::GetUniformiv(program, from, ints.as_mut_ptr());
+            gl::Uniform4iv(to, 1, ints.as_ptr());
+        }
+        // Booleans and samplers are set through the integer entry points
+        t if t == gl::INT || t == gl::BOOL || is_sampler(t) => {
+            gl::GetUniformiv(program, from, ints.as_mut_ptr());
+            gl::Uniform1iv(to, 1, ints.as_ptr());
+        }
+        // Types without a `Uniform` implementation can't have been set
+        _ => {}
     }
-    pub fn set_vec4_g(&self, name: &str, value: &glm::Vec4) {
-        unsafe {
-            gl::Uniform4fv(
-                gl::GetUniformLocation(self.id, to_c_str(name).as_ptr()),
-                1,
-                &value[0],
-            )
+}
+
+/// Returns false if the program has no active block `name`
+fn bind_block(program: GLuint, name: &str, binding: GLuint) -> bool {
+    unsafe {
+        let index = gl::GetUniformBlockIndex(program, to_c_str(name).as_ptr());
+        if index == gl::INVALID_INDEX {
+            return false;
         }
+        gl::UniformBlockBinding(program, index, binding);
+        true
     }
-    pub fn set_vec4_f(&self, name: &str, x: f32, y: f32, z: f32, w: f32) {
-        unsafe {
-            gl::Uniform4f(
-                gl::GetUniformLocation(self.id, to_c_str(name).as_ptr()),
-                x,
-                y,
-                z,
-                w,
-            );
+}
+
+/// Builds a `Shader` out of any combination of graphics stages, or a single compute stage.
+/// Each stage comes from a file or from a string:
+/// ```ignore
+/// let normals = ShaderBuilder::new()
+///     .vertex("src/shaders/vertex.glsl")
+///     .geometry(ShaderSource::code("normals.geom", NORMALS_GEOMETRY))
+///     .fragment("src/shaders/fragment-single.glsl")
+///     .build()?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ShaderBuilder {
+    stages: Vec<(ShaderStage, ShaderSource)>,
+    defines: Vec<(String, String)>,
+    cache: Option<ProgramCache>,
+}
+impl ShaderBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn stage(mut self, stage: ShaderStage, source: impl Into<ShaderSource>) -> Self {
+        self.stages.push((stage, source.into()));
+        self
+    }
+    pub fn vertex(self, source: impl Into<ShaderSource>) -> Self {
+        self.stage(ShaderStage::Vertex, source)
+    }
+    pub fn tess_control(self, source: impl Into<ShaderSource>) -> Self {
+        self.stage(ShaderStage::TessControl, source)
+    }
+    pub fn tess_evaluation(self, source: impl Into<ShaderSource>) -> Self {
+        self.stage(ShaderStage::TessEvaluation, source)
+    }
+    pub fn geometry(self, source: impl Into<ShaderSource>) -> Self {
+        self.stage(ShaderStage::Geometry, source)
+    }
+    pub fn fragment(self, source: impl Into<ShaderSource>) -> Self {
+        self.stage(ShaderStage::Fragment, source)
+    }
+    pub fn compute(self, source: impl Into<ShaderSource>) -> Self {
+        self.stage(ShaderStage::Compute, source)
+    }
+    /// Adds `#define <name> <value>` to every stage
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+    /// Loads the linked program from `cache` when possible, and stores it there otherwise
+    pub fn cache(mut self, cache: ProgramCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+    pub fn build(self) -> Result<Shader, ShaderError> {
+        self.validate()?;
+        let mut files = vec![];
+        let program_id =
+            build_program(&self.stages, &self.defines, self.cache.as_ref(), &mut files)?;
+
+        Ok(Shader {
+            program: Program::from_raw(program_id),
+            uniforms: Shader::reflect_uniforms(program_id),
+            block_bindings: RefCell::new(HashMap::new()),
+            stages: self.stages,
+            defines: self.defines,
+            cache: self.cache,
+            files,
+        })
+    }
+    fn validate(&self) -> Result<(), ShaderError> {
+        let has = |stage| self.stages.iter().any(|(s, _)| *s == stage);
+        if self.stages.is_empty() {
+            return Err(ShaderError::InvalidStages("no stage given"));
+        }
+        for (i, (stage, _)) in self.stages.iter().enumerate() {
+            if self.stages[..i].iter().any(|(s, _)| s == stage) {
+                return Err(ShaderError::InvalidStages(
+                    "a stage is given more than once",
+                ));
+            }
+        }
+        if has(ShaderStage::Compute) && self.stages.len() > 1 {
+            return Err(ShaderError::InvalidStages(
+                "a compute stage can't be linked with other stages",
+            ));
+        }
+        if has(ShaderStage::TessControl) && !has(ShaderStage::TessEvaluation) {
+            return Err(ShaderError::InvalidStages(
+                "a tessellation control stage needs an evaluation stage",
+            ));
+        }
+        for (stage, _) in self.stages.iter() {
+            if let Some((version, extension)) = stage.requirement() {
+                if !stage.is_supported() {
+                    return Err(ShaderError::UnsupportedStage {
+                        stage: *stage,
+                        version,
+                        extension,
+                    });
+                }
+            }
         }
+        Ok(())
     }
-    pub fn set_mat2(&self, name: &str, value: &glm::Mat2) {
-        unsafe {
-            gl::UniformMatrix2fv(
-                gl::GetUniformLocation(self.id, to_c_str(name).as_ptr()),
-                1,
-                gl::FALSE,
-                &value.m11,
-            )
+}
+
+/// Where the code of a stage comes from. Paths (`&str`, `String`, `Path`, `PathBuf`) convert to
+/// `File`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ShaderSource {
+    File(PathBuf),
+    /// In-memory code. `name` shows up in error messages, and includes are resolved relative to
+    /// it like for a file.
+    Code {
+        name: String,
+        code: String,
+    },
+}
+impl ShaderSource {
+    pub fn code(name: impl Into<String>, code: impl Into<String>) -> Self {
+        ShaderSource::Code {
+            name: name.into(),
+            code: code.into(),
         }
     }
-    pub fn set_mat3(&self, name: &str, value: &glm::Mat3) {
-        unsafe {
-            gl::UniformMatrix3fv(
-                gl::GetUniformLocation(self.id, to_c_str(name).as_ptr()),
-                1,
-                gl::FALSE,
-                &value.m11,
-            )
+}
+impl From<&str> for ShaderSource {
+    fn from(path: &str) -> Self {
+        ShaderSource::File(PathBuf::from(path))
+    }
+}
+impl From<String> for ShaderSource {
+    fn from(path: String) -> Self {
+        ShaderSource::File(PathBuf::from(path))
+    }
+}
+impl From<&Path> for ShaderSource {
+    fn from(path: &Path) -> Self {
+        ShaderSource::File(path.to_path_buf())
+    }
+}
+impl From<PathBuf> for ShaderSource {
+    fn from(path: PathBuf) -> Self {
+        ShaderSource::File(path)
+    }
+}
+
+/// Preprocesses, compiles and links `stages`. `files` receives every file read on the way, even
+/// when it fails. A binary from `cache` replaces compilation when the preprocessed sources match.
+fn build_program(
+    stages: &[(ShaderStage, ShaderSource)],
+    defines: &[(String, String)],
+    cache: Option<&ProgramCache>,
+    files: &mut Vec<PathBuf>,
+) -> Result<GLuint, ShaderError> {
+    let sources = preprocess_stages(stages, defines, files)?;
+
+    let cache = cache.filter(|cache| cache.is_usable());
+    let key = cache.map(|cache| {
+        let sources: Vec<(ShaderStage, &str)> = sources
+            .iter()
+            .map(|(stage, code)| (*stage, code.source.as_str()))
+            .collect();
+        cache.key(&sources)
+    });
+    if let (Some(cache), Some(key)) = (cache, key) {
+        if let Some(program_id) = cache.load(key) {
+            return Ok(program_id);
         }
     }
-    pub fn set_mat4(&self, name: &str, value: &glm::Mat4) {
-        unsafe {
-            gl::UniformMatrix4fv(
-                gl::GetUniformLocation(self.id, to_c_str(name).as_ptr()),
-                1,
-                gl::FALSE,
-                &value.m11,
-            )
+
+    let mut shaders: Vec<GLuint> = vec![];
+    let delete_shaders = |shaders: &[GLuint]| unsafe {
+        shaders.iter().for_each(|shader| gl::DeleteShader(*shader));
+    };
+    for (stage, code) in sources.iter() {
+        match compile_stage(*stage, code) {
+            Ok(shader) => shaders.push(shader),
+            Err(error) => {
+                delete_shaders(&shaders);
+                return Err(error);
+            }
         }
     }
-    fn check_compile_errors(shader: GLuint, compile_type: &str) {
+    let program_id = link_program(&shaders, cache.is_some());
+    delete_shaders(&shaders);
+    let program_id = program_id?;
+
+    if let (Some(cache), Some(key)) = (cache, key) {
+        cache.store(key, program_id);
+    }
+    Ok(program_id)
+}
+
+/// Resolves the includes and defines of every stage. Files on disk are added to `files` as they
+/// are read, so an include that fails to load is in it too.
+fn preprocess_stages(
+    stages: &[(ShaderStage, ShaderSource)],
+    defines: &[(String, String)],
+    files: &mut Vec<PathBuf>,
+) -> Result<Vec<(ShaderStage, Preprocessed)>, ShaderError> {
+    let defines: Vec<(&str, &str)> = defines
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+
+    let mut sources: Vec<(ShaderStage, Preprocessed)> = vec![];
+    for (stage, source) in stages {
+        let (root, inline) = match source {
+            ShaderSource::File(path) => (path.clone(), None),
+            ShaderSource::Code { name, code } => (PathBuf::from(name), Some(code)),
+        };
+        let read = |path: &Path| match inline {
+            Some(code) if path == root => Ok(code.clone()),
+            _ => {
+                // In-memory code has nothing to watch on disk
+                merge_files(files, vec![path.to_path_buf()]);
+                std::fs::read_to_string(path)
+            }
+        };
+        let code = preprocess(&root, &defines, read)?;
+        sources.push((*stage, code));
+    }
+    Ok(sources)
+}
+
+/// Appends the paths of `new` missing from `files`
+fn merge_files(files: &mut Vec<PathBuf>, new: Vec<PathBuf>) {
+    for file in new {
+        if !files.contains(&file) {
+            files.push(file);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    TessControl,
+    TessEvaluation,
+    Geometry,
+    Fragment,
+    Compute,
+}
+impl ShaderStage {
+    pub fn gl_enum(self) -> GLenum {
+        match self {
+            ShaderStage::Vertex => gl::VERTEX_SHADER,
+            ShaderStage::TessControl => gl::TESS_CONTROL_SHADER,
+            ShaderStage::TessEvaluation => gl::TESS_EVALUATION_SHADER,
+            ShaderStage::Geometry => gl::GEOMETRY_SHADER,
+            ShaderStage::Fragment => gl::FRAGMENT_SHADER,
+            ShaderStage::Compute => gl::COMPUTE_SHADER,
+        }
+    }
+    /// OpenGL version the stage became core in, and the extension providing it before that.
+    /// `None` for the stages of OpenGL 3.3.
+    pub fn requirement(self) -> Option<((GLint, GLint), &'static str)> {
+        match self {
+            ShaderStage::TessControl | ShaderStage::TessEvaluation => {
+                Some(((4, 0), "GL_ARB_tessellation_shader"))
+            }
+            ShaderStage::Compute => Some(((4, 3), "GL_ARB_compute_shader")),
+            _ => None,
+        }
+    }
+    /// Whether the current context can compile the stage
+    pub fn is_supported(self) -> bool {
+        let Some((version, extension)) = self.requirement() else {
+            return true;
+        };
+        let (mut major, mut minor, mut count) = (0, 0, 0);
         unsafe {
-            let mut success = gl::FALSE as GLint; // 0 = failure ; 1 = success
-            let mut info_log: Vec<u8> = Vec::with_capacity(1024);
-            info_log.set_len(1024 - 1); // set the last byte to null char
-            if compile_type != "PROGRAM" {
-                gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
-                if success == gl::FALSE as GLint {
-                    gl::GetShaderInfoLog(
-                        shader,
-                        1024,
-                        std::ptr::null_mut(),
-                        info_log.as_mut_slice().as_mut_ptr() as *mut GLchar,
-                    );
-                    println!(
-                        "\nERROR::SHADER_COMPILATION_ERROR of type:{}\n{}\n-------------------------------------\n",
-                        compile_type, std::str::from_utf8_unchecked(&info_log)
-                    );
-                }
-            } else {
-                gl::GetProgramiv(shader, gl::LINK_STATUS, &mut success);
-                if success == gl::FALSE as GLint {
-                    gl::GetProgramInfoLog(
-                        shader,
-                        1024,
-                        std::ptr::null_mut(),
-                        info_log.as_mut_slice().as_mut_ptr() as *mut GLchar,
-                    );
-                    println!(
-                    "\nERROR::PROGRAM_LINKING_ERROR of type:{}\n{}\n-------------------------------------\n",
-                    compile_type,std::str::from_utf8_unchecked(&info_log)
-                );
+            gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+            gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+            if (major, minor) >= version {
+                return true;
+            }
+            gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+            (0..count as GLuint).any(|i| {
+                let name = gl::GetStringi(gl::EXTENSIONS, i);
+                !name.is_null()
+                    && CStr::from_ptr(name as *const c_char).to_bytes() == extension.as_bytes()
+            })
+        }
+    }
+}
+impl fmt::Display for ShaderStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ShaderStage::Vertex => "VERTEX",
+            ShaderStage::TessControl => "TESS_CONTROL",
+            ShaderStage::TessEvaluation => "TESS_EVALUATION",
+            ShaderStage::Geometry => "GEOMETRY",
+            ShaderStage::Fragment => "FRAGMENT",
+            ShaderStage::Compute => "COMPUTE",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Compile {
+        stage: ShaderStage,
+        /// Files the stage was assembled from, `log` entries refer to them by index
+        files: Vec<PathBuf>,
+        log: Vec<InfoLogEntry>,
+    },
+    Link {
+        log: Vec<InfoLogEntry>,
+    },
+    /// A malformed preprocessor directive
+    Preprocess {
+        path: PathBuf,
+        line: u32,
+        message: String,
+    },
+    /// `chain` lists the includes leading back to its first file
+    IncludeCycle {
+        chain: Vec<PathBuf>,
+    },
+    /// The builder was given a combination of stages that can't form a program
+    InvalidStages(&'static str),
+    /// The context is older than `version` and lacks `extension`
+    UnsupportedStage {
+        stage: ShaderStage,
+        version: (GLint, GLint),
+        extension: &'static str,
+    },
+}
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io { path, source } => {
+                write!(f, "couldn't read shader {}: {}", path.display(), source)
+            }
+            ShaderError::Compile { stage, files, log } => {
+                write!(f, "SHADER_COMPILATION_ERROR of type {}", stage)?;
+                for entry in log {
+                    let file = entry.file.and_then(|file| files.get(file as usize));
+                    match (file, entry.line) {
+                        (Some(file), Some(line)) => {
+                            write!(f, "\n{}:{}: {}", file.display(), line, entry.message)?
+                        }
+                        _ => write!(f, "\n{}", entry)?,
+                    }
                 }
+                Ok(())
+            }
+            ShaderError::Link { log } => {
+                write!(f, "PROGRAM_LINKING_ERROR")?;
+                log.iter().try_for_each(|entry| write!(f, "\n{}", entry))
+            }
+            ShaderError::Preprocess {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ShaderError::IncludeCycle { chain } => {
+                write!(f, "include cycle: ")?;
+                let chain: Vec<String> = chain.iter().map(|p| p.display().to_string()).collect();
+                f.write_str(&chain.join(" -> "))
             }
+            ShaderError::InvalidStages(message) => write!(f, "invalid shader stages: {}", message),
+            ShaderError::UnsupportedStage {
+                stage,
+                version: (major, minor),
+                extension,
+            } => write!(
+                f,
+                "{} shaders need OpenGL {}.{} or {}",
+                stage, major, minor, extension
+            ),
+        }
+    }
+}
+impl std::error::Error for ShaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShaderError::Io { source, .. } => Some(source),
+            _ => None,
         }
     }
+}
 
-    pub fn new(vertex_path: &'static str, fragment_path: &'static str) -> Self {
-        // Read the source code files
-        if let (Ok(vertex_code_v), Ok(fragment_code_v)) =
-            (std::fs::read(vertex_path), std::fs::read(fragment_path))
-        {
-            let vertex_code = std::str::from_utf8(&vertex_code_v).unwrap();
-            let fragment_code = std::str::from_utf8(&fragment_code_v).unwrap();
+/// One message of a driver info log
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InfoLogEntry {
+    /// Source string number, as set by `#line <line> <file>`
+    pub file: Option<u32>,
+    pub line: Option<u32>,
+    pub message: String,
+}
+impl fmt::Display for InfoLogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{}({}): {}", file, line, self.message),
+            _ => f.write_str(&self.message),
+        }
+    }
+}
 
-            let program_id = unsafe {
-                let (mut vertex_shader, mut fragment_shader) = (0, 0);
-                let mut success = gl::FALSE as GLint; // 0 = failure ; 1 = success
-                let mut info_log: Vec<u8> = Vec::with_capacity(512);
-                info_log.set_len(512 - 1); // set the last byte to null char
+/// Splits a driver info log into entries. Drivers don't agree on a format, the ones handled are
+/// `0(12) : error C0000: ...` (NVIDIA), `0:12(5): error: ...` (Mesa) and
+/// `ERROR: 0:12: ...` (AMD, Apple, ANGLE). Lines that match none of them are kept as plain messages.
+pub fn parse_info_log(log: &str) -> Vec<InfoLogEntry> {
+    log.lines()
+        .map(|line| line.trim_matches(|c: char| c == '\0' || c.is_whitespace()))
+        .filter(|line| !line.is_empty())
+        .map(parse_info_log_line)
+        .collect()
+}
 
-                ///////////// VERTEX SHADER /////////////
-                vertex_shader = gl::CreateShader(gl::VERTEX_SHADER);
-                gl::ShaderSource(
-                    vertex_shader,
-                    1,
-                    &to_c_str(vertex_code).as_ptr(),
-                    std::ptr::null(),
-                );
-                gl::CompileShader(vertex_shader);
-                Self::check_compile_errors(vertex_shader, "VERTEX");
-
-                ///////////// FRAGMENT SHADER /////////////
-                fragment_shader = gl::CreateShader(gl::FRAGMENT_SHADER);
-                gl::ShaderSource(
-                    fragment_shader,
-                    1,
-                    &to_c_str(fragment_code).as_ptr(),
-                    std::ptr::null(),
-                );
-                gl::CompileShader(fragment_shader);
-                Self::check_compile_errors(fragment_shader, "FRAGMENT");
+fn parse_info_log_line(line: &str) -> InfoLogEntry {
+    let (severity, rest) = ["ERROR:", "WARNING:"]
+        .iter()
+        .find_map(|prefix| {
+            line.strip_prefix(prefix)
+                .map(|rest| (Some(prefix.trim_end_matches(':')), rest.trim_start()))
+        })
+        .unwrap_or((None, line));
 
-                ///////////// PROGRAM /////////////
-                let program_id: u32 = gl::CreateProgram();
-                gl::AttachShader(program_id, vertex_shader);
-                gl::AttachShader(program_id, fragment_shader);
-                gl::LinkProgram(program_id);
-                Self::check_compile_errors(program_id, "PROGRAM");
+    match parse_location(rest) {
+        Some((file, line_nr, message)) => InfoLogEntry {
+            file: Some(file),
+            line: Some(line_nr),
+            message: match severity {
+                Some(severity) => format!("{}: {}", severity.to_lowercase(), message),
+                None => message.to_string(),
+            },
+        },
+        None => InfoLogEntry {
+            file: None,
+            line: None,
+            message: line.to_string(),
+        },
+    }
+}
 
-                gl::DeleteShader(vertex_shader);
-                gl::DeleteShader(fragment_shader);
+/// Parses `F(L) :`, `F:L(C):` or `F:L:` and returns the file, line and remaining message
+fn parse_location(s: &str) -> Option<(u32, u32, &str)> {
+    fn number(s: &str) -> Option<(u32, &str)> {
+        let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        Some((s[..end].parse().ok()?, &s[end..]))
+    }
+    let (file, s) = number(s)?;
+    let (line, s) = if let Some(s) = s.strip_prefix('(') {
+        let (line, s) = number(s)?;
+        (line, s.strip_prefix(')')?)
+    } else {
+        let (line, s) = number(s.strip_prefix(':')?)?;
+        // Mesa appends the column
+        let s = match s.strip_prefix('(') {
+            Some(s) => number(s)?.1.strip_prefix(')')?,
+            None => s,
+        };
+        (line, s)
+    };
+    let message = s.trim_start().strip_prefix(':')?.trim();
+    Some((file, line, message))
+}
 
-                program_id
-            };
-            Self { id: program_id }
-        } else {
-            panic!("Couldn't read vertex or fragment file")
+fn compile_stage(stage: ShaderStage, code: &Preprocessed) -> Result<GLuint, ShaderError> {
+    unsafe {
+        let shader = gl::CreateShader(stage.gl_enum());
+        gl::ShaderSource(
+            shader,
+            1,
+            &to_c_str(&code.source).as_ptr(),
+            std::ptr::null(),
+        );
+        gl::CompileShader(shader);
+
+        let mut success = gl::FALSE as GLint; // 0 = failure ; 1 = success
+        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+        if success == gl::FALSE as GLint {
+            let log = parse_info_log(&shader_info_log(shader));
+            gl::DeleteShader(shader);
+            return Err(ShaderError::Compile {
+                stage,
+                files: code.files.clone(),
+                log,
+            });
+        }
+        Ok(shader)
+    }
+}
+
+/// `retrievable` asks the driver to keep the binary around for `gl::GetProgramBinary`
+fn link_program(shaders: &[GLuint], retrievable: bool) -> Result<GLuint, ShaderError> {
+    unsafe {
+        let program = gl::CreateProgram();
+        for shader in shaders {
+            gl::AttachShader(program, *shader);
+        }
+        if retrievable {
+            gl::ProgramParameteri(
+                program,
+                gl::PROGRAM_BINARY_RETRIEVABLE_HINT,
+                gl::TRUE as GLint,
+            );
+        }
+        gl::LinkProgram(program);
+        for shader in shaders {
+            gl::DetachShader(program, *shader);
+        }
+
+        let mut success = gl::FALSE as GLint;
+        gl::GetProgramiv(program, gl::LINK_STATUS, &mut success);
+        if success == gl::FALSE as GLint {
+            let log = parse_info_log(&program_info_log(program));
+            gl::DeleteProgram(program);
+            return Err(ShaderError::Link { log });
         }
+        Ok(program)
+    }
+}
+
+unsafe fn shader_info_log(shader: GLuint) -> String {
+    let mut len = 0;
+    gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
+    let mut info_log: Vec<u8> = vec![0; len.max(1) as usize];
+    gl::GetShaderInfoLog(
+        shader,
+        info_log.len() as GLsizei,
+        &mut len,
+        info_log.as_mut_ptr() as *mut GLchar,
+    );
+    String::from_utf8_lossy(&info_log[..len as usize]).into_owned()
+}
+
+unsafe fn program_info_log(program: GLuint) -> String {
+    let mut len = 0;
+    gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len);
+    let mut info_log: Vec<u8> = vec![0; len.max(1) as usize];
+    gl::GetProgramInfoLog(
+        program,
+        info_log.len() as GLsizei,
+        &mut len,
+        info_log.as_mut_ptr() as *mut GLchar,
+    );
+    String::from_utf8_lossy(&info_log[..len as usize]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempDir;
+
+    #[test]
+    fn failed_preprocessing_reports_the_files_it_read() {
+        let dir = TempDir::new("shader-files-test");
+        let vertex = dir.path().join("vertex.glsl");
+        let fragment = dir.path().join("fragment.glsl");
+        std::fs::write(&vertex, "#version 330 core\nvoid main() {}\n").unwrap();
+        std::fs::write(
+            &fragment,
+            "#version 330 core\n#include \"missing.glsl\"\nvoid main() {}\n",
+        )
+        .unwrap();
+        let stages = [
+            (ShaderStage::Vertex, ShaderSource::from(vertex.clone())),
+            (ShaderStage::Fragment, ShaderSource::from(fragment.clone())),
+        ];
+
+        let mut files = vec![];
+        let result = preprocess_stages(&stages, &[], &mut files);
+        assert!(matches!(result, Err(ShaderError::Io { .. })));
+        let missing = dir.path().join("missing.glsl");
+        assert_eq!(
+            files,
+            vec![vertex.clone(), fragment.clone(), missing.clone()]
+        );
+
+        // The files of the previous program stay watched next to the new ones
+        let mut watched = vec![vertex.clone(), fragment.clone()];
+        merge_files(&mut watched, files);
+        assert_eq!(watched, vec![vertex, fragment, missing]);
     }
 }
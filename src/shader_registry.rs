@@ -0,0 +1,98 @@
+use crate::shader::{Shader, ShaderError};
+use std::collections::HashMap;
+use std::ops::Index;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ShaderId(usize);
+
+struct Entry {
+    shader: Shader,
+    modified: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+/// Owns the shaders of the application and rebuilds them when their sources change on disk
+#[derive(Default)]
+pub struct ShaderRegistry {
+    entries: Vec<Entry>,
+}
+
+impl ShaderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn add(&mut self, shader: Shader) -> ShaderId {
+        let modified = modification_times(shader.files());
+        self.entries.push(Entry { shader, modified });
+        ShaderId(self.entries.len() - 1)
+    }
+    pub fn get(&self, id: ShaderId) -> &Shader {
+        &self.entries[id.0].shader
+    }
+    /// Checks the modification time of every source file and reloads the shaders that changed.
+    /// Meant to be called once per frame. A shader that fails to build keeps its current program
+    /// and its error is returned; it is retried on the next change.
+    pub fn reload_changed(&mut self) -> Vec<(ShaderId, ShaderError)> {
+        let mut errors = vec![];
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            if !changed(&entry.modified) {
+                continue;
+            }
+            if let Err(error) = entry.shader.reload() {
+                errors.push((ShaderId(i), error));
+            }
+            // Refreshed on failure too, so a broken shader isn't rebuilt every frame. The files
+            // then include the ones the failed build read, a new include that doesn't exist yet
+            // among them.
+            entry.modified = modification_times(entry.shader.files());
+        }
+        errors
+    }
+}
+impl Index<ShaderId> for ShaderRegistry {
+    type Output = Shader;
+
+    fn index(&self, id: ShaderId) -> &Shader {
+        self.get(id)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn modification_times(files: &[PathBuf]) -> HashMap<PathBuf, Option<SystemTime>> {
+    files
+        .iter()
+        .map(|path| (path.clone(), modified(path)))
+        .collect()
+}
+
+/// Whether a file was modified, created or deleted since `times` were taken
+fn changed(times: &HashMap<PathBuf, Option<SystemTime>>) -> bool {
+    times.iter().any(|(path, time)| modified(path) != *time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::TempDir;
+
+    #[test]
+    fn creating_a_watched_file_is_a_change() {
+        let dir = TempDir::new("registry-test");
+        let shader = dir.path().join("shader.glsl");
+        let include = dir.path().join("include.glsl");
+        std::fs::write(&shader, "#include \"include.glsl\"\n").unwrap();
+
+        // The include didn't exist when the shader failed to build
+        let times = modification_times(&[shader.clone(), include.clone()]);
+        assert_eq!(times[&include], None);
+        assert!(!changed(&times));
+
+        std::fs::write(&include, "void f() {}\n").unwrap();
+        assert!(changed(&times));
+        assert!(!changed(&modification_times(&[shader, include])));
+    }
+}
@@ -0,0 +1,5 @@
+// Fit of the ACES filmic curve by Krzysztof Narkowicz
+vec3 aces(vec3 x)
+{
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), 0.0, 1.0);
+}
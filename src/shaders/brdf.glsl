@@ -0,0 +1,52 @@
+#version 330 core
+out vec2 FragColor;
+
+// N·V on x and roughness on y
+in vec2 TexCoords;
+
+uniform int sampleCount = 1024;
+
+#include "importance-sampling.glsl"
+
+float geometrySchlickGGX(float NdotV, float roughness)
+{
+    // Remapped for image based lighting
+    float k = roughness * roughness / 2.0;
+    return NdotV / (NdotV * (1.0 - k) + k);
+}
+
+float geometrySmith(float NdotV, float NdotL, float roughness)
+{
+    return geometrySchlickGGX(NdotV, roughness) * geometrySchlickGGX(NdotL, roughness);
+}
+
+// Scale and bias applied to F0 by the split-sum approximation of the specular integral
+void main()
+{
+    float NdotV = TexCoords.x;
+    float roughness = TexCoords.y;
+    vec3 view = vec3(sqrt(1.0 - NdotV * NdotV), 0.0, NdotV);
+    vec3 normal = vec3(0.0, 0.0, 1.0);
+
+    uint count = uint(sampleCount);
+    float scale = 0.0;
+    float bias = 0.0;
+    for (uint i = 0u; i < count; i++)
+    {
+        vec2 xi = hammersley(i, count);
+        vec3 halfway = importanceSampleGGX(xi, normal, roughness);
+        vec3 light = normalize(2.0 * dot(view, halfway) * halfway - view);
+        float NdotL = max(light.z, 0.0);
+        float NdotH = max(halfway.z, 0.0);
+        float VdotH = max(dot(view, halfway), 0.0);
+        if (NdotL > 0.0)
+        {
+            float G = geometrySmith(NdotV, NdotL, roughness);
+            float visibility = G * VdotH / (NdotH * NdotV);
+            float fresnel = pow(1.0 - VdotH, 5.0);
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+    FragColor = vec2(scale, bias) / float(count);
+}
@@ -0,0 +1,45 @@
+// Written by `LightList::upload`, `MAX_LIGHTS` is defined when building the shader
+#ifndef MAX_LIGHTS
+#define MAX_LIGHTS 16
+#endif
+
+#define DIRECTIONAL_LIGHT 0
+#define POINT_LIGHT 1
+#define SPOT_LIGHT 2
+
+struct Light {
+    vec4 position;    // w: kind of light
+    vec4 direction;
+    vec4 color;       // premultiplied by the intensity
+    vec4 attenuation; // constant, linear, quadratic
+    vec4 cutoff;      // cosines of the inner and outer cutoffs
+};
+
+layout (std140) uniform Lights {
+    ivec4 lightCount;
+    Light lights[MAX_LIGHTS];
+};
+
+// Radiance reaching `fragPos` from `light`, and the direction towards the light
+vec3 lightRadiance(Light light, vec3 fragPos, out vec3 lightDir)
+{
+    int kind = int(light.position.w);
+    if (kind == DIRECTIONAL_LIGHT)
+    {
+        lightDir = -light.direction.xyz;
+        return light.color.rgb;
+    }
+    vec3 toLight = light.position.xyz - fragPos;
+    float distance = length(toLight);
+    lightDir = toLight / distance;
+    vec3 a = light.attenuation.xyz;
+    float attenuation = 1.0 / (a.x + a.y * distance + a.z * distance * distance);
+    if (kind == SPOT_LIGHT)
+    {
+        float theta = dot(lightDir, -light.direction.xyz);
+        // Equal cutoffs make a hard edge
+        float epsilon = max(light.cutoff.x - light.cutoff.y, 1e-4);
+        attenuation *= clamp((theta - light.cutoff.y) / epsilon, 0.0, 1.0);
+    }
+    return light.color.rgb * attenuation;
+}
@@ -0,0 +1,67 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec3 FragPos;
+in vec3 Normal;
+in vec2 TexCoords;
+#ifdef NORMAL_MAP
+in mat3 TBN;
+#endif
+
+#include "material.glsl"
+#include "lights.glsl"
+#include "output.glsl"
+
+uniform vec3 viewPos;
+uniform vec3 ambientColor = vec3(0.1);
+#ifdef SHADOWS
+#include "shadows.glsl"
+#endif
+
+vec3 surfaceNormal()
+{
+#ifdef NORMAL_MAP
+    if (material.has_normal_map)
+    {
+        vec3 tangentNormal = texture(material.normal_map, TexCoords).rgb * 2.0 - 1.0;
+        return normalize(TBN * tangentNormal);
+    }
+#endif
+    return normalize(Normal);
+}
+
+void main()
+{
+    vec3 albedo = material.diffuse;
+    if (material.has_diffuse_map)
+        albedo *= texture(material.diffuse_map, TexCoords).rgb;
+    vec3 specularColor = material.specular;
+    if (material.has_specular_map)
+        specularColor *= texture(material.specular_map, TexCoords).rgb;
+
+    // Blinn-Phong
+    vec3 normal = surfaceNormal();
+    vec3 viewDir = normalize(viewPos - FragPos);
+    vec3 color = ambientColor * albedo;
+    for (int i = 0; i < min(lightCount.x, MAX_LIGHTS); i++)
+    {
+        vec3 lightDir;
+        vec3 radiance = lightRadiance(lights[i], FragPos, lightDir);
+#ifdef SHADOWS
+        radiance *= lightVisibility(i, FragPos, normal, lightDir);
+#endif
+        vec3 halfway = normalize(lightDir + viewDir);
+        vec3 diffuse = max(dot(normal, lightDir), 0.0) * albedo;
+        vec3 specular = pow(max(dot(normal, halfway), 0.0), material.shininess) * specularColor;
+        color += (diffuse + specular) * radiance;
+    }
+
+    vec3 emissive = material.emissive;
+    if (material.has_emissive_map)
+        emissive *= texture(material.emissive_map, TexCoords).rgb;
+    color += emissive;
+    float alpha = material.opacity;
+    if (material.has_opacity_map)
+        alpha *= texture(material.opacity_map, TexCoords).r;
+    FragColor = outputColor(color, alpha);
+}
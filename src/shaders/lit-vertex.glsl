@@ -0,0 +1,37 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec2 aTexCoords;
+layout (location = 5) in vec3 aTangent;
+layout (location = 6) in vec3 aBitangent;
+
+out vec3 FragPos;
+out vec3 Normal;
+out vec2 TexCoords;
+#ifdef NORMAL_MAP
+// Tangent space to world space
+out mat3 TBN;
+#endif
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+
+void main()
+{
+    mat3 normalMatrix = mat3(transpose(inverse(model)));
+    FragPos = vec3(model * vec4(aPos, 1.0));
+    Normal = normalize(normalMatrix * aNormal);
+    TexCoords = aTexCoords;
+#ifdef NORMAL_MAP
+    // Tangents lie in the surface and follow the model matrix itself, then Gram-Schmidt keeps
+    // the frame orthonormal. The bitangent keeps the handedness of the mesh.
+    vec3 T = mat3(model) * aTangent;
+    T = normalize(T - dot(T, Normal) * Normal);
+    vec3 B = cross(Normal, T);
+    if (dot(B, mat3(model) * aBitangent) < 0.0)
+        B = -B;
+    TBN = mat3(T, B, Normal);
+#endif
+    gl_Position = projection * view * vec4(FragPos, 1.0);
+}
@@ -0,0 +1,28 @@
+// Set by `Material::bind`, a `has_*_map` flag is false when the slot has no texture
+struct Material {
+    sampler2D diffuse_map;
+    bool has_diffuse_map;
+    sampler2D specular_map;
+    bool has_specular_map;
+    sampler2D normal_map;
+    bool has_normal_map;
+    sampler2D emissive_map;
+    bool has_emissive_map;
+    sampler2D occlusion_map;
+    bool has_occlusion_map;
+    // Roughness in green and metalness in blue, like glTF
+    sampler2D metallic_roughness_map;
+    bool has_metallic_roughness_map;
+    sampler2D opacity_map;
+    bool has_opacity_map;
+
+    vec3 diffuse;
+    vec3 specular;
+    float shininess;
+    float opacity;
+    vec3 emissive;
+    float metallic;
+    float roughness;
+};
+
+uniform Material material;
@@ -0,0 +1,9 @@
+// Colors are computed in linear space and encoded to sRGB for the default framebuffer. Passes
+// rendering to a linear target for further processing define `LINEAR_OUTPUT`.
+vec4 outputColor(vec3 color, float alpha)
+{
+#ifndef LINEAR_OUTPUT
+    color = pow(color, vec3(1.0 / 2.2));
+#endif
+    return vec4(color, alpha);
+}
@@ -0,0 +1,155 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec3 FragPos;
+in vec3 Normal;
+in vec2 TexCoords;
+#ifdef NORMAL_MAP
+in mat3 TBN;
+#endif
+
+#include "material.glsl"
+#include "lights.glsl"
+#include "output.glsl"
+
+uniform vec3 viewPos;
+#ifdef SHADOWS
+#include "shadows.glsl"
+#endif
+// Set by `Environment::bind`, without `IBL` the ambient light is a constant color
+#ifdef IBL
+uniform samplerCube irradianceMap;
+uniform samplerCube prefilterMap;
+uniform sampler2D brdfLUT;
+uniform float maxReflectionLod;
+#else
+uniform vec3 ambientColor = vec3(0.03);
+#endif
+uniform float exposure = 1.0;
+
+const float PI = 3.14159265359;
+
+vec3 surfaceNormal()
+{
+#ifdef NORMAL_MAP
+    if (material.has_normal_map)
+    {
+        vec3 tangentNormal = texture(material.normal_map, TexCoords).rgb * 2.0 - 1.0;
+        return normalize(TBN * tangentNormal);
+    }
+#endif
+    return normalize(Normal);
+}
+
+// Cook-Torrance terms
+float distributionGGX(float NdotH, float roughness)
+{
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float denom = NdotH * NdotH * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denom * denom);
+}
+
+float geometrySchlickGGX(float NdotV, float roughness)
+{
+    // Remapped for direct lighting
+    float r = roughness + 1.0;
+    float k = r * r / 8.0;
+    return NdotV / (NdotV * (1.0 - k) + k);
+}
+
+float geometrySmith(float NdotV, float NdotL, float roughness)
+{
+    return geometrySchlickGGX(NdotV, roughness) * geometrySchlickGGX(NdotL, roughness);
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 F0)
+{
+    return F0 + (1.0 - F0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+// Rough surfaces reflect less at grazing angles
+vec3 fresnelSchlickRoughness(float cosTheta, vec3 F0, float roughness)
+{
+    return F0 + (max(vec3(1.0 - roughness), F0) - F0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+void main()
+{
+    vec3 albedo = material.diffuse;
+    float alpha = material.opacity;
+    if (material.has_diffuse_map)
+    {
+        vec4 texel = texture(material.diffuse_map, TexCoords);
+        albedo *= texel.rgb;
+        alpha *= texel.a;
+    }
+    if (material.has_opacity_map)
+        alpha *= texture(material.opacity_map, TexCoords).r;
+    float metallic = material.metallic;
+    float roughness = material.roughness;
+    if (material.has_metallic_roughness_map)
+    {
+        vec4 texel = texture(material.metallic_roughness_map, TexCoords);
+        roughness *= texel.g;
+        metallic *= texel.b;
+    }
+    // Fully smooth surfaces have an infinitely sharp highlight
+    roughness = clamp(roughness, 0.04, 1.0);
+    float occlusion = 1.0;
+    if (material.has_occlusion_map)
+        occlusion = texture(material.occlusion_map, TexCoords).r;
+
+    vec3 normal = surfaceNormal();
+    vec3 viewDir = normalize(viewPos - FragPos);
+    float NdotV = max(dot(normal, viewDir), 0.0001);
+    // Dielectrics reflect about 4% at normal incidence, metals tint their reflections
+    vec3 F0 = mix(vec3(0.04), albedo, metallic);
+
+    vec3 color = vec3(0.0);
+    for (int i = 0; i < min(lightCount.x, MAX_LIGHTS); i++)
+    {
+        vec3 lightDir;
+        vec3 radiance = lightRadiance(lights[i], FragPos, lightDir);
+#ifdef SHADOWS
+        radiance *= lightVisibility(i, FragPos, normal, lightDir);
+#endif
+        vec3 halfway = normalize(lightDir + viewDir);
+        float NdotL = max(dot(normal, lightDir), 0.0);
+        float NdotH = max(dot(normal, halfway), 0.0);
+
+        vec3 F = fresnelSchlick(max(dot(halfway, viewDir), 0.0), F0);
+        float D = distributionGGX(NdotH, roughness);
+        float G = geometrySmith(NdotV, NdotL, roughness);
+        vec3 specular = D * G * F / (4.0 * NdotV * NdotL + 0.0001);
+        // Energy not reflected is refracted, and absorbed by metals
+        vec3 kD = (1.0 - F) * (1.0 - metallic);
+        color += (kD * albedo / PI + specular) * radiance * NdotL;
+    }
+
+#ifdef IBL
+    vec3 F = fresnelSchlickRoughness(NdotV, F0, roughness);
+    vec3 kD = (1.0 - F) * (1.0 - metallic);
+    vec3 diffuse = texture(irradianceMap, normal).rgb * albedo;
+    vec3 reflection = reflect(-viewDir, normal);
+    vec3 prefiltered = textureLod(prefilterMap, reflection, roughness * maxReflectionLod).rgb;
+    vec2 brdf = texture(brdfLUT, vec2(NdotV, roughness)).rg;
+    vec3 specular = prefiltered * (F * brdf.x + brdf.y);
+    vec3 ambient = (kD * diffuse + specular) * occlusion;
+#else
+    vec3 ambient = ambientColor * albedo * occlusion;
+#endif
+    color += ambient;
+
+    vec3 emissive = material.emissive;
+    if (material.has_emissive_map)
+        emissive *= texture(material.emissive_map, TexCoords).rgb;
+    color += emissive;
+
+    color *= exposure;
+#ifndef LINEAR_OUTPUT
+    // Reinhard, tone mapping is left to the post processing of linear targets
+    color = color / (color + 1.0);
+#endif
+    FragColor = outputColor(color, alpha);
+}
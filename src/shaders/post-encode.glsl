@@ -0,0 +1,15 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+
+#include "aces.glsl"
+
+// Tone mapping and gamma with their default settings, for when no effect is enabled
+void main()
+{
+    vec4 color = texture(screenTexture, TexCoords);
+    FragColor = vec4(pow(aces(color.rgb), vec3(1.0 / 2.2)), color.a);
+}
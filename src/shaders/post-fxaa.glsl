@@ -0,0 +1,49 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+uniform vec2 texelSize;
+
+const float REDUCE_MIN = 1.0 / 128.0;
+const float REDUCE_MUL = 1.0 / 8.0;
+const float SPAN_MAX = 8.0;
+
+float luma(vec3 color)
+{
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+// FXAA 2: blurs along the edges found from the luma of the neighbours
+void main()
+{
+    float lumaNW = luma(texture(screenTexture, TexCoords + vec2(-1.0, 1.0) * texelSize).rgb);
+    float lumaNE = luma(texture(screenTexture, TexCoords + vec2(1.0, 1.0) * texelSize).rgb);
+    float lumaSW = luma(texture(screenTexture, TexCoords + vec2(-1.0, -1.0) * texelSize).rgb);
+    float lumaSE = luma(texture(screenTexture, TexCoords + vec2(1.0, -1.0) * texelSize).rgb);
+    vec4 center = texture(screenTexture, TexCoords);
+    float lumaM = luma(center.rgb);
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    // Perpendicular to the gradient of the luma
+    vec2 direction = vec2(
+        -((lumaNW + lumaNE) - (lumaSW + lumaSE)),
+        (lumaNW + lumaSW) - (lumaNE + lumaSE)
+    );
+    float reduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * 0.25 * REDUCE_MUL, REDUCE_MIN);
+    float scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2(-SPAN_MAX), vec2(SPAN_MAX)) * texelSize;
+
+    vec3 colorA = 0.5 * (
+        texture(screenTexture, TexCoords + direction * (1.0 / 3.0 - 0.5)).rgb +
+        texture(screenTexture, TexCoords + direction * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 colorB = colorA * 0.5 + 0.25 * (
+        texture(screenTexture, TexCoords - direction * 0.5).rgb +
+        texture(screenTexture, TexCoords + direction * 0.5).rgb);
+    // The wider blur crossed another edge
+    float lumaB = luma(colorB);
+    vec3 color = lumaB < lumaMin || lumaB > lumaMax ? colorA : colorB;
+    FragColor = vec4(color, center.a);
+}
@@ -0,0 +1,23 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+uniform vec2 texelSize;
+// Row major, top row first
+uniform float kernel[9];
+
+void main()
+{
+    vec3 color = vec3(0.0);
+    for (int y = 0; y < 3; y++)
+    {
+        for (int x = 0; x < 3; x++)
+        {
+            vec2 offset = vec2(x - 1, 1 - y) * texelSize;
+            color += texture(screenTexture, TexCoords + offset).rgb * kernel[y * 3 + x];
+        }
+    }
+    FragColor = vec4(color, texture(screenTexture, TexCoords).a);
+}
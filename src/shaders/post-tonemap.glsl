@@ -0,0 +1,15 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+uniform float exposure = 1.0;
+
+#include "aces.glsl"
+
+void main()
+{
+    vec4 color = texture(screenTexture, TexCoords);
+    FragColor = vec4(aces(color.rgb * exposure), color.a);
+}
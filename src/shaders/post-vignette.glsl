@@ -0,0 +1,18 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2D screenTexture;
+// Distance from the center where the darkening starts, the corners are at 1
+uniform float radius = 0.5;
+// Darkening at the corners
+uniform float strength = 0.5;
+
+void main()
+{
+    vec4 color = texture(screenTexture, TexCoords);
+    float distance = length(TexCoords - 0.5) / length(vec2(0.5));
+    float darkening = smoothstep(radius, 1.0, distance) * strength;
+    FragColor = vec4(color.rgb * (1.0 - darkening), color.a);
+}
@@ -0,0 +1,55 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec3 LocalPos;
+
+uniform samplerCube environmentMap;
+// Of the mip level being rendered
+uniform float roughness;
+// Size of a face of the environment map
+uniform float resolution;
+uniform int sampleCount = 1024;
+
+#include "importance-sampling.glsl"
+
+float distributionGGX(float NdotH, float roughness)
+{
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float denom = NdotH * NdotH * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denom * denom);
+}
+
+// The view direction is assumed to be the normal, which loses the stretched reflections at
+// grazing angles but lets a single lookup serve every view
+void main()
+{
+    vec3 normal = normalize(LocalPos);
+    vec3 view = normal;
+
+    uint count = uint(sampleCount);
+    float totalWeight = 0.0;
+    vec3 color = vec3(0.0);
+    for (uint i = 0u; i < count; i++)
+    {
+        vec2 xi = hammersley(i, count);
+        vec3 halfway = importanceSampleGGX(xi, normal, roughness);
+        vec3 light = normalize(2.0 * dot(view, halfway) * halfway - view);
+        float NdotL = dot(normal, light);
+        if (NdotL > 0.0)
+        {
+            // Samples with a low probability cover a larger solid angle, read from a smaller
+            // mip level of the environment to avoid bright dots
+            float NdotH = max(dot(normal, halfway), 0.0);
+            float HdotV = max(dot(halfway, view), 0.0);
+            float pdf = distributionGGX(NdotH, roughness) * NdotH / (4.0 * HdotV) + 0.0001;
+            float texelSolidAngle = 4.0 * PI / (6.0 * resolution * resolution);
+            float sampleSolidAngle = 1.0 / (float(count) * pdf + 0.0001);
+            float mip = roughness == 0.0 ? 0.0 : 0.5 * log2(sampleSolidAngle / texelSolidAngle);
+
+            color += textureLod(environmentMap, light, mip).rgb * NdotL;
+            totalWeight += NdotL;
+        }
+    }
+    FragColor = vec4(color / totalWeight, 1.0);
+}
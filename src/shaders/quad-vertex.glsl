@@ -0,0 +1,12 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+
+// Full-screen pass drawn with `ScreenQuad`, with the origin of the texture coordinates at the
+// bottom left
+out vec2 TexCoords;
+
+void main()
+{
+    TexCoords = aPos.xy * 0.5 + 0.5;
+    gl_Position = vec4(aPos.xy, 0.0, 1.0);
+}
@@ -0,0 +1,31 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 TexCoords;
+
+uniform sampler2DArray cascades;
+uniform samplerCube cube;
+// 0 shows a cascade, 1 a face of the cube map
+uniform int mode;
+// Cascade or face
+uniform int layer;
+
+void main()
+{
+    float depth;
+    if (mode == 0)
+    {
+        depth = texture(cascades, vec3(TexCoords, layer)).r;
+    }
+    else
+    {
+        vec2 uv = TexCoords * 2.0 - 1.0;
+        vec3 directions[6] = vec3[](
+            vec3(1.0, -uv.y, -uv.x), vec3(-1.0, -uv.y, uv.x),
+            vec3(uv.x, 1.0, uv.y), vec3(uv.x, -1.0, -uv.y),
+            vec3(uv.x, -uv.y, 1.0), vec3(-uv.x, -uv.y, -1.0)
+        );
+        depth = texture(cube, directions[layer]).r;
+    }
+    FragColor = vec4(vec3(depth), 1.0);
+}
@@ -0,0 +1,16 @@
+#version 330 core
+in vec3 FragPos;
+
+#ifdef POINT_SHADOW
+uniform vec3 lightPos;
+uniform float farPlane;
+#endif
+
+// Only depth is written. Point shadows store the linear distance to the light, the same in
+// every face of the cube map.
+void main()
+{
+#ifdef POINT_SHADOW
+    gl_FragDepth = length(FragPos - lightPos) / farPlane;
+#endif
+}
@@ -0,0 +1,101 @@
+// Set by `ShadowRenderer::bind`. Included after the `viewPos` uniform, needs the `view` matrix
+// of the camera to pick cascades.
+#define MAX_CASCADES 4
+
+uniform sampler2DArray shadowCascades;
+uniform mat4 cascadeLightSpace[MAX_CASCADES];
+// View space distance where each cascade ends
+uniform float cascadeSplits[MAX_CASCADES];
+uniform int cascadeCount = 0;
+// Index of the light casting directional shadows, -1 for none
+uniform int shadowLight = -1;
+
+uniform samplerCube pointShadowMap;
+uniform vec3 pointShadowPos;
+uniform float pointShadowFar;
+uniform int pointShadowLight = -1;
+
+uniform float shadowBias = 0.0005;
+uniform float shadowSlopeBias = 0.005;
+uniform int pcfRadius = 1;
+
+uniform mat4 view;
+
+float shadowBiasFor(vec3 normal, vec3 lightDir)
+{
+    return max(shadowSlopeBias * (1.0 - dot(normal, lightDir)), shadowBias);
+}
+
+// 0 when lit, 1 when fully in shadow
+float directionalShadow(vec3 fragPos, vec3 normal, vec3 lightDir)
+{
+    float depth = -(view * vec4(fragPos, 1.0)).z;
+    int cascade = cascadeCount - 1;
+    for (int i = 0; i < cascadeCount; i++)
+    {
+        if (depth < cascadeSplits[i])
+        {
+            cascade = i;
+            break;
+        }
+    }
+    vec4 lightSpace = cascadeLightSpace[cascade] * vec4(fragPos, 1.0);
+    vec3 coords = lightSpace.xyz / lightSpace.w * 0.5 + 0.5;
+    // Beyond the far plane of the cascade
+    if (coords.z > 1.0)
+        return 0.0;
+
+    float bias = shadowBiasFor(normal, lightDir);
+    vec2 texelSize = 1.0 / vec2(textureSize(shadowCascades, 0).xy);
+    float shadow = 0.0;
+    for (int x = -pcfRadius; x <= pcfRadius; x++)
+    {
+        for (int y = -pcfRadius; y <= pcfRadius; y++)
+        {
+            vec2 uv = coords.xy + vec2(x, y) * texelSize;
+            float closest = texture(shadowCascades, vec3(uv, cascade)).r;
+            shadow += coords.z - bias > closest ? 1.0 : 0.0;
+        }
+    }
+    float kernelSize = float(2 * pcfRadius + 1);
+    return shadow / (kernelSize * kernelSize);
+}
+
+float pointShadow(vec3 fragPos, vec3 normal, vec3 lightDir)
+{
+    vec3 fromLight = fragPos - pointShadowPos;
+    float current = length(fromLight) / pointShadowFar;
+    if (current > 1.0)
+        return 0.0;
+
+    float bias = shadowBiasFor(normal, lightDir);
+    // Samples a texel apart, spread further for fragments far from the camera
+    vec3 direction = normalize(fromLight);
+    float texel = 2.0 / float(textureSize(pointShadowMap, 0).x);
+    float step = texel * (1.0 + length(viewPos - fragPos) / pointShadowFar);
+    float shadow = 0.0;
+    for (int x = -pcfRadius; x <= pcfRadius; x++)
+    {
+        for (int y = -pcfRadius; y <= pcfRadius; y++)
+        {
+            for (int z = -pcfRadius; z <= pcfRadius; z++)
+            {
+                vec3 offset = vec3(x, y, z) * step;
+                float closest = texture(pointShadowMap, direction + offset).r;
+                shadow += current - bias > closest ? 1.0 : 0.0;
+            }
+        }
+    }
+    float kernelSize = float(2 * pcfRadius + 1);
+    return shadow / (kernelSize * kernelSize * kernelSize);
+}
+
+// Fraction of the light of `lights[index]` reaching `fragPos`
+float lightVisibility(int index, vec3 fragPos, vec3 normal, vec3 lightDir)
+{
+    if (index == shadowLight && cascadeCount > 0)
+        return 1.0 - directionalShadow(fragPos, normal, lightDir);
+    if (index == pointShadowLight)
+        return 1.0 - pointShadow(fragPos, normal, lightDir);
+    return 1.0;
+}
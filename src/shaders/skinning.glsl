@@ -0,0 +1,52 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec2 aTexCoords;
+layout (location = 3) in uvec4 aBoneIds;
+layout (location = 4) in vec4 aBoneWeights;
+
+out vec2 TexCoords;
+out vec3 Normal;
+out vec3 FragPos;
+
+// Same as `animation::MAX_BONES`
+const int MAX_BONES = 48;
+const int MAX_MORPH_TARGETS = 32;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+uniform mat4 bones[MAX_BONES];
+
+// Position then normal deltas of each target, morphVertexCount texels each
+uniform samplerBuffer morphDeltas;
+uniform int morphTargetCount;
+uniform int morphVertexCount;
+uniform float morphWeights[MAX_MORPH_TARGETS];
+
+void main()
+{
+    vec3 position = aPos;
+    vec3 normal = aNormal;
+    for (int i = 0; i < morphTargetCount; i++)
+    {
+        if (morphWeights[i] == 0.0)
+            continue;
+        int offset = 2 * i * morphVertexCount + gl_VertexID;
+        position += texelFetch(morphDeltas, offset).xyz * morphWeights[i];
+        normal += texelFetch(morphDeltas, offset + morphVertexCount).xyz * morphWeights[i];
+    }
+
+    mat4 skin = mat4(0.0);
+    for (int i = 0; i < 4; i++)
+        skin += bones[min(aBoneIds[i], uint(MAX_BONES - 1))] * aBoneWeights[i];
+    // Vertices without bones aren't deformed
+    if (dot(aBoneWeights, vec4(1.0)) == 0.0)
+        skin = mat4(1.0);
+
+    vec4 worldPos = model * skin * vec4(position, 1.0);
+    FragPos = worldPos.xyz;
+    Normal = mat3(transpose(inverse(model * skin))) * normalize(normal);
+    TexCoords = aTexCoords;
+    gl_Position = projection * view * worldPos;
+}
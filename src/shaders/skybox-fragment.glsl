@@ -0,0 +1,13 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec3 LocalPos;
+
+uniform samplerCube environmentMap;
+
+#include "output.glsl"
+
+void main()
+{
+    FragColor = outputColor(texture(environmentMap, LocalPos).rgb, 1.0);
+}
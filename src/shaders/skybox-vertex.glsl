@@ -0,0 +1,17 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+
+// Direction from the center of the cube
+out vec3 LocalPos;
+
+uniform mat4 projection;
+// Rotation of the camera only, the sky stays around it
+uniform mat4 view;
+
+void main()
+{
+    LocalPos = aPos;
+    vec4 position = projection * view * vec4(aPos, 1.0);
+    // Always at the far plane, behind everything drawn before
+    gl_Position = position.xyww;
+}
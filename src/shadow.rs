@@ -0,0 +1,511 @@
+use crate::gl_objects::{Texture2DArray, TextureCube};
+use crate::light::{Light, LightList};
+use crate::mesh::ScreenQuad;
+use crate::shader::{Shader, ShaderBuilder, ShaderError};
+use gl::types::*;
+use std::fmt;
+
+/// Size of the cascade arrays in `shadows.glsl`
+pub const MAX_CASCADES: usize = 4;
+/// Texture units of the shadow maps, after the units of image-based lighting
+pub const CASCADES_UNIT: u32 = 10;
+pub const POINT_SHADOW_UNIT: u32 = 11;
+
+/// Quality of the shadows. Sizes are fixed when a map is created, the bias and filtering can be
+/// changed at any time.
+#[derive(Clone, Debug)]
+pub struct ShadowSettings {
+    /// Size of each cascade of directional shadows, in texels per side
+    pub cascade_resolution: u32,
+    /// Clamped to `MAX_CASCADES`
+    pub cascade_count: usize,
+    /// Blend between evenly spaced cascades (0) and logarithmically spaced ones (1), which
+    /// give more texels to what's close to the camera
+    pub split_lambda: f32,
+    /// Directional shadows end this far from the camera
+    pub max_distance: f32,
+    pub cube_resolution: u32,
+    /// Point shadows end this far from the light
+    pub point_far: f32,
+    /// Depth bias of surfaces facing the light, against shadow acne
+    pub bias: f32,
+    /// Bias of surfaces at grazing angles to the light
+    pub slope_bias: f32,
+    /// Half size of the PCF kernel in texels, 0 takes a single sample
+    pub pcf_radius: i32,
+}
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            cascade_resolution: 2048,
+            cascade_count: 4,
+            split_lambda: 0.75,
+            max_distance: 50.0,
+            cube_resolution: 1024,
+            point_far: 25.0,
+            bias: 0.0005,
+            slope_bias: 0.005,
+            pcf_radius: 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ShadowError {
+    Shader(ShaderError),
+    /// Status returned by `glCheckFramebufferStatus`
+    IncompleteFramebuffer(GLenum),
+}
+impl fmt::Display for ShadowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShadowError::Shader(error) => write!(f, "{}", error),
+            ShadowError::IncompleteFramebuffer(status) => {
+                write!(f, "incomplete shadow map framebuffer: 0x{:x}", status)
+            }
+        }
+    }
+}
+impl std::error::Error for ShadowError {}
+impl From<ShaderError> for ShadowError {
+    fn from(error: ShaderError) -> Self {
+        ShadowError::Shader(error)
+    }
+}
+
+/// Framebuffer without color attachment, its depth attachment changes for each pass
+struct DepthFramebuffer(GLuint);
+impl DepthFramebuffer {
+    /// `attach` attaches the depth texture to the bound framebuffer, the result is checked
+    fn new(attach: impl FnOnce()) -> Result<Self, ShadowError> {
+        let mut id = 0;
+        let status = with_framebuffer_bound(|| unsafe {
+            gl::GenFramebuffers(1, &mut id);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, id);
+            attach();
+            gl::DrawBuffer(gl::NONE);
+            gl::ReadBuffer(gl::NONE);
+            gl::CheckFramebufferStatus(gl::FRAMEBUFFER)
+        });
+        let framebuffer = Self(id);
+        if status == gl::FRAMEBUFFER_COMPLETE {
+            Ok(framebuffer)
+        } else {
+            Err(ShadowError::IncompleteFramebuffer(status))
+        }
+    }
+}
+impl Drop for DepthFramebuffer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteFramebuffers(1, &self.0) }
+    }
+}
+
+/// Runs `f`, then binds the framebuffer and sets the viewport that were current before
+fn with_framebuffer_bound<R>(f: impl FnOnce() -> R) -> R {
+    let mut framebuffer = 0;
+    let mut viewport = [0; 4];
+    unsafe {
+        gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut framebuffer);
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+    }
+    let result = f();
+    let [x, y, width, height] = viewport;
+    unsafe {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer as GLuint);
+        gl::Viewport(x, y, width, height);
+    }
+    result
+}
+
+/// Shadows of a directional light, split in cascades along the view of the camera so close
+/// shadows get as many texels as distant ones
+pub struct CascadedShadowMap {
+    /// Index in the `LightList` of the directional light casting the shadows
+    pub light: usize,
+    texture: Texture2DArray,
+    framebuffer: DepthFramebuffer,
+    resolution: u32,
+    count: usize,
+    light_space: [glm::Mat4; MAX_CASCADES],
+    /// View space distance where each cascade ends
+    splits: [f32; MAX_CASCADES],
+}
+impl CascadedShadowMap {
+    pub fn new(light: usize, settings: &ShadowSettings) -> Result<Self, ShadowError> {
+        let count = settings.cascade_count.clamp(1, MAX_CASCADES);
+        let resolution = settings.cascade_resolution as GLsizei;
+        let texture = Texture2DArray::new();
+        texture.bind();
+        unsafe {
+            gl::TexImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                gl::DEPTH_COMPONENT32F as GLint,
+                resolution,
+                resolution,
+                count as GLsizei,
+                0,
+                gl::DEPTH_COMPONENT,
+                gl::FLOAT,
+                std::ptr::null(),
+            );
+            set_depth_parameters(gl::TEXTURE_2D_ARRAY, gl::CLAMP_TO_BORDER);
+            // Everything outside of the cascades is lit
+            let border = [1.0f32; 4];
+            gl::TexParameterfv(
+                gl::TEXTURE_2D_ARRAY,
+                gl::TEXTURE_BORDER_COLOR,
+                border.as_ptr(),
+            );
+        }
+        let framebuffer = DepthFramebuffer::new(|| unsafe {
+            gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, texture.id(), 0, 0);
+        })?;
+        Ok(Self {
+            light,
+            texture,
+            framebuffer,
+            resolution: settings.cascade_resolution,
+            count,
+            light_space: [glm::Mat4::identity(); MAX_CASCADES],
+            splits: [0.0; MAX_CASCADES],
+        })
+    }
+    /// Fits the cascades to the view of the camera, described like its `glm::perspective`
+    /// projection. Does nothing if the light isn't directional.
+    pub fn update(
+        &mut self,
+        lights: &LightList,
+        view: &glm::Mat4,
+        aspect: f32,
+        fov_y: f32,
+        near: f32,
+        settings: &ShadowSettings,
+    ) {
+        let Some(Light::Directional { direction, .. }) = lights.lights().get(self.light) else {
+            return;
+        };
+        let direction = direction.normalize();
+        let far = settings.max_distance.max(near);
+        let lambda = settings.split_lambda.clamp(0.0, 1.0);
+        let count = self.count;
+        for (i, split) in self.splits[..count].iter_mut().enumerate() {
+            let p = (i + 1) as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+            *split = lambda * logarithmic + (1.0 - lambda) * uniform;
+        }
+
+        let inverse_view = view.try_inverse().unwrap_or_else(glm::Mat4::identity);
+        let tan_y = (fov_y / 2.0).tan();
+        let tan_x = tan_y * aspect;
+        let up = if direction.y.abs() > 0.99 {
+            glm::vec3(0.0, 0.0, 1.0)
+        } else {
+            glm::vec3(0.0, 1.0, 0.0)
+        };
+        let mut slice_near = near;
+        for (i, &slice_far) in self.splits[..count].iter().enumerate() {
+            // Corners of the slice of the view frustum, in world space
+            let mut corners = [glm::Vec3::zeros(); 8];
+            for (j, corner) in corners.iter_mut().enumerate() {
+                let depth = if j < 4 { slice_near } else { slice_far };
+                let x = if j % 4 == 0 || j % 4 == 3 { -1.0 } else { 1.0 };
+                let y = if j % 4 < 2 { -1.0 } else { 1.0 };
+                let view_corner = glm::vec4(x * tan_x * depth, y * tan_y * depth, -depth, 1.0);
+                *corner = (inverse_view * view_corner).xyz();
+            }
+            let center = corners.iter().fold(glm::Vec3::zeros(), |sum, c| sum + c) / 8.0;
+            // A bounding sphere keeps the cascade the same size as the camera turns
+            let radius = corners
+                .iter()
+                .map(|corner| glm::distance(corner, &center))
+                .fold(0.0, f32::max);
+            let radius = (radius * 16.0).ceil() / 16.0;
+
+            // Casters up to `max_distance` towards the light shade the slice
+            let margin = settings.max_distance;
+            let eye = center - direction * (radius + margin);
+            let light_view = glm::look_at(&eye, &center, &up);
+            let mut projection =
+                glm::ortho(-radius, radius, -radius, radius, 0.0, 2.0 * radius + margin);
+            // Snapped to whole texels so the edges of the shadows don't shimmer when the
+            // camera moves
+            let half_size = self.resolution as f32 / 2.0;
+            let origin = (projection * light_view * glm::vec4(0.0, 0.0, 0.0, 1.0)).xy() * half_size;
+            let offset = (origin.map(f32::round) - origin) / half_size;
+            projection[(0, 3)] += offset.x;
+            projection[(1, 3)] += offset.y;
+
+            self.light_space[i] = projection * light_view;
+            slice_near = slice_far;
+        }
+    }
+    pub fn cascade_count(&self) -> usize {
+        self.count
+    }
+    /// World to shadow map clip space of each cascade
+    pub fn light_space(&self) -> &[glm::Mat4] {
+        &self.light_space[..self.count]
+    }
+    /// View space distance where each cascade ends
+    pub fn splits(&self) -> &[f32] {
+        &self.splits[..self.count]
+    }
+}
+
+/// Shadows of a point or spot light in every direction, stored in a cube map as the distance
+/// to the light divided by `ShadowSettings::point_far`
+pub struct PointShadowMap {
+    /// Index in the `LightList` of the light casting the shadows
+    pub light: usize,
+    texture: TextureCube,
+    framebuffer: DepthFramebuffer,
+    resolution: u32,
+    position: glm::Vec3,
+    far: f32,
+}
+impl PointShadowMap {
+    pub fn new(light: usize, settings: &ShadowSettings) -> Result<Self, ShadowError> {
+        let resolution = settings.cube_resolution as GLsizei;
+        let texture = TextureCube::new();
+        texture.bind();
+        unsafe {
+            for face in 0..6 {
+                gl::TexImage2D(
+                    gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                    0,
+                    gl::DEPTH_COMPONENT32F as GLint,
+                    resolution,
+                    resolution,
+                    0,
+                    gl::DEPTH_COMPONENT,
+                    gl::FLOAT,
+                    std::ptr::null(),
+                );
+            }
+            set_depth_parameters(gl::TEXTURE_CUBE_MAP, gl::CLAMP_TO_EDGE);
+            gl::TexParameteri(
+                gl::TEXTURE_CUBE_MAP,
+                gl::TEXTURE_WRAP_R,
+                gl::CLAMP_TO_EDGE as GLint,
+            );
+        }
+        let framebuffer = DepthFramebuffer::new(|| unsafe {
+            gl::FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X,
+                texture.id(),
+                0,
+            );
+        })?;
+        Ok(Self {
+            light,
+            texture,
+            framebuffer,
+            resolution: settings.cube_resolution,
+            position: glm::Vec3::zeros(),
+            far: settings.point_far,
+        })
+    }
+    /// Follows the light. Does nothing if it's directional.
+    pub fn update(&mut self, lights: &LightList, settings: &ShadowSettings) {
+        match lights.lights().get(self.light) {
+            Some(Light::Point { position, .. }) | Some(Light::Spot { position, .. }) => {
+                self.position = *position;
+                self.far = settings.point_far;
+            }
+            _ => {}
+        }
+    }
+    /// World to clip space of each face of the cube map
+    fn face_transforms(&self) -> [glm::Mat4; 6] {
+        let projection = glm::perspective(1.0, 90f32.to_radians(), 0.05, self.far);
+        let faces = [
+            (glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.0, -1.0, 0.0)),
+            (glm::vec3(-1.0, 0.0, 0.0), glm::vec3(0.0, -1.0, 0.0)),
+            (glm::vec3(0.0, 1.0, 0.0), glm::vec3(0.0, 0.0, 1.0)),
+            (glm::vec3(0.0, -1.0, 0.0), glm::vec3(0.0, 0.0, -1.0)),
+            (glm::vec3(0.0, 0.0, 1.0), glm::vec3(0.0, -1.0, 0.0)),
+            (glm::vec3(0.0, 0.0, -1.0), glm::vec3(0.0, -1.0, 0.0)),
+        ];
+        faces.map(|(direction, up)| {
+            projection * glm::look_at(&self.position, &(self.position + direction), &up)
+        })
+    }
+}
+
+/// Nearest filtering, PCF is done in the shaders
+unsafe fn set_depth_parameters(target: GLenum, wrap: GLenum) {
+    gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+    gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+    gl::TexParameteri(target, gl::TEXTURE_WRAP_S, wrap as GLint);
+    gl::TexParameteri(target, gl::TEXTURE_WRAP_T, wrap as GLint);
+}
+
+/// Renders the shadow maps and hands them to the lit shaders, which include `shadows.glsl`
+/// when built with `SHADOWS` defined
+pub struct ShadowRenderer {
+    pub settings: ShadowSettings,
+    depth: Shader,
+    point_depth: Shader,
+    debug: Shader,
+    quad: ScreenQuad,
+}
+impl ShadowRenderer {
+    pub fn new(settings: ShadowSettings) -> Result<Self, ShadowError> {
+        let depth = |point: bool| {
+            let builder = ShaderBuilder::new()
+                .vertex("src/shaders/shadow-vertex.glsl")
+                .fragment("src/shaders/shadow-fragment.glsl");
+            let builder = if point {
+                builder.define("POINT_SHADOW", "1")
+            } else {
+                builder
+            };
+            builder.build()
+        };
+        Ok(Self {
+            settings,
+            depth: depth(false)?,
+            point_depth: depth(true)?,
+            debug: ShaderBuilder::new()
+                .vertex("src/shaders/quad-vertex.glsl")
+                .fragment("src/shaders/shadow-debug.glsl")
+                .build()?,
+            quad: ScreenQuad::new(),
+        })
+    }
+    /// Renders every cascade. `draw` is given the depth shader, in use, and draws the scene
+    /// setting the `model` uniform of each object.
+    pub fn render_cascades(&self, map: &CascadedShadowMap, mut draw: impl FnMut(&Shader)) {
+        with_framebuffer_bound(|| {
+            self.depth.use_shader();
+            unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, map.framebuffer.0);
+                gl::Viewport(0, 0, map.resolution as GLsizei, map.resolution as GLsizei);
+            }
+            for (i, light_space) in map.light_space().iter().enumerate() {
+                unsafe {
+                    gl::FramebufferTextureLayer(
+                        gl::FRAMEBUFFER,
+                        gl::DEPTH_ATTACHMENT,
+                        map.texture.id(),
+                        0,
+                        i as GLint,
+                    );
+                    gl::Clear(gl::DEPTH_BUFFER_BIT);
+                }
+                let _ = self.depth.set_uniform("lightSpace", *light_space);
+                draw(&self.depth);
+            }
+        });
+    }
+    /// Renders the six faces of the cube map, `draw` is called like for `render_cascades`
+    pub fn render_point(&self, map: &PointShadowMap, mut draw: impl FnMut(&Shader)) {
+        with_framebuffer_bound(|| {
+            let shader = &self.point_depth;
+            shader.use_shader();
+            let _ = shader.set_uniform("lightPos", map.position);
+            let _ = shader.set_uniform("farPlane", map.far);
+            unsafe {
+                gl::BindFramebuffer(gl::FRAMEBUFFER, map.framebuffer.0);
+                gl::Viewport(0, 0, map.resolution as GLsizei, map.resolution as GLsizei);
+            }
+            for (i, transform) in map.face_transforms().iter().enumerate() {
+                unsafe {
+                    gl::FramebufferTexture2D(
+                        gl::FRAMEBUFFER,
+                        gl::DEPTH_ATTACHMENT,
+                        gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as GLenum,
+                        map.texture.id(),
+                        0,
+                    );
+                    gl::Clear(gl::DEPTH_BUFFER_BIT);
+                }
+                let _ = shader.set_uniform("lightSpace", *transform);
+                draw(shader);
+            }
+        });
+    }
+    /// Binds the maps and sets the uniforms of `shadows.glsl` on `shader`, which must be in
+    /// use. A missing map disables its shadows.
+    pub fn bind(
+        &self,
+        shader: &Shader,
+        cascades: Option<&CascadedShadowMap>,
+        point: Option<&PointShadowMap>,
+    ) {
+        let settings = &self.settings;
+        let _ = shader.set_uniform("shadowBias", settings.bias);
+        let _ = shader.set_uniform("shadowSlopeBias", settings.slope_bias);
+        let _ = shader.set_uniform("pcfRadius", settings.pcf_radius.max(0));
+        // Samplers of different types can't share a unit, even when they aren't sampled
+        let _ = shader.set_uniform("shadowCascades", CASCADES_UNIT as i32);
+        let _ = shader.set_uniform("pointShadowMap", POINT_SHADOW_UNIT as i32);
+        match cascades {
+            Some(map) => {
+                map.texture.bind_to_unit(CASCADES_UNIT);
+                let _ = shader.set_uniform("shadowLight", map.light as i32);
+                let _ = shader.set_uniform("cascadeCount", map.count as i32);
+                let _ = shader.set_uniform_array("cascadeLightSpace", map.light_space());
+                let _ = shader.set_uniform_array("cascadeSplits", map.splits());
+            }
+            None => {
+                let _ = shader.set_uniform("shadowLight", -1);
+            }
+        }
+        match point {
+            Some(map) => {
+                map.texture.bind_to_unit(POINT_SHADOW_UNIT);
+                let _ = shader.set_uniform("pointShadowLight", map.light as i32);
+                let _ = shader.set_uniform("pointShadowPos", map.position);
+                let _ = shader.set_uniform("pointShadowFar", map.far);
+            }
+            None => {
+                let _ = shader.set_uniform("pointShadowLight", -1);
+            }
+        }
+        unsafe { gl::ActiveTexture(gl::TEXTURE0) };
+    }
+    /// Shows the shadow maps in a row of tiles along the bottom of the viewport, `width` by
+    /// `height` pixels: each cascade, then each face of the cube map
+    pub fn draw_debug(
+        &self,
+        cascades: Option<&CascadedShadowMap>,
+        point: Option<&PointShadowMap>,
+        width: i32,
+        height: i32,
+    ) {
+        let tile = (height / 5).min(width / 10);
+        let shader = &self.debug;
+        shader.use_shader();
+        let _ = shader.set_uniform("cascades", 0);
+        let _ = shader.set_uniform("cube", 1);
+        let mut tiles = vec![];
+        if let Some(map) = cascades {
+            map.texture.bind_to_unit(0);
+            tiles.extend((0..map.count as i32).map(|layer| (0, layer)));
+        }
+        if let Some(map) = point {
+            map.texture.bind_to_unit(1);
+            tiles.extend((0..6).map(|face| (1, face)));
+        }
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::Disable(gl::DEPTH_TEST);
+        }
+        for (i, (mode, layer)) in tiles.into_iter().enumerate() {
+            unsafe { gl::Viewport(i as i32 * tile, 0, tile, tile) };
+            let _ = shader.set_uniform("mode", mode);
+            let _ = shader.set_uniform("layer", layer);
+            self.quad.draw();
+        }
+        unsafe {
+            gl::Enable(gl::DEPTH_TEST);
+            gl::Viewport(0, 0, width, height);
+        }
+    }
+}
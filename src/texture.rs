@@ -0,0 +1,222 @@
+use crate::gl_objects::Texture2D;
+use gl::types::*;
+use image::DynamicImage;
+use std::ffi::c_void;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+// From EXT_texture_filter_anisotropic, core since 4.6 and missing from the bindings
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
+/// How a texture is uploaded and sampled. The same image loaded with different descriptors is
+/// a different texture.
+#[derive(Clone, Copy, Debug)]
+pub struct TextureDescriptor {
+    pub wrap_s: GLenum,
+    pub wrap_t: GLenum,
+    /// Mipmapped filters fall back to their base filter when `mipmaps` is off
+    pub min_filter: GLenum,
+    pub mag_filter: GLenum,
+    /// Maximum anisotropy, 1 disables it. Clamped to what the driver supports.
+    pub anisotropy: f32,
+    pub mipmaps: bool,
+    /// Colors are decoded from sRGB when sampled, for albedo and emissive maps. Only 8-bit RGB
+    /// and RGBA images have an sRGB format, the others are always linear.
+    pub srgb: bool,
+    /// Uploads the bottom row first, for texture coordinates with their origin at the bottom
+    pub flip_vertically: bool,
+}
+impl Default for TextureDescriptor {
+    fn default() -> Self {
+        Self {
+            wrap_s: gl::REPEAT,
+            wrap_t: gl::REPEAT,
+            min_filter: gl::LINEAR_MIPMAP_LINEAR,
+            mag_filter: gl::LINEAR,
+            anisotropy: 1.0,
+            mipmaps: true,
+            srgb: false,
+            flip_vertically: false,
+        }
+    }
+}
+impl TextureDescriptor {
+    /// Same wrap mode on both axes
+    pub fn wrap(self, wrap: GLenum) -> Self {
+        Self {
+            wrap_s: wrap,
+            wrap_t: wrap,
+            ..self
+        }
+    }
+    /// Minifying filter actually used, without mipmaps when there are none
+    pub fn effective_min_filter(&self) -> GLenum {
+        if self.mipmaps {
+            return self.min_filter;
+        }
+        match self.min_filter {
+            gl::NEAREST_MIPMAP_NEAREST | gl::NEAREST_MIPMAP_LINEAR => gl::NEAREST,
+            gl::LINEAR_MIPMAP_NEAREST | gl::LINEAR_MIPMAP_LINEAR => gl::LINEAR,
+            filter => filter,
+        }
+    }
+    /// Sets the sampling parameters of the bound 2D texture
+    fn apply(&self) {
+        unsafe {
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, self.wrap_s as GLint);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, self.wrap_t as GLint);
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MIN_FILTER,
+                self.effective_min_filter() as GLint,
+            );
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MAG_FILTER,
+                self.mag_filter as GLint,
+            );
+            if self.anisotropy > 1.0 {
+                // Stays 0 without anisotropic filtering support
+                let mut max = 0.0;
+                gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+                if max >= 1.0 {
+                    gl::TexParameterf(
+                        gl::TEXTURE_2D,
+                        TEXTURE_MAX_ANISOTROPY,
+                        self.anisotropy.min(max),
+                    );
+                }
+            }
+        }
+    }
+}
+// Compared bit for bit so descriptors can key the texture cache
+impl PartialEq for TextureDescriptor {
+    fn eq(&self, other: &Self) -> bool {
+        self.wrap_s == other.wrap_s
+            && self.wrap_t == other.wrap_t
+            && self.min_filter == other.min_filter
+            && self.mag_filter == other.mag_filter
+            && self.anisotropy.to_bits() == other.anisotropy.to_bits()
+            && self.mipmaps == other.mipmaps
+            && self.srgb == other.srgb
+            && self.flip_vertically == other.flip_vertically
+    }
+}
+impl Eq for TextureDescriptor {}
+impl Hash for TextureDescriptor {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.wrap_s.hash(state);
+        self.wrap_t.hash(state);
+        self.min_filter.hash(state);
+        self.mag_filter.hash(state);
+        self.anisotropy.to_bits().hash(state);
+        self.mipmaps.hash(state);
+        self.srgb.hash(state);
+        self.flip_vertically.hash(state);
+    }
+}
+
+/// An image file that couldn't be opened or decoded
+#[derive(Debug)]
+pub struct TextureError {
+    pub path: PathBuf,
+    pub error: image::ImageError,
+}
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "couldn't load texture {}: {}",
+            self.path.display(),
+            self.error
+        )
+    }
+}
+impl std::error::Error for TextureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Loads any image format the `image` crate decodes, including 16-bit PNGs and float HDR and
+/// EXR images
+pub fn load_texture_with(
+    path: &Path,
+    descriptor: &TextureDescriptor,
+) -> Result<Texture2D, TextureError> {
+    let image = image::open(path).map_err(|error| TextureError {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    Ok(texture_from_image(image, descriptor))
+}
+
+/// Uploads a decoded image with the internal format matching its channels and depth
+pub fn texture_from_image(image: DynamicImage, descriptor: &TextureDescriptor) -> Texture2D {
+    let image = if descriptor.flip_vertically {
+        image.flipv()
+    } else {
+        image
+    };
+    let srgb = descriptor.srgb;
+    let (format, data_type, internal_format) = match image {
+        DynamicImage::ImageLuma8(_) => (gl::RED, gl::UNSIGNED_BYTE, gl::R8),
+        DynamicImage::ImageLumaA8(_) => (gl::RG, gl::UNSIGNED_BYTE, gl::RG8),
+        DynamicImage::ImageRgb8(_) if srgb => (gl::RGB, gl::UNSIGNED_BYTE, gl::SRGB8),
+        DynamicImage::ImageRgb8(_) => (gl::RGB, gl::UNSIGNED_BYTE, gl::RGB8),
+        DynamicImage::ImageRgba8(_) if srgb => (gl::RGBA, gl::UNSIGNED_BYTE, gl::SRGB8_ALPHA8),
+        DynamicImage::ImageRgba8(_) => (gl::RGBA, gl::UNSIGNED_BYTE, gl::RGBA8),
+        DynamicImage::ImageLuma16(_) => (gl::RED, gl::UNSIGNED_SHORT, gl::R16),
+        DynamicImage::ImageLumaA16(_) => (gl::RG, gl::UNSIGNED_SHORT, gl::RG16),
+        DynamicImage::ImageRgb16(_) => (gl::RGB, gl::UNSIGNED_SHORT, gl::RGB16),
+        DynamicImage::ImageRgba16(_) => (gl::RGBA, gl::UNSIGNED_SHORT, gl::RGBA16),
+        DynamicImage::ImageRgb32F(_) => (gl::RGB, gl::FLOAT, gl::RGB32F),
+        DynamicImage::ImageRgba32F(_) => (gl::RGBA, gl::FLOAT, gl::RGBA32F),
+        // Formats added to `image` later
+        image => {
+            let image = DynamicImage::ImageRgba8(image.to_rgba8());
+            return texture_from_image(image, descriptor);
+        }
+    };
+
+    let texture = Texture2D::new();
+    texture.bind();
+    unsafe {
+        // Rows are tightly packed, the default alignment of 4 breaks RGB and grayscale images
+        // of most widths
+        let mut alignment = 0;
+        gl::GetIntegerv(gl::UNPACK_ALIGNMENT, &mut alignment);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            internal_format as GLint,
+            image.width() as GLsizei,
+            image.height() as GLsizei,
+            0,
+            format,
+            data_type,
+            image.as_bytes().as_ptr() as *const c_void,
+        );
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, alignment);
+
+        // Grayscale is sampled as gray instead of red
+        let swizzle = match format {
+            gl::RED => Some([gl::RED, gl::RED, gl::RED, gl::ONE]),
+            gl::RG => Some([gl::RED, gl::RED, gl::RED, gl::GREEN]),
+            _ => None,
+        };
+        if let Some(swizzle) = swizzle {
+            let swizzle = swizzle.map(|channel| channel as GLint);
+            gl::TexParameteriv(gl::TEXTURE_2D, gl::TEXTURE_SWIZZLE_RGBA, swizzle.as_ptr());
+        }
+        if descriptor.mipmaps {
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+    }
+    descriptor.apply();
+    texture
+}
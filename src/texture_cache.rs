@@ -0,0 +1,194 @@
+use crate::gl_objects::Texture2D;
+use crate::texture::{load_texture_with, TextureDescriptor, TextureError};
+use gl::types::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::{Rc, Weak};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextureCacheStats {
+    /// Textures alive
+    pub count: usize,
+    /// GPU memory of the textures alive, estimated from their size and format
+    pub bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+struct Entry {
+    texture: Weak<Texture2D>,
+    bytes: usize,
+}
+
+/// Textures loaded from files, shared by every model. The cache only keeps weak references: a
+/// texture is deleted once its last handle is dropped, and loaded again when requested after.
+#[derive(Default)]
+pub struct TextureCache {
+    entries: HashMap<(PathBuf, TextureDescriptor), Entry>,
+    hits: u64,
+    misses: u64,
+}
+
+thread_local! {
+    // GL objects belong to the thread of their context
+    static TEXTURE_CACHE: RefCell<TextureCache> = RefCell::new(TextureCache::new());
+}
+
+impl TextureCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Runs `f` with the cache of the current thread. `f` must not use the global cache again.
+    pub fn with_global<R>(f: impl FnOnce(&mut TextureCache) -> R) -> R {
+        TEXTURE_CACHE.with(|cache| f(&mut cache.borrow_mut()))
+    }
+    /// Loads the image at `path`, or returns the texture already loaded from it with the same
+    /// descriptor
+    pub fn load(
+        &mut self,
+        path: &Path,
+        descriptor: TextureDescriptor,
+    ) -> Result<Rc<Texture2D>, TextureError> {
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let key = (path, descriptor);
+        if let Some(texture) = self.get(&key) {
+            return Ok(texture);
+        }
+        let texture = load_texture_with(&key.0, &descriptor)?;
+        Ok(self.insert(key, texture))
+    }
+    /// Returns the texture cached under `key` or caches the one made by `create`. For textures
+    /// that aren't loaded from a file of their own, `key` only has to be unique.
+    pub fn get_or_insert_with(
+        &mut self,
+        key: PathBuf,
+        descriptor: TextureDescriptor,
+        create: impl FnOnce() -> Result<Texture2D, TextureError>,
+    ) -> Result<Rc<Texture2D>, TextureError> {
+        let key = (key, descriptor);
+        match self.get(&key) {
+            Some(texture) => Ok(texture),
+            None => Ok(self.insert(key, create()?)),
+        }
+    }
+    pub fn stats(&self) -> TextureCacheStats {
+        let alive = self
+            .entries
+            .values()
+            .filter(|entry| entry.texture.strong_count() > 0);
+        let (count, bytes) = alive.fold((0, 0), |(count, bytes), entry| {
+            (count + 1, bytes + entry.bytes)
+        });
+        TextureCacheStats {
+            count,
+            bytes,
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+    /// Counts a hit or a miss
+    fn get(&mut self, key: &(PathBuf, TextureDescriptor)) -> Option<Rc<Texture2D>> {
+        let texture = self
+            .entries
+            .get(key)
+            .and_then(|entry| entry.texture.upgrade());
+        match texture {
+            Some(_) => self.hits += 1,
+            None => self.misses += 1,
+        }
+        texture
+    }
+    fn insert(&mut self, key: (PathBuf, TextureDescriptor), texture: Texture2D) -> Rc<Texture2D> {
+        self.remove_dropped();
+        let texture = Rc::new(texture);
+        let bytes = estimated_bytes(&texture, key.1.mipmaps);
+        self.entries.insert(
+            key,
+            Entry {
+                texture: Rc::downgrade(&texture),
+                bytes,
+            },
+        );
+        texture
+    }
+    /// Forgets the entries of textures whose handles were all dropped
+    pub fn remove_dropped(&mut self) {
+        self.entries
+            .retain(|_, entry| entry.texture.strong_count() > 0);
+    }
+}
+
+/// Estimated GPU memory of a texture, see `texture_bytes`
+fn estimated_bytes(texture: &Texture2D, mipmaps: bool) -> usize {
+    let (mut width, mut height, mut format) = (0, 0, 0);
+    texture.bind();
+    unsafe {
+        gl::GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_WIDTH, &mut width);
+        gl::GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_HEIGHT, &mut height);
+        gl::GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_INTERNAL_FORMAT, &mut format);
+    }
+    texture_bytes(
+        format as GLenum,
+        width.max(0) as u32,
+        height.max(0) as u32,
+        mipmaps,
+    )
+}
+
+/// Size of the base level times the size of its texels, plus a third for the mipmaps
+fn texture_bytes(format: GLenum, width: u32, height: u32, mipmaps: bool) -> usize {
+    let texel = match format {
+        gl::RED | gl::R8 => 1,
+        gl::RG | gl::RG8 | gl::R16 | gl::R16F => 2,
+        gl::RGB | gl::RGB8 | gl::SRGB8 => 3,
+        gl::RGBA | gl::RGBA8 | gl::SRGB8_ALPHA8 | gl::RG16 | gl::RG16F | gl::R32F => 4,
+        gl::RGB16 | gl::RGB16F => 6,
+        gl::RGBA16 | gl::RGBA16F | gl::RG32F => 8,
+        gl::RGB32F => 12,
+        gl::RGBA32F => 16,
+        _ => 4,
+    };
+    let bytes = width as usize * height as usize * texel;
+    if mipmaps {
+        bytes * 4 / 3
+    } else {
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texel_sizes_follow_the_internal_format() {
+        let cases = [
+            (gl::R8, 1),
+            (gl::R16, 2),
+            (gl::RG16F, 4),
+            (gl::SRGB8, 3),
+            (gl::SRGB8_ALPHA8, 4),
+            (gl::RGB16, 6),
+            (gl::RGBA16, 8),
+            (gl::RG32F, 8),
+            (gl::RGB32F, 12),
+            (gl::RGBA32F, 16),
+        ];
+        for (format, texel) in cases {
+            assert_eq!(
+                texture_bytes(format, 4, 2, false),
+                8 * texel,
+                "{:#x}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn mipmaps_add_a_third() {
+        assert_eq!(texture_bytes(gl::RGBA16, 64, 32, false), 16384);
+        assert_eq!(texture_bytes(gl::RGBA16, 64, 32, true), 21845);
+        assert_eq!(texture_bytes(gl::RGB8, 0, 32, true), 0);
+    }
+}
@@ -0,0 +1,235 @@
+extern crate gl;
+use gl::types::*;
+use std::cell::Cell;
+use std::fmt;
+
+/// An active uniform as reported by the driver after linking.
+#[derive(Clone, Debug)]
+pub struct UniformInfo {
+    pub location: GLint,
+    pub gl_type: GLenum,
+    /// Number of array elements, 1 for non-array uniforms
+    pub size: GLint,
+    /// Set once a value was uploaded through this name, its elements are then carried over
+    /// when the program is rebuilt
+    pub(crate) written: Cell<bool>,
+}
+
+#[derive(Debug)]
+pub enum UniformError {
+    /// The name is not an active uniform of the program (misspelled or optimised away)
+    Unknown(String),
+    TypeMismatch {
+        name: String,
+        expected: GLenum,
+        found: GLenum,
+    },
+    /// More values were given than the uniform array can hold
+    OutOfBounds {
+        name: String,
+        size: GLint,
+        len: usize,
+    },
+}
+impl fmt::Display for UniformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UniformError::Unknown(name) => write!(f, "unknown uniform `{}`", name),
+            UniformError::TypeMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "uniform `{}` is of type {} but a {} was given",
+                name,
+                gl_type_name(*expected),
+                gl_type_name(*found)
+            ),
+            UniformError::OutOfBounds { name, size, len } => write!(
+                f,
+                "uniform `{}` holds {} elements but {} were given",
+                name, size, len
+            ),
+        }
+    }
+}
+impl std::error::Error for UniformError {}
+
+/// A value that can be written to a uniform of the currently bound program
+pub trait Uniform: Sized {
+    const GL_TYPE: GLenum;
+
+    /// Whether a value of this type may be written to a uniform of `gl_type`
+    fn accepts(gl_type: GLenum) -> bool {
+        gl_type == Self::GL_TYPE
+    }
+    /// Uploads `values` starting at `location`. The program must be in use.
+    unsafe fn upload(location: GLint, values: &[Self]);
+}
+
+impl Uniform for bool {
+    const GL_TYPE: GLenum = gl::BOOL;
+    // Converted on the stack, a chunk at a time
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        let mut ints = [0i32; 16];
+        for (i, chunk) in values.chunks(ints.len()).enumerate() {
+            for (int, value) in ints.iter_mut().zip(chunk) {
+                *int = *value as i32;
+            }
+            let location = location + (i * ints.len()) as GLint;
+            gl::Uniform1iv(location, chunk.len() as GLsizei, ints.as_ptr());
+        }
+    }
+}
+impl Uniform for i32 {
+    const GL_TYPE: GLenum = gl::INT;
+    // Samplers and booleans are set through the integer entry points as well
+    fn accepts(gl_type: GLenum) -> bool {
+        gl_type == gl::INT || gl_type == gl::BOOL || is_sampler(gl_type)
+    }
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        gl::Uniform1iv(location, values.len() as GLsizei, values.as_ptr());
+    }
+}
+impl Uniform for u32 {
+    const GL_TYPE: GLenum = gl::UNSIGNED_INT;
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        gl::Uniform1uiv(location, values.len() as GLsizei, values.as_ptr());
+    }
+}
+impl Uniform for f32 {
+    const GL_TYPE: GLenum = gl::FLOAT;
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        gl::Uniform1fv(location, values.len() as GLsizei, values.as_ptr());
+    }
+}
+impl Uniform for glm::Vec2 {
+    const GL_TYPE: GLenum = gl::FLOAT_VEC2;
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        gl::Uniform2fv(location, values.len() as GLsizei, values.as_ptr() as *const f32);
+    }
+}
+impl Uniform for glm::Vec3 {
+    const GL_TYPE: GLenum = gl::FLOAT_VEC3;
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        gl::Uniform3fv(location, values.len() as GLsizei, values.as_ptr() as *const f32);
+    }
+}
+impl Uniform for glm::Vec4 {
+    const GL_TYPE: GLenum = gl::FLOAT_VEC4;
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        gl::Uniform4fv(location, values.len() as GLsizei, values.as_ptr() as *const f32);
+    }
+}
+impl Uniform for glm::IVec2 {
+    const GL_TYPE: GLenum = gl::INT_VEC2;
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        gl::Uniform2iv(location, values.len() as GLsizei, values.as_ptr() as *const i32);
+    }
+}
+impl Uniform for glm::IVec3 {
+    const GL_TYPE: GLenum = gl::INT_VEC3;
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        gl::Uniform3iv(location, values.len() as GLsizei, values.as_ptr() as *const i32);
+    }
+}
+impl Uniform for glm::IVec4 {
+    const GL_TYPE: GLenum = gl::INT_VEC4;
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        gl::Uniform4iv(location, values.len() as GLsizei, values.as_ptr() as *const i32);
+    }
+}
+// glm matrices are column major, same as OpenGL, so no transposition is needed
+impl Uniform for glm::Mat2 {
+    const GL_TYPE: GLenum = gl::FLOAT_MAT2;
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        gl::UniformMatrix2fv(
+            location,
+            values.len() as GLsizei,
+            gl::FALSE,
+            values.as_ptr() as *const f32,
+        );
+    }
+}
+impl Uniform for glm::Mat3 {
+    const GL_TYPE: GLenum = gl::FLOAT_MAT3;
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        gl::UniformMatrix3fv(
+            location,
+            values.len() as GLsizei,
+            gl::FALSE,
+            values.as_ptr() as *const f32,
+        );
+    }
+}
+impl Uniform for glm::Mat4 {
+    const GL_TYPE: GLenum = gl::FLOAT_MAT4;
+    unsafe fn upload(location: GLint, values: &[Self]) {
+        gl::UniformMatrix4fv(
+            location,
+            values.len() as GLsizei,
+            gl::FALSE,
+            values.as_ptr() as *const f32,
+        );
+    }
+}
+
+pub fn is_sampler(gl_type: GLenum) -> bool {
+    matches!(
+        gl_type,
+        gl::SAMPLER_1D
+            | gl::SAMPLER_2D
+            | gl::SAMPLER_3D
+            | gl::SAMPLER_CUBE
+            | gl::SAMPLER_1D_SHADOW
+            | gl::SAMPLER_2D_SHADOW
+            | gl::SAMPLER_1D_ARRAY
+            | gl::SAMPLER_2D_ARRAY
+            | gl::SAMPLER_1D_ARRAY_SHADOW
+            | gl::SAMPLER_2D_ARRAY_SHADOW
+            | gl::SAMPLER_2D_MULTISAMPLE
+            | gl::SAMPLER_2D_MULTISAMPLE_ARRAY
+            | gl::SAMPLER_CUBE_SHADOW
+            | gl::SAMPLER_BUFFER
+            | gl::SAMPLER_2D_RECT
+            | gl::SAMPLER_2D_RECT_SHADOW
+            | gl::INT_SAMPLER_1D
+            | gl::INT_SAMPLER_2D
+            | gl::INT_SAMPLER_3D
+            | gl::INT_SAMPLER_CUBE
+            | gl::INT_SAMPLER_1D_ARRAY
+            | gl::INT_SAMPLER_2D_ARRAY
+            | gl::INT_SAMPLER_BUFFER
+            | gl::UNSIGNED_INT_SAMPLER_1D
+            | gl::UNSIGNED_INT_SAMPLER_2D
+            | gl::UNSIGNED_INT_SAMPLER_3D
+            | gl::UNSIGNED_INT_SAMPLER_CUBE
+            | gl::UNSIGNED_INT_SAMPLER_1D_ARRAY
+            | gl::UNSIGNED_INT_SAMPLER_2D_ARRAY
+            | gl::UNSIGNED_INT_SAMPLER_BUFFER
+    )
+}
+
+/// GLSL spelling of a uniform type, for error messages
+pub fn gl_type_name(gl_type: GLenum) -> &'static str {
+    match gl_type {
+        gl::BOOL => "bool",
+        gl::INT => "int",
+        gl::UNSIGNED_INT => "uint",
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::INT_VEC2 => "ivec2",
+        gl::INT_VEC3 => "ivec3",
+        gl::INT_VEC4 => "ivec4",
+        gl::FLOAT_MAT2 => "mat2",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        t if is_sampler(t) => "sampler",
+        _ => "unknown",
+    }
+}
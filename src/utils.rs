@@ -1,7 +1,10 @@
-use crate::gl::types::*;
+use crate::gl_objects::Texture2D;
 use crate::mesh::Texture;
+use crate::texture::{load_texture_with, texture_from_image, TextureDescriptor, TextureError};
 use crate::{camera, gl, Camera};
+use image::error::{ImageError, ParameterError, ParameterErrorKind};
 use std::ffi::c_void;
+use std::path::{Component, Path, PathBuf};
 
 // Helps with all the nasty casts
 pub struct ToCVoid<T>(pub T);
@@ -32,6 +35,49 @@ pub fn to_c_str(str: &str) -> std::ffi::CString {
     std::ffi::CString::new(str.as_bytes()).unwrap()
 }
 
+/// Lexically removes `.` and `..` components so the same file is always spelled the same way.
+/// Unlike `canonicalize` it doesn't touch the filesystem or resolve symlinks.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Directory under the system temp dir for the files of a test, removed with its content on drop
+#[cfg(test)]
+pub struct TempDir(PathBuf);
+#[cfg(test)]
+impl TempDir {
+    /// `name` keeps tests running in parallel apart, the process id separate runs
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("opengl-rust-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+#[cfg(test)]
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
 pub fn process_input(window: &mut glfw::Window, camera: &mut Camera, delta_time: f32) {
     if window.get_key(glfw::Key::Escape) == glfw::Action::Press {
         window.set_should_close(true)
@@ -78,47 +124,52 @@ pub fn process_mouse(
     }
 }
 
-pub fn load_texture(path: &str) -> u32 {
-    unsafe {
-        let mut texture_id = 0;
-        gl::GenTextures(1, &mut texture_id);
-
-        let mut image = image::open(path).unwrap();
-        let data = image.as_bytes().as_ptr();
-        let format = match image {
-            image::DynamicImage::ImageLuma8(_) => gl::RED,
-            image::DynamicImage::ImageLumaA8(_) => gl::RG,
-            image::DynamicImage::ImageRgb8(_) => gl::RGB,
-            image::DynamicImage::ImageRgba8(_) => gl::RGBA,
-            _ => panic!("Unsupported format"),
-        };
-        let width = image.width();
-        let height = image.height();
-
-        gl::BindTexture(gl::TEXTURE_2D, texture_id);
-        gl::TexImage2D(
-            gl::TEXTURE_2D,
-            0,
-            format as GLint,
-            width as GLsizei,
-            height as GLsizei,
-            0,
-            format,
-            gl::UNSIGNED_BYTE,
-            data as *const c_void,
-        );
-        gl::GenerateMipmap(gl::TEXTURE_2D);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
-        gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MIN_FILTER,
-            gl::LINEAR_MIPMAP_LINEAR as GLint,
-        );
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+pub fn load_texture(path: &str) -> Result<Texture2D, TextureError> {
+    load_texture_with(Path::new(path), &TextureDescriptor::default())
+}
 
-        return texture_id;
+/// Texture from 8-bit RGBA pixels, top row first. `path` names the image in the error returned
+/// when the pixels don't match the size.
+pub fn texture_from_rgba(
+    path: &Path,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+    descriptor: &TextureDescriptor,
+) -> Result<Texture2D, TextureError> {
+    let image =
+        image::RgbaImage::from_raw(width, height, pixels.to_vec()).ok_or_else(|| TextureError {
+            path: path.to_path_buf(),
+            error: ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )),
+        })?;
+    Ok(texture_from_image(
+        image::DynamicImage::ImageRgba8(image),
+        descriptor,
+    ))
+}
+/// Small magenta and black checker board, stands in for textures that couldn't be loaded
+pub fn checker_texture() -> Result<Texture2D, TextureError> {
+    const SIZE: u32 = 8;
+    let mut pixels: Vec<u8> = Vec::with_capacity((SIZE * SIZE * 4) as usize);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let color: [u8; 4] = if (x + y) % 2 == 0 {
+                [255, 0, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            };
+            pixels.extend_from_slice(&color);
+        }
     }
+    let descriptor = TextureDescriptor {
+        min_filter: gl::NEAREST,
+        mag_filter: gl::NEAREST,
+        mipmaps: false,
+        ..TextureDescriptor::default()
+    };
+    texture_from_rgba(Path::new("#checker"), SIZE, SIZE, &pixels, &descriptor)
 }
 pub fn framebuffer_size_callback(width: i32, height: i32) {
     unsafe {
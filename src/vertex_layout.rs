@@ -0,0 +1,145 @@
+extern crate gl;
+use gl::types::*;
+use std::ffi::c_void;
+use std::mem::size_of;
+
+/// One vertex attribute, as passed to `glVertexAttribPointer`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexAttribute {
+    pub location: GLuint,
+    pub components: GLint,
+    pub gl_type: GLenum,
+    /// Fixed point values are mapped to [0, 1] (or [-1, 1] when signed)
+    pub normalized: bool,
+    /// Read as integers by the shader (`ivec4`/`uvec4`), e.g. bone ids
+    pub integer: bool,
+    /// Offset in bytes from the start of the vertex
+    pub offset: usize,
+}
+
+/// Describes how a `#[repr(C)]` vertex struct is laid out for the shaders.
+/// Implement it with `vertex_layout!` rather than by hand.
+pub trait VertexLayout: Sized {
+    fn attributes() -> Vec<VertexAttribute>;
+    fn stride() -> usize {
+        size_of::<Self>()
+    }
+}
+
+/// Sets up the attributes of `V` on the bound vertex array, sourcing from the bound
+/// `ARRAY_BUFFER`
+pub fn apply_layout<V: VertexLayout>() {
+    let stride = V::stride() as GLsizei;
+    for attribute in V::attributes() {
+        unsafe {
+            gl::EnableVertexAttribArray(attribute.location);
+            if attribute.integer {
+                gl::VertexAttribIPointer(
+                    attribute.location,
+                    attribute.components,
+                    attribute.gl_type,
+                    stride,
+                    attribute.offset as *const c_void,
+                );
+            } else {
+                gl::VertexAttribPointer(
+                    attribute.location,
+                    attribute.components,
+                    attribute.gl_type,
+                    attribute.normalized as GLboolean,
+                    stride,
+                    attribute.offset as *const c_void,
+                );
+            }
+        }
+    }
+}
+
+/// Component count and type of a field type usable as a vertex attribute
+pub trait AttributeFormat {
+    const COMPONENTS: GLint;
+    const GL_TYPE: GLenum;
+    const NORMALIZED: bool = false;
+    const INTEGER: bool = false;
+}
+impl AttributeFormat for f32 {
+    const COMPONENTS: GLint = 1;
+    const GL_TYPE: GLenum = gl::FLOAT;
+}
+impl AttributeFormat for glm::Vec2 {
+    const COMPONENTS: GLint = 2;
+    const GL_TYPE: GLenum = gl::FLOAT;
+}
+impl AttributeFormat for glm::Vec3 {
+    const COMPONENTS: GLint = 3;
+    const GL_TYPE: GLenum = gl::FLOAT;
+}
+impl AttributeFormat for glm::Vec4 {
+    const COMPONENTS: GLint = 4;
+    const GL_TYPE: GLenum = gl::FLOAT;
+}
+impl AttributeFormat for [f32; 4] {
+    const COMPONENTS: GLint = 4;
+    const GL_TYPE: GLenum = gl::FLOAT;
+}
+/// 8-bit colors, read as normalized floats
+impl AttributeFormat for [u8; 4] {
+    const COMPONENTS: GLint = 4;
+    const GL_TYPE: GLenum = gl::UNSIGNED_BYTE;
+    const NORMALIZED: bool = true;
+}
+impl AttributeFormat for [i32; 4] {
+    const COMPONENTS: GLint = 4;
+    const GL_TYPE: GLenum = gl::INT;
+    const INTEGER: bool = true;
+}
+impl AttributeFormat for [u32; 4] {
+    const COMPONENTS: GLint = 4;
+    const GL_TYPE: GLenum = gl::UNSIGNED_INT;
+    const INTEGER: bool = true;
+}
+
+/// Builds the attribute for a field at `offset`, the pointer only gives the type of the field
+pub fn attribute_of<T: AttributeFormat>(
+    location: GLuint,
+    offset: usize,
+    _field: *const T,
+) -> VertexAttribute {
+    VertexAttribute {
+        location,
+        components: T::COMPONENTS,
+        gl_type: T::GL_TYPE,
+        normalized: T::NORMALIZED,
+        integer: T::INTEGER,
+        offset,
+    }
+}
+
+/// Implements `VertexLayout` for a `#[repr(C)]` struct, mapping shader locations to fields.
+/// Component count and type are taken from the field types.
+/// ```ignore
+/// vertex_layout!(Vertex {
+///     0 => position,
+///     1 => normal,
+///     2 => tex_coords,
+/// });
+/// ```
+#[macro_export]
+macro_rules! vertex_layout {
+    ($ty:ty { $($location:literal => $field:ident),* $(,)? }) => {
+        impl $crate::vertex_layout::VertexLayout for $ty {
+            fn attributes() -> Vec<$crate::vertex_layout::VertexAttribute> {
+                let vertex = std::mem::MaybeUninit::<$ty>::uninit();
+                let ptr = vertex.as_ptr();
+                vec![$(
+                    $crate::vertex_layout::attribute_of(
+                        $location,
+                        $crate::offset_of!($ty, $field),
+                        // Only computes the address of the field, nothing is read
+                        unsafe { std::ptr::addr_of!((*ptr).$field) },
+                    )
+                ),*]
+            }
+        }
+    };
+}